@@ -0,0 +1,84 @@
+//! Derive macros for [sendgrid-rs](https://docs.rs/sendgrid-rs). Enable the `derive` feature of
+//! `sendgrid-rs` and use them through it rather than depending on this crate directly.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, LitStr};
+
+/// Implements `sendgrid_rs::TemplateData` for a struct with named fields, making it usable as the
+/// `dynamic_template_data` of a `Personalization`. The struct must also implement
+/// `serde::Serialize`, which decides the Handlebars keys it provides.
+///
+/// Use `#[sendgrid(template_id = "d-...")]` on the struct to tie it to a dynamic template, so
+/// the template id is set on the `MessageBuilder` it is added to.
+///
+/// # Examples
+/// ```
+/// # use serde::Serialize;
+/// # use sendgrid_rs::TemplateData;
+/// #[derive(Serialize, TemplateData)]
+/// #[sendgrid(template_id = "d-0123456789abcdef0123456789abcdef")]
+/// struct Welcome {
+///     first_name: String,
+/// }
+///
+/// assert_eq!(
+///     Welcome::template_id(),
+///     Some("d-0123456789abcdef0123456789abcdef")
+/// );
+/// ```
+#[proc_macro_derive(TemplateData, attributes(sendgrid))]
+pub fn derive_template_data(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match template_data(input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn template_data(input: DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    match &input.data {
+        Data::Struct(data) if matches!(data.fields, Fields::Named(_)) => {}
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "TemplateData can only be derived for structs with named fields",
+            ))
+        }
+    }
+
+    let mut template_id: Option<LitStr> = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("sendgrid")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("template_id") {
+                let id: LitStr = meta.value()?.parse()?;
+                if !id.value().starts_with("d-") {
+                    return Err(Error::new_spanned(
+                        &id,
+                        "dynamic template ids start with \"d-\"",
+                    ));
+                }
+                template_id = Some(id);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported sendgrid attribute, expected `template_id`"))
+            }
+        })?;
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let template_id = match template_id {
+        Some(id) => quote!(::std::option::Option::Some(#id)),
+        None => quote!(::std::option::Option::None),
+    };
+    Ok(quote! {
+        impl #impl_generics ::sendgrid_rs::TemplateData for #name #ty_generics #where_clause {
+            fn template_id() -> ::std::option::Option<&'static str> {
+                #template_id
+            }
+        }
+    })
+}
@@ -1,6 +1,10 @@
-use sendgrid_rs::{ContactBuilder, MailSettingsBuilder, MessageBuilder, PersonalizationBuilder};
+use sendgrid_rs::message::Message;
+use sendgrid_rs::transport::ReqwestTransport;
+use sendgrid_rs::{
+    ClientBuilder, ContactBuilder, MailSettingsBuilder, MessageBuilder, PersonalizationBuilder,
+};
 
-fn build_api_email() -> String {
+fn build_api_email() -> Message {
     MessageBuilder::new(
         ContactBuilder::new("from@example.com").name("from").build(),
         "Subject Line!",
@@ -14,20 +18,16 @@ fn build_api_email() -> String {
             .build(),
     )
     .build()
-    .to_json()
 }
 
-fn main() {
-    // SendGrid's v3 API URL
-    let url = "https://api.sendgrid.com/v3/mail/send";
-
+#[tokio::main]
+async fn main() {
     // API Key assigned by SendGrid
     let secret_api_key = "SENDGRID SECRET API KEY";
-    let client = reqwest::Client::new()
-        .post(url)
-        .header("authorization", format!("bearer {}", secret_api_key))
-        .header("content-type", "application/json")
-        .body(build_api_email());
+    let client = ClientBuilder::new(secret_api_key, ReqwestTransport::default()).build();
 
-    client.send().expect("Error calling API");
+    client
+        .send_async(&build_api_email())
+        .await
+        .expect("Error calling API");
 }
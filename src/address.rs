@@ -0,0 +1,592 @@
+//! Parsing and validation of email addresses.
+//!
+//! An [EmailAddress](struct.EmailAddress.html) is an RFC 5322 addr-spec: a dot-atom or quoted
+//! local part, an `@`, and a domain name or bracketed IP address literal. Internationalized
+//! domains are converted to their ASCII (punycode) form and domains are lowercased, so two
+//! spellings of the same address compare equal. The local part is kept as written, since its
+//! case may matter to the receiving server.
+//!
+//! `Contact::parse` also accepts the `"Name" <address>` display form, and `parse_list` reads a
+//! whole comma separated list of them, as found in `To` headers.
+//!
+//! # Examples
+//! ```
+//! # use sendgrid_rs::address::{AddressError, EmailAddress};
+//! let address: EmailAddress = "Jane.Doe@Bücher.Example".parse().unwrap();
+//! assert_eq!(address.local_part(), "Jane.Doe");
+//! assert_eq!(address.domain(), "xn--bcher-kva.example");
+//! assert_eq!(address.to_string(), "Jane.Doe@xn--bcher-kva.example");
+//!
+//! assert_eq!(
+//!     "jane..doe@example.com".parse::<EmailAddress>(),
+//!     Err(AddressError::ConsecutiveDots(5))
+//! );
+//! ```
+
+use crate::Contact;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::error::Error;
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr};
+use std::str::FromStr;
+
+/// The longest address SMTP can carry, in bytes
+pub const MAX_ADDRESS_LENGTH: usize = 254;
+
+/// The longest local part SMTP can carry, in bytes
+pub const MAX_LOCAL_PART_LENGTH: usize = 64;
+
+/// The reasons a string is not a valid email address. Positions are byte offsets into the
+/// string that was parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AddressError {
+    /// The string is empty or only whitespace
+    Empty,
+    /// There is no `@` separating the local part from the domain
+    MissingAt,
+    /// Nothing comes before the `@`
+    EmptyLocalPart,
+    /// The local part is longer than `MAX_LOCAL_PART_LENGTH` bytes
+    LocalPartTooLong(usize),
+    /// The local part starts or ends with a dot, at this position
+    MisplacedDot(usize),
+    /// The local part has two dots in a row, the second at this position
+    ConsecutiveDots(usize),
+    /// This character at this position is not allowed
+    InvalidCharacter(char, usize),
+    /// A quoted string or angle bracket is never closed
+    Unterminated(char),
+    /// Nothing comes after the `@`
+    EmptyDomain,
+    /// The domain is not a valid (internationalized) domain name or address literal
+    InvalidDomain(String),
+    /// The whole address is longer than `MAX_ADDRESS_LENGTH` bytes
+    TooLong(usize),
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressError::Empty => write!(f, "the address is empty"),
+            AddressError::MissingAt => write!(f, "the address has no @"),
+            AddressError::EmptyLocalPart => write!(f, "nothing comes before the @"),
+            AddressError::LocalPartTooLong(len) => write!(
+                f,
+                "the part before the @ is {} bytes, longer than {}",
+                len, MAX_LOCAL_PART_LENGTH
+            ),
+            AddressError::MisplacedDot(at) => {
+                write!(
+                    f,
+                    "the part before the @ starts or ends with a dot at {}",
+                    at
+                )
+            }
+            AddressError::ConsecutiveDots(at) => write!(f, "two dots in a row at {}", at),
+            AddressError::InvalidCharacter(c, at) => {
+                write!(f, "{:?} at {} is not allowed", c, at)
+            }
+            AddressError::Unterminated(c) => write!(f, "{} is never closed", c),
+            AddressError::EmptyDomain => write!(f, "nothing comes after the @"),
+            AddressError::InvalidDomain(domain) => write!(f, "{:?} is not a valid domain", domain),
+            AddressError::TooLong(len) => write!(
+                f,
+                "the address is {} bytes, longer than {}",
+                len, MAX_ADDRESS_LENGTH
+            ),
+        }
+    }
+}
+
+impl Error for AddressError {}
+
+/// A valid email address, with its domain in lowercase ASCII
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EmailAddress {
+    address: String,
+    at: usize,
+}
+
+impl EmailAddress {
+    /// Parses an addr-spec such as `jane@example.com`, without a display name.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::address::EmailAddress;
+    ///
+    /// let address = EmailAddress::parse("\"jane doe\"@EXAMPLE.com").unwrap();
+    /// assert_eq!(address.as_str(), "\"jane doe\"@example.com");
+    /// ```
+    pub fn parse(input: &str) -> Result<EmailAddress, AddressError> {
+        parse_addr_spec(input.trim(), input.len() - input.trim_start().len())
+    }
+
+    /// The part before the `@`
+    pub fn local_part(&self) -> &str {
+        &self.address[..self.at]
+    }
+
+    /// The part after the `@`, in lowercase ASCII
+    pub fn domain(&self) -> &str {
+        &self.address[self.at + 1..]
+    }
+
+    /// The whole address
+    pub fn as_str(&self) -> &str {
+        &self.address
+    }
+}
+
+impl FromStr for EmailAddress {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EmailAddress::parse(s)
+    }
+}
+
+impl fmt::Display for EmailAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.address)
+    }
+}
+
+impl AsRef<str> for EmailAddress {
+    fn as_ref(&self) -> &str {
+        &self.address
+    }
+}
+
+impl From<EmailAddress> for String {
+    fn from(address: EmailAddress) -> String {
+        address.address
+    }
+}
+
+impl Serialize for EmailAddress {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.address)
+    }
+}
+
+impl<'de> Deserialize<'de> for EmailAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        EmailAddress::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Returned by `parse_list` when an entry of the list is not a valid address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressListError {
+    position: usize,
+    entry: String,
+    error: AddressError,
+}
+
+impl AddressListError {
+    /// The byte offset of the invalid entry in the list
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The invalid entry, trimmed of whitespace
+    pub fn entry(&self) -> &str {
+        &self.entry
+    }
+
+    /// Why the entry is invalid, with positions relative to the entry
+    pub fn error(&self) -> &AddressError {
+        &self.error
+    }
+}
+
+impl fmt::Display for AddressListError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid address {:?} at {}: {}",
+            self.entry, self.position, self.error
+        )
+    }
+}
+
+impl Error for AddressListError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Parses an RFC 5322 address list, such as `Alice <a@example.com>, "Doe, Bob" <b@example.com>`,
+/// into `Contact`s. Entries may also be separated by semicolons, empty entries are skipped, and
+/// the members of groups like `Team: a@example.com, b@example.com;` are included.
+///
+/// # Examples
+/// ```
+/// # use sendgrid_rs::address;
+///
+/// let contacts = address::parse_list("Alice <a@x.com>, b@y.com; \"Doe, C\" <c@z.com>").unwrap();
+/// assert_eq!(contacts.len(), 3);
+/// assert_eq!(contacts[0].name(), Some("Alice"));
+/// assert_eq!(contacts[1].email(), "b@y.com");
+/// assert_eq!(contacts[2].name(), Some("Doe, C"));
+///
+/// let error = address::parse_list("a@x.com, nobody").unwrap_err();
+/// assert_eq!(error.position(), 9);
+/// assert_eq!(error.entry(), "nobody");
+/// ```
+pub fn parse_list(input: &str) -> Result<Vec<Contact>, AddressListError> {
+    let mut contacts = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut angle = false;
+    let ends = input
+        .char_indices()
+        .filter(|(_, c)| {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if quoted => escaped = true,
+                '"' => quoted = !quoted,
+                '<' if !quoted => angle = true,
+                '>' if !quoted => angle = false,
+                ',' | ';' if !quoted && !angle => return true,
+                _ => {}
+            }
+            false
+        })
+        .map(|(i, _)| i)
+        .chain(std::iter::once(input.len()))
+        .collect::<Vec<_>>();
+    for end in ends {
+        let mut entry = &input[start..end];
+        let mut position = start;
+        start = end + 1;
+
+        // A group's display name, up to a colon before any address
+        if let Some(colon) = find_unquoted(entry, ':') {
+            if !entry[..colon].contains(['<', '@']) {
+                entry = &entry[colon + 1..];
+                position += colon + 1;
+            }
+        }
+        position += entry.len() - entry.trim_start().len();
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+        let contact = Contact::parse(entry).map_err(|error| AddressListError {
+            position,
+            entry: entry.to_string(),
+            error,
+        })?;
+        contacts.push(contact);
+    }
+    Ok(contacts)
+}
+
+/// Parses `Name <address>`, `"Name" <address>`, `<address>` or a bare address into an optional
+/// display name and the address
+pub(crate) fn parse_mailbox(input: &str) -> Result<(Option<String>, EmailAddress), AddressError> {
+    let trimmed = input.trim_end();
+    if !trimmed.ends_with('>') {
+        if let Some(open) = find_unquoted(trimmed, '<') {
+            return Err(match trimmed[open..].find('>') {
+                Some(close) => AddressError::InvalidCharacter(
+                    trimmed[open + close + 1..].chars().next().unwrap_or('>'),
+                    open + close + 1,
+                ),
+                None => AddressError::Unterminated('<'),
+            });
+        }
+        return EmailAddress::parse(input).map(|address| (None, address));
+    }
+    let open = find_unquoted(trimmed, '<')
+        .ok_or(AddressError::InvalidCharacter('>', trimmed.len() - 1))?;
+    let address = &trimmed[open + 1..trimmed.len() - 1];
+    let offset = open + 1 + address.len() - address.trim_start().len();
+    let address = parse_addr_spec(address.trim(), offset)?;
+    let name = display_name(&input[..open])?;
+    Ok((name, address))
+}
+
+/// The position of the first `c` in `s` outside a quoted string
+fn find_unquoted(s: &str, c: char) -> Option<usize> {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, ch) in s.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            _ if ch == c && !quoted => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Unquotes a display name, returning `None` if it is empty
+fn display_name(raw: &str) -> Result<Option<String>, AddressError> {
+    let raw = raw.trim();
+    let name = match raw.strip_prefix('"') {
+        Some(rest) => {
+            let (value, len) = quoted_string(rest).ok_or(AddressError::Unterminated('"'))?;
+            if len + 1 != rest.len() {
+                return Err(AddressError::InvalidCharacter(
+                    rest[len + 1..].chars().next().unwrap_or('"'),
+                    len + 2,
+                ));
+            }
+            value
+        }
+        None => raw.split_whitespace().collect::<Vec<_>>().join(" "),
+    };
+    Ok(Some(name).filter(|n| !n.is_empty()))
+}
+
+/// Reads a quoted string up to its closing quote, returning its unescaped value and the byte
+/// length of what was read, excluding the closing quote
+fn quoted_string(s: &str) -> Option<(String, usize)> {
+    let mut value = String::new();
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => {
+                value.push(c);
+                escaped = false;
+            }
+            '\\' => escaped = true,
+            '"' => return Some((value, i)),
+            _ => value.push(c),
+        }
+    }
+    None
+}
+
+fn is_atext(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(c)
+}
+
+/// Parses a trimmed addr-spec that starts at `offset` in the original input
+fn parse_addr_spec(input: &str, offset: usize) -> Result<EmailAddress, AddressError> {
+    if input.is_empty() {
+        return Err(AddressError::Empty);
+    }
+
+    let local_len = match input.strip_prefix('"') {
+        Some(rest) => {
+            let (_, len) = quoted_string(rest).ok_or(AddressError::Unterminated('"'))?;
+            for (i, c) in rest[..len].char_indices() {
+                if c.is_control() || !c.is_ascii() {
+                    return Err(AddressError::InvalidCharacter(c, offset + 1 + i));
+                }
+            }
+            len + 2
+        }
+        None => {
+            let end = input.rfind('@').ok_or(AddressError::MissingAt)?;
+            let local = &input[..end];
+            let mut previous = None;
+            for (i, c) in local.char_indices() {
+                if c == '.' {
+                    if i == 0 || i + 1 == local.len() {
+                        return Err(AddressError::MisplacedDot(offset + i));
+                    }
+                    if previous == Some('.') {
+                        return Err(AddressError::ConsecutiveDots(offset + i));
+                    }
+                } else if !is_atext(c) {
+                    return Err(AddressError::InvalidCharacter(c, offset + i));
+                }
+                previous = Some(c);
+            }
+            end
+        }
+    };
+
+    match input[local_len..].chars().next() {
+        Some('@') => {}
+        Some(c) => return Err(AddressError::InvalidCharacter(c, offset + local_len)),
+        None => return Err(AddressError::MissingAt),
+    }
+    if local_len == 0 {
+        return Err(AddressError::EmptyLocalPart);
+    }
+    if local_len > MAX_LOCAL_PART_LENGTH {
+        return Err(AddressError::LocalPartTooLong(local_len));
+    }
+
+    let domain = parse_domain(&input[local_len + 1..])?;
+    let address = format!("{}@{}", &input[..local_len], domain);
+    if address.len() > MAX_ADDRESS_LENGTH {
+        return Err(AddressError::TooLong(address.len()));
+    }
+    Ok(EmailAddress {
+        address,
+        at: local_len,
+    })
+}
+
+/// Validates a domain name or address literal, returning it in lowercase ASCII
+fn parse_domain(domain: &str) -> Result<String, AddressError> {
+    let invalid = || AddressError::InvalidDomain(domain.to_string());
+    if domain.is_empty() {
+        return Err(AddressError::EmptyDomain);
+    }
+    if let Some(literal) = domain.strip_prefix('[') {
+        let literal = literal.strip_suffix(']').ok_or_else(invalid)?;
+        let valid = match literal.get(..5) {
+            Some(tag) if tag.eq_ignore_ascii_case("IPv6:") => {
+                literal[5..].parse::<Ipv6Addr>().is_ok()
+            }
+            _ => matches!(literal.parse::<IpAddr>(), Ok(IpAddr::V4(_))),
+        };
+        return if valid {
+            Ok(domain.to_ascii_lowercase().replace("ipv6:", "IPv6:"))
+        } else {
+            Err(invalid())
+        };
+    }
+
+    let ascii = idna::domain_to_ascii_strict(domain).map_err(|_| invalid())?;
+    let labels_valid = ascii.split('.').all(|label| {
+        !label.is_empty() && label.len() <= 63 && !label.starts_with('-') && !label.ends_with('-')
+    });
+    if !labels_valid || ascii.len() > 253 {
+        return Err(invalid());
+    }
+    Ok(ascii)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_list, parse_mailbox, AddressError, EmailAddress};
+
+    #[test]
+    fn parses_addr_specs() {
+        let address = EmailAddress::parse("  first.o'last+tag@Sub.EXAMPLE.com ").unwrap();
+        assert_eq!(address.local_part(), "first.o'last+tag");
+        assert_eq!(address.domain(), "sub.example.com");
+
+        let address = EmailAddress::parse("\"a@b \\\" c\"@example.com").unwrap();
+        assert_eq!(address.local_part(), "\"a@b \\\" c\"");
+
+        let address = EmailAddress::parse("postmaster@[IPv6:2001:DB8::1]").unwrap();
+        assert_eq!(address.domain(), "[IPv6:2001:db8::1]");
+        assert!(EmailAddress::parse("postmaster@[192.168.0.1]").is_ok());
+
+        let address = EmailAddress::parse("user@例え.テスト").unwrap();
+        assert_eq!(address.domain(), "xn--r8jz45g.xn--zckzah");
+    }
+
+    #[test]
+    fn rejects_invalid_addresses() {
+        let cases = [
+            ("", AddressError::Empty),
+            ("example.com", AddressError::MissingAt),
+            ("@example.com", AddressError::EmptyLocalPart),
+            ("user@", AddressError::EmptyDomain),
+            (".user@example.com", AddressError::MisplacedDot(0)),
+            ("user.@example.com", AddressError::MisplacedDot(4)),
+            ("us..er@example.com", AddressError::ConsecutiveDots(3)),
+            ("us er@example.com", AddressError::InvalidCharacter(' ', 2)),
+            ("jöhn@example.com", AddressError::InvalidCharacter('ö', 1)),
+            ("\"user@example.com", AddressError::Unterminated('"')),
+            (
+                "\"us\"er@example.com",
+                AddressError::InvalidCharacter('e', 4),
+            ),
+            (
+                "user@exa mple.com",
+                AddressError::InvalidDomain(String::from("exa mple.com")),
+            ),
+            (
+                "user@-example.com",
+                AddressError::InvalidDomain(String::from("-example.com")),
+            ),
+            (
+                "user@example..com",
+                AddressError::InvalidDomain(String::from("example..com")),
+            ),
+            (
+                "user@[300.1.1.1]",
+                AddressError::InvalidDomain(String::from("[300.1.1.1]")),
+            ),
+        ];
+        for (input, error) in cases.iter() {
+            assert_eq!(EmailAddress::parse(input).as_ref(), Err(error), "{}", input);
+        }
+
+        let long = format!("{}@example.com", "a".repeat(65));
+        assert_eq!(
+            EmailAddress::parse(&long),
+            Err(AddressError::LocalPartTooLong(65))
+        );
+        let long = format!(
+            "{}@{}.com",
+            "a".repeat(64),
+            vec!["b".repeat(60); 4].join(".")
+        );
+        assert_eq!(EmailAddress::parse(&long), Err(AddressError::TooLong(312)));
+    }
+
+    #[test]
+    fn parses_display_names() {
+        let (name, address) = parse_mailbox("\"Doe, Jane \\\"JD\\\"\" <jane@Example.com>").unwrap();
+        assert_eq!(name.as_deref(), Some("Doe, Jane \"JD\""));
+        assert_eq!(address.as_str(), "jane@example.com");
+
+        let (name, address) = parse_mailbox("  Jane   Doe <jane@example.com>").unwrap();
+        assert_eq!(name.as_deref(), Some("Jane Doe"));
+        assert_eq!(address.as_str(), "jane@example.com");
+
+        let (name, _) = parse_mailbox("<jane@example.com>").unwrap();
+        assert_eq!(name, None);
+
+        assert_eq!(
+            parse_mailbox("Jane <jane@example.com"),
+            Err(AddressError::Unterminated('<'))
+        );
+        assert_eq!(
+            parse_mailbox("Jane <jane@example.com> x"),
+            Err(AddressError::InvalidCharacter(' ', 23))
+        );
+        assert_eq!(
+            parse_mailbox("Jane <jane@@example.com>"),
+            Err(AddressError::InvalidCharacter('@', 10))
+        );
+    }
+
+    #[test]
+    fn parses_address_lists() {
+        let contacts = parse_list(concat!(
+            "  Alice <alice@example.com> ,bob@EXAMPLE.com,,",
+            "\"Doe, Carol; CEO\" <carol@example.com>;",
+            "Team: dan@example.com, Erin <erin@example.com>;",
+            "Empty group:;",
+        ))
+        .unwrap();
+        let found: Vec<_> = contacts.iter().map(|c| (c.name(), c.email())).collect();
+        assert_eq!(
+            found,
+            vec![
+                (Some("Alice"), "alice@example.com"),
+                (None, "bob@example.com"),
+                (Some("Doe, Carol; CEO"), "carol@example.com"),
+                (None, "dan@example.com"),
+                (Some("Erin"), "erin@example.com"),
+            ]
+        );
+        assert_eq!(parse_list("  ").unwrap(), vec![]);
+
+        let error = parse_list("a@example.com,  Bob <bob@example..com>").unwrap_err();
+        assert_eq!(error.position(), 16);
+        assert_eq!(error.entry(), "Bob <bob@example..com>");
+        assert_eq!(
+            error.error(),
+            &AddressError::InvalidDomain(String::from("example..com"))
+        );
+    }
+}
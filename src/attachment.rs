@@ -1,15 +1,20 @@
-use serde::Serialize;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
 
 /// This is a struct for serializing SendGrid API attachments.
 /// Use `AttachmentBuilder` to construct these.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Attachment {
-    content: String,
+    pub(crate) content: String,
     #[serde(rename = "type")]
-    a_type: Option<String>,
-    filename: String,
-    disposition: Option<String>,
-    content_id: Option<String>,
+    pub(crate) a_type: Option<String>,
+    pub(crate) filename: String,
+    pub(crate) disposition: Option<String>,
+    pub(crate) content_id: Option<String>,
 }
 
 /// Builder pattern for creating an `Attachment`
@@ -42,6 +47,63 @@ impl AttachmentBuilder {
         }
     }
 
+    /// Constructs an `AttachmentBuilder` from raw bytes, which are base64 encoded for you. The
+    /// mime type is inferred from the filename's extension, or failing that the content itself,
+    /// and can be overridden with `attachment_type`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::AttachmentBuilder;
+    ///
+    /// let builder = AttachmentBuilder::from_bytes(b"Hello World!", "file.txt");
+    /// ```
+    pub fn from_bytes(content: impl AsRef<[u8]>, filename: impl Into<String>) -> Self {
+        let content = content.as_ref();
+        let filename = filename.into();
+        let a_type = guess_type(&filename, content).map(String::from);
+        AttachmentBuilder {
+            attachment: Attachment {
+                content: STANDARD.encode(content),
+                a_type,
+                filename,
+                disposition: None,
+                content_id: None,
+            },
+        }
+    }
+
+    /// Constructs an `AttachmentBuilder` by reading `reader` to the end, see `from_bytes`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::AttachmentBuilder;
+    ///
+    /// let builder = AttachmentBuilder::from_reader(&b"Hello World!"[..], "file.txt").unwrap();
+    /// ```
+    pub fn from_reader(mut reader: impl Read, filename: impl Into<String>) -> io::Result<Self> {
+        let mut content = vec![];
+        reader.read_to_end(&mut content)?;
+        Ok(AttachmentBuilder::from_bytes(content, filename))
+    }
+
+    /// Constructs an `AttachmentBuilder` from the file at `path`, named after the file, see
+    /// `from_bytes`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use sendgrid_rs::AttachmentBuilder;
+    ///
+    /// let builder = AttachmentBuilder::from_path("invoice.pdf").unwrap();
+    /// ```
+    pub fn from_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let filename = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        AttachmentBuilder::from_reader(File::open(path)?, filename)
+    }
+
     /// Sets the mime type on the `Attachment`
     ///
     /// # Examples
@@ -105,3 +167,109 @@ impl AttachmentBuilder {
         self.attachment
     }
 }
+
+/// Infers a mime type from a filename's extension, falling back to the content's magic bytes
+pub(crate) fn guess_type(filename: &str, content: &[u8]) -> Option<&'static str> {
+    let extension = Path::new(filename)
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    let by_extension = match extension.as_deref() {
+        Some("txt") => Some("text/plain"),
+        Some("htm") | Some("html") => Some("text/html"),
+        Some("csv") => Some("text/csv"),
+        Some("ics") => Some("text/calendar"),
+        Some("css") => Some("text/css"),
+        Some("xml") => Some("application/xml"),
+        Some("json") => Some("application/json"),
+        Some("pdf") => Some("application/pdf"),
+        Some("zip") => Some("application/zip"),
+        Some("gz") => Some("application/gzip"),
+        Some("doc") => Some("application/msword"),
+        Some("docx") => {
+            Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document")
+        }
+        Some("xls") => Some("application/vnd.ms-excel"),
+        Some("xlsx") => Some("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+        Some("ppt") => Some("application/vnd.ms-powerpoint"),
+        Some("pptx") => {
+            Some("application/vnd.openxmlformats-officedocument.presentationml.presentation")
+        }
+        Some("png") => Some("image/png"),
+        Some("jpg") | Some("jpeg") => Some("image/jpeg"),
+        Some("gif") => Some("image/gif"),
+        Some("bmp") => Some("image/bmp"),
+        Some("webp") => Some("image/webp"),
+        Some("svg") => Some("image/svg+xml"),
+        Some("ico") => Some("image/x-icon"),
+        Some("mp3") => Some("audio/mpeg"),
+        Some("wav") => Some("audio/wav"),
+        Some("mp4") => Some("video/mp4"),
+        _ => None,
+    };
+    by_extension.or_else(|| {
+        let magic: &[(&[u8], &str)] = &[
+            (b"\x89PNG\r\n\x1a\n", "image/png"),
+            (b"\xff\xd8\xff", "image/jpeg"),
+            (b"GIF87a", "image/gif"),
+            (b"GIF89a", "image/gif"),
+            (b"%PDF-", "application/pdf"),
+            (b"PK\x03\x04", "application/zip"),
+            (b"\x1f\x8b", "application/gzip"),
+            (b"BM", "image/bmp"),
+        ];
+        magic
+            .iter()
+            .find(|(prefix, _)| content.starts_with(prefix))
+            .map(|(_, t)| *t)
+            .or_else(|| {
+                if content.len() >= 12 && &content[..4] == b"RIFF" && &content[8..12] == b"WEBP" {
+                    Some("image/webp")
+                } else {
+                    None
+                }
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{guess_type, AttachmentBuilder};
+
+    #[test]
+    fn encodes_and_infers_type() {
+        let attachment = AttachmentBuilder::from_bytes(b"Hello World!", "file.txt").build();
+        assert_eq!(attachment.content, "SGVsbG8gV29ybGQh");
+        assert_eq!(attachment.a_type.as_deref(), Some("text/plain"));
+        assert_eq!(attachment.filename, "file.txt");
+
+        let attachment = AttachmentBuilder::from_bytes(b"Hello World!", "file.txt")
+            .attachment_type("text/markdown")
+            .build();
+        assert_eq!(attachment.a_type.as_deref(), Some("text/markdown"));
+    }
+
+    #[test]
+    fn infers_type_from_magic_bytes() {
+        assert_eq!(
+            guess_type("logo", b"\x89PNG\r\n\x1a\n\0\0"),
+            Some("image/png")
+        );
+        assert_eq!(guess_type("scan.PDF", b""), Some("application/pdf"));
+        assert_eq!(guess_type("report", b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(guess_type("data", b"\0\0\0"), None);
+    }
+
+    #[test]
+    fn reads_files() {
+        let path = std::env::temp_dir().join(format!("sendgrid-rs-{}.csv", std::process::id()));
+        std::fs::write(&path, "a,b\n1,2\n").unwrap();
+        let attachment = AttachmentBuilder::from_path(&path).unwrap().build();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(attachment.content, "YSxiCjEsMgo=");
+        assert_eq!(attachment.a_type.as_deref(), Some("text/csv"));
+        assert_eq!(
+            Some(attachment.filename.as_str()),
+            path.file_name().and_then(|n| n.to_str())
+        );
+    }
+}
@@ -0,0 +1,406 @@
+//! Models and requests for managing scheduled sends. A batch id groups scheduled `Message`s, set
+//! with `MessageBuilder::batch_id`, so they can be paused or cancelled together before their
+//! send_at time.
+//!
+//! Each request implements [Endpoint](../client/trait.Endpoint.html) and is made with
+//! `Client::call`.
+//!
+//! # Examples
+//! ```
+//! # use sendgrid_rs::batch::{BatchId, CreateBatchId, CreateScheduledSend, ScheduledSendStatus};
+//! # use sendgrid_rs::client::ClientBuilder;
+//! # use sendgrid_rs::transport::{Request, Response, Transport};
+//! # struct Fake;
+//! # impl Transport for Fake {
+//! #     type Error = std::io::Error;
+//! #     fn send(&self, request: &Request) -> Result<Response, Self::Error> {
+//! #         Ok(Response::new(201, r#"{"batch_id": "YOUR_BATCH_ID", "status": "cancel"}"#))
+//! #     }
+//! # }
+//! let client = ClientBuilder::new("SENDGRID SECRET API KEY", Fake).build();
+//!
+//! let batch: BatchId = client.call(&CreateBatchId).unwrap();
+//! // ... send messages with MessageBuilder::batch_id(batch.id()) and a send_at time
+//! let cancelled = client
+//!     .call(&CreateScheduledSend::new(batch.id(), ScheduledSendStatus::Cancel))
+//!     .unwrap();
+//! assert_eq!(cancelled.status(), ScheduledSendStatus::Cancel);
+//! ```
+
+use crate::client::{encode_path_segment, Endpoint};
+use crate::transport::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// The path of the batch id endpoints, relative to the base URL
+pub const MAIL_BATCH_PATH: &str = "/v3/mail/batch";
+
+/// The path of the scheduled send endpoints, relative to the base URL
+pub const SCHEDULED_SENDS_PATH: &str = "/v3/user/scheduled_sends";
+
+/// A batch id, as returned by SendGrid
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BatchId {
+    batch_id: String,
+}
+
+impl BatchId {
+    /// The id, to pass to `MessageBuilder::batch_id`
+    pub fn id(&self) -> &str {
+        &self.batch_id
+    }
+}
+
+/// What happens to the scheduled `Message`s of a batch
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduledSendStatus {
+    /// The messages are held until the batch is resumed, and discarded if that is more than 72
+    /// hours after their send_at time
+    Pause,
+    /// The messages are discarded
+    Cancel,
+}
+
+/// A batch that has been paused or cancelled
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduledSend {
+    batch_id: String,
+    status: ScheduledSendStatus,
+}
+
+impl ScheduledSend {
+    /// The batch id
+    pub fn batch_id(&self) -> &str {
+        &self.batch_id
+    }
+
+    /// Whether the batch is paused or cancelled
+    pub fn status(&self) -> ScheduledSendStatus {
+        self.status
+    }
+}
+
+/// `POST /v3/mail/batch`, creates a new batch id
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CreateBatchId;
+
+impl Endpoint for CreateBatchId {
+    type Output = BatchId;
+
+    fn method(&self) -> Method {
+        Method::Post
+    }
+
+    fn path(&self) -> String {
+        String::from(MAIL_BATCH_PATH)
+    }
+}
+
+/// `GET /v3/mail/batch/{batch_id}`, checks that a batch id is valid. SendGrid responds with an
+/// error if it is not.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidateBatchId {
+    batch_id: String,
+}
+
+impl ValidateBatchId {
+    /// Constructs a `ValidateBatchId` for `batch_id`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::batch::ValidateBatchId;
+    ///
+    /// let request = ValidateBatchId::new("YOUR_BATCH_ID");
+    /// ```
+    pub fn new(batch_id: impl Into<String>) -> Self {
+        ValidateBatchId {
+            batch_id: batch_id.into(),
+        }
+    }
+}
+
+impl Endpoint for ValidateBatchId {
+    type Output = BatchId;
+
+    fn method(&self) -> Method {
+        Method::Get
+    }
+
+    fn path(&self) -> String {
+        format!(
+            "{}/{}",
+            MAIL_BATCH_PATH,
+            encode_path_segment(&self.batch_id)
+        )
+    }
+}
+
+/// `GET /v3/user/scheduled_sends`, lists every paused or cancelled batch
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ListScheduledSends;
+
+impl Endpoint for ListScheduledSends {
+    type Output = Vec<ScheduledSend>;
+
+    fn method(&self) -> Method {
+        Method::Get
+    }
+
+    fn path(&self) -> String {
+        String::from(SCHEDULED_SENDS_PATH)
+    }
+}
+
+/// `GET /v3/user/scheduled_sends/{batch_id}`, the status of a batch. The list is empty if the
+/// batch is neither paused nor cancelled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GetScheduledSend {
+    batch_id: String,
+}
+
+impl GetScheduledSend {
+    /// Constructs a `GetScheduledSend` for `batch_id`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::batch::GetScheduledSend;
+    ///
+    /// let request = GetScheduledSend::new("YOUR_BATCH_ID");
+    /// ```
+    pub fn new(batch_id: impl Into<String>) -> Self {
+        GetScheduledSend {
+            batch_id: batch_id.into(),
+        }
+    }
+}
+
+impl Endpoint for GetScheduledSend {
+    type Output = Vec<ScheduledSend>;
+
+    fn method(&self) -> Method {
+        Method::Get
+    }
+
+    fn path(&self) -> String {
+        format!(
+            "{}/{}",
+            SCHEDULED_SENDS_PATH,
+            encode_path_segment(&self.batch_id)
+        )
+    }
+}
+
+/// `POST /v3/user/scheduled_sends`, pauses or cancels a batch
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateScheduledSend {
+    batch_id: String,
+    status: ScheduledSendStatus,
+}
+
+impl CreateScheduledSend {
+    /// Constructs a `CreateScheduledSend` setting `batch_id` to `status`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::batch::{CreateScheduledSend, ScheduledSendStatus};
+    ///
+    /// let request = CreateScheduledSend::new("YOUR_BATCH_ID", ScheduledSendStatus::Pause);
+    /// ```
+    pub fn new(batch_id: impl Into<String>, status: ScheduledSendStatus) -> Self {
+        CreateScheduledSend {
+            batch_id: batch_id.into(),
+            status,
+        }
+    }
+}
+
+impl Endpoint for CreateScheduledSend {
+    type Output = ScheduledSend;
+
+    fn method(&self) -> Method {
+        Method::Post
+    }
+
+    fn path(&self) -> String {
+        String::from(SCHEDULED_SENDS_PATH)
+    }
+
+    fn body(&self) -> Option<String> {
+        Some(json!({"batch_id": self.batch_id, "status": self.status}).to_string())
+    }
+}
+
+/// `PATCH /v3/user/scheduled_sends/{batch_id}`, changes a paused batch to cancelled or back
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateScheduledSend {
+    batch_id: String,
+    status: ScheduledSendStatus,
+}
+
+impl UpdateScheduledSend {
+    /// Constructs an `UpdateScheduledSend` setting `batch_id` to `status`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::batch::{UpdateScheduledSend, ScheduledSendStatus};
+    ///
+    /// let request = UpdateScheduledSend::new("YOUR_BATCH_ID", ScheduledSendStatus::Cancel);
+    /// ```
+    pub fn new(batch_id: impl Into<String>, status: ScheduledSendStatus) -> Self {
+        UpdateScheduledSend {
+            batch_id: batch_id.into(),
+            status,
+        }
+    }
+}
+
+impl Endpoint for UpdateScheduledSend {
+    type Output = ();
+
+    fn method(&self) -> Method {
+        Method::Patch
+    }
+
+    fn path(&self) -> String {
+        format!(
+            "{}/{}",
+            SCHEDULED_SENDS_PATH,
+            encode_path_segment(&self.batch_id)
+        )
+    }
+
+    fn body(&self) -> Option<String> {
+        Some(json!({ "status": self.status }).to_string())
+    }
+}
+
+/// `DELETE /v3/user/scheduled_sends/{batch_id}`, resumes a paused batch
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeleteScheduledSend {
+    batch_id: String,
+}
+
+impl DeleteScheduledSend {
+    /// Constructs a `DeleteScheduledSend` for `batch_id`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::batch::DeleteScheduledSend;
+    ///
+    /// let request = DeleteScheduledSend::new("YOUR_BATCH_ID");
+    /// ```
+    pub fn new(batch_id: impl Into<String>) -> Self {
+        DeleteScheduledSend {
+            batch_id: batch_id.into(),
+        }
+    }
+}
+
+impl Endpoint for DeleteScheduledSend {
+    type Output = ();
+
+    fn method(&self) -> Method {
+        Method::Delete
+    }
+
+    fn path(&self) -> String {
+        format!(
+            "{}/{}",
+            SCHEDULED_SENDS_PATH,
+            encode_path_segment(&self.batch_id)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CreateBatchId, CreateScheduledSend, DeleteScheduledSend, ListScheduledSends,
+        ScheduledSendStatus, UpdateScheduledSend, ValidateBatchId,
+    };
+    use crate::client::{ClientBuilder, Error};
+    use crate::transport::{Method, Replay};
+    use serde_json::json;
+
+    #[test]
+    fn creates_and_validates_batch_ids() {
+        let transport = Replay::new(201, r#"{"batch_id": "HkJ5yLYULb7Rj8GKSx7u025ouWVlMgAi"}"#);
+        let client = ClientBuilder::new("KEY", &transport).build();
+
+        let batch = client.call(&CreateBatchId).unwrap();
+        assert_eq!(batch.id(), "HkJ5yLYULb7Rj8GKSx7u025ouWVlMgAi");
+        assert_eq!(
+            transport.last(),
+            (
+                Method::Post,
+                String::from("https://api.sendgrid.com/v3/mail/batch"),
+                None
+            )
+        );
+
+        client.call(&ValidateBatchId::new("a b/c")).unwrap();
+        assert_eq!(
+            transport.last().1,
+            "https://api.sendgrid.com/v3/mail/batch/a%20b%2Fc"
+        );
+    }
+
+    #[test]
+    fn pauses_updates_and_resumes_batches() {
+        let transport = Replay::new(201, r#"{"batch_id": "abc", "status": "pause"}"#);
+        let client = ClientBuilder::new("KEY", &transport).build();
+
+        let send = client
+            .call(&CreateScheduledSend::new("abc", ScheduledSendStatus::Pause))
+            .unwrap();
+        assert_eq!(send.batch_id(), "abc");
+        assert_eq!(send.status(), ScheduledSendStatus::Pause);
+        assert_eq!(
+            transport.last(),
+            (
+                Method::Post,
+                String::from("https://api.sendgrid.com/v3/user/scheduled_sends"),
+                Some(json!({"batch_id": "abc", "status": "pause"}))
+            )
+        );
+
+        let transport = Replay::new(204, "");
+        let client = ClientBuilder::new("KEY", &transport).build();
+        client
+            .call(&UpdateScheduledSend::new(
+                "abc",
+                ScheduledSendStatus::Cancel,
+            ))
+            .unwrap();
+        assert_eq!(
+            transport.last(),
+            (
+                Method::Patch,
+                String::from("https://api.sendgrid.com/v3/user/scheduled_sends/abc"),
+                Some(json!({"status": "cancel"}))
+            )
+        );
+        client.call(&DeleteScheduledSend::new("abc")).unwrap();
+        assert_eq!(transport.last().0, Method::Delete);
+    }
+
+    #[test]
+    fn lists_scheduled_sends() {
+        let transport = Replay::new(
+            200,
+            r#"[{"batch_id": "a", "status": "pause"}, {"batch_id": "b", "status": "cancel"}]"#,
+        );
+        let client = ClientBuilder::new("KEY", &transport).build();
+        let sends = client.call(&ListScheduledSends).unwrap();
+        assert_eq!(sends.len(), 2);
+        assert_eq!(sends[1].status(), ScheduledSendStatus::Cancel);
+
+        let transport = Replay::new(200, r#"{"unexpected": true}"#);
+        let client = ClientBuilder::new("KEY", &transport).build();
+        assert!(matches!(
+            client.call(&ListScheduledSends),
+            Err(Error::Parse(_))
+        ));
+    }
+}
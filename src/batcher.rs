@@ -0,0 +1,253 @@
+//! Splits a large set of `Personalization`s into as many `Message`s as SendGrid's limits require.
+//!
+//! A `Message` carries at most `MAX_PERSONALIZATIONS` personalizations and `MAX_RECIPIENTS`
+//! recipients. A `Batcher` copies a template `Message` once per chunk, filling each copy with as
+//! many of the personalizations as fit, in their original order. Giving the batcher a batch id,
+//! e.g. from [CreateBatchId](../batch/struct.CreateBatchId.html), lets every chunk of a scheduled
+//! send be paused or cancelled together.
+//!
+//! # Examples
+//! ```
+//! # use sendgrid_rs::{BatcherBuilder, ContactBuilder, Content, MessageBuilder, PersonalizationBuilder};
+//! let template = MessageBuilder::new(
+//!     ContactBuilder::new("from@example.com").build(),
+//!     "Our spring sale",
+//! )
+//! .content(Content::new("text/plain", "Everything is 20% off"));
+//! let personalizations = (0..2500).map(|i| {
+//!     PersonalizationBuilder::default()
+//!         .to(ContactBuilder::new(format!("user{}@example.com", i)).build())
+//!         .build()
+//! });
+//!
+//! let messages: Vec<_> = BatcherBuilder::new(template)
+//!     .batch_id("YOUR_BATCH_ID")
+//!     .build(personalizations)
+//!     .collect();
+//! assert_eq!(messages.len(), 3);
+//! assert!(messages.iter().all(|m| m.validate().is_ok()));
+//! ```
+
+use crate::message::{Message, MessageBuilder};
+use crate::personalization::Personalization;
+use crate::validation::{MAX_PERSONALIZATIONS, MAX_RECIPIENTS};
+use std::iter::{Chain, Peekable};
+use std::vec;
+
+/// An iterator of `Message`s, each holding the next chunk of personalizations. Use
+/// `BatcherBuilder` to construct this.
+///
+/// A single personalization with more than the maximum recipients can't be split, so it is
+/// yielded in a `Message` of its own, which fails validation.
+pub struct Batcher<I: Iterator<Item = Personalization>> {
+    template: Message,
+    personalizations: Peekable<Chain<vec::IntoIter<Personalization>, I>>,
+    max_personalizations: usize,
+    max_recipients: usize,
+}
+
+impl<I: Iterator<Item = Personalization>> Iterator for Batcher<I> {
+    type Item = Message;
+
+    fn next(&mut self) -> Option<Message> {
+        let (max_personalizations, max_recipients) =
+            (self.max_personalizations, self.max_recipients);
+        let mut personalizations = vec![];
+        let mut recipients = 0;
+        while let Some(personalization) = self.personalizations.next_if(|p| {
+            personalizations.is_empty()
+                || (personalizations.len() < max_personalizations
+                    && recipients + p.recipient_count() <= max_recipients)
+        }) {
+            recipients += personalization.recipient_count();
+            personalizations.push(personalization);
+        }
+        if personalizations.is_empty() {
+            return None;
+        }
+
+        let mut message = self.template.clone();
+        message.personalizations = personalizations;
+        Some(message)
+    }
+}
+
+/// Builder pattern for `Batcher`. Make sure you call `build()` with the personalizations when
+/// done to consume the builder and return the `Batcher`.
+pub struct BatcherBuilder {
+    template: Message,
+    max_personalizations: usize,
+    max_recipients: usize,
+}
+
+impl BatcherBuilder {
+    /// Constructs a `BatcherBuilder`. The required parameter is the `MessageBuilder` every
+    /// `Message` is built from. Personalizations already added to it are sent first.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::{BatcherBuilder, ContactBuilder, MessageBuilder};
+    ///
+    /// let builder = BatcherBuilder::new(MessageBuilder::new(
+    ///     ContactBuilder::new("from@example.com").build(),
+    ///     "Subject Line",
+    /// ));
+    /// ```
+    pub fn new(template: MessageBuilder) -> Self {
+        BatcherBuilder {
+            template: template.build(),
+            max_personalizations: MAX_PERSONALIZATIONS,
+            max_recipients: MAX_RECIPIENTS,
+        }
+    }
+
+    /// Sets the batch id of every `Message`, overriding any on the template
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::{BatcherBuilder, ContactBuilder, MessageBuilder};
+    ///
+    /// let builder = BatcherBuilder::new(MessageBuilder::new(
+    ///     ContactBuilder::new("from@example.com").build(),
+    ///     "Subject Line",
+    /// ))
+    /// .batch_id("YOUR_BATCH_ID");
+    /// ```
+    pub fn batch_id(mut self, id: impl Into<String>) -> Self {
+        self.template.batch_id = Some(id.into());
+        self
+    }
+
+    /// Lowers the number of personalizations per `Message` below `MAX_PERSONALIZATIONS`. At least
+    /// one personalization is always sent per `Message`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::{BatcherBuilder, ContactBuilder, MessageBuilder};
+    ///
+    /// let builder = BatcherBuilder::new(MessageBuilder::new(
+    ///     ContactBuilder::new("from@example.com").build(),
+    ///     "Subject Line",
+    /// ))
+    /// .max_personalizations(100);
+    /// ```
+    pub fn max_personalizations(mut self, max: usize) -> Self {
+        self.max_personalizations = max.min(MAX_PERSONALIZATIONS);
+        self
+    }
+
+    /// Lowers the number of recipients per `Message` below `MAX_RECIPIENTS`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::{BatcherBuilder, ContactBuilder, MessageBuilder};
+    ///
+    /// let builder = BatcherBuilder::new(MessageBuilder::new(
+    ///     ContactBuilder::new("from@example.com").build(),
+    ///     "Subject Line",
+    /// ))
+    /// .max_recipients(500);
+    /// ```
+    pub fn max_recipients(mut self, max: usize) -> Self {
+        self.max_recipients = max.min(MAX_RECIPIENTS);
+        self
+    }
+
+    /// Consumes the builder and returns a `Batcher` over `personalizations`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::{BatcherBuilder, ContactBuilder, MessageBuilder};
+    ///
+    /// let mut batcher = BatcherBuilder::new(MessageBuilder::new(
+    ///     ContactBuilder::new("from@example.com").build(),
+    ///     "Subject Line",
+    /// ))
+    /// .build(vec![]);
+    /// assert!(batcher.next().is_none());
+    /// ```
+    pub fn build<I>(mut self, personalizations: I) -> Batcher<I::IntoIter>
+    where
+        I: IntoIterator<Item = Personalization>,
+    {
+        let first = std::mem::take(&mut self.template.personalizations);
+        Batcher {
+            template: self.template,
+            personalizations: first.into_iter().chain(personalizations).peekable(),
+            max_personalizations: self.max_personalizations,
+            max_recipients: self.max_recipients,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BatcherBuilder;
+    use crate::personalization::Personalization;
+    use crate::{ContactBuilder, MessageBuilder, PersonalizationBuilder};
+
+    fn personalization(id: usize, recipients: usize) -> Personalization {
+        (0..recipients)
+            .fold(PersonalizationBuilder::default(), |builder, i| {
+                builder.bcc(ContactBuilder::new(format!("{}-{}@example.com", id, i)).build())
+            })
+            .custom_arg("id".to_string(), id.to_string())
+            .build()
+    }
+
+    fn template() -> MessageBuilder {
+        MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "subject")
+            .batch_id("template")
+    }
+
+    fn ids(personalizations: &[Personalization]) -> Vec<usize> {
+        personalizations
+            .iter()
+            .map(|p| p.custom_args["id"].parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn splits_on_personalization_count() {
+        let messages: Vec<_> =
+            BatcherBuilder::new(template().personalization(personalization(0, 1)))
+                .build((1..2001).map(|id| personalization(id, 0)))
+                .collect();
+
+        let sizes: Vec<_> = messages.iter().map(|m| m.personalizations.len()).collect();
+        assert_eq!(sizes, vec![1000, 1000, 1]);
+        let all: Vec<_> = messages
+            .iter()
+            .flat_map(|m| ids(&m.personalizations))
+            .collect();
+        assert_eq!(all, (0..2001).collect::<Vec<_>>());
+        assert!(messages
+            .iter()
+            .all(|m| m.batch_id.as_deref() == Some("template")));
+    }
+
+    #[test]
+    fn splits_on_recipient_count() {
+        let recipients = [600, 300, 100, 1, 1500, 2, 999];
+        let messages: Vec<_> = BatcherBuilder::new(template())
+            .batch_id("shared")
+            .max_personalizations(2)
+            .build(
+                recipients
+                    .iter()
+                    .enumerate()
+                    .map(|(id, &n)| personalization(id, n)),
+            )
+            .collect();
+
+        let chunks: Vec<_> = messages.iter().map(|m| ids(&m.personalizations)).collect();
+        assert_eq!(
+            chunks,
+            vec![vec![0, 1], vec![2, 3], vec![4], vec![5], vec![6]]
+        );
+        assert!(messages[2].validate().is_err());
+        assert!(messages
+            .iter()
+            .all(|m| m.batch_id.as_deref() == Some("shared")));
+    }
+}
@@ -0,0 +1,390 @@
+//! A `Client` holds a SendGrid API key and base URL and sends a
+//! [Message](../message/struct.Message.html) through a
+//! [Transport](../transport/trait.Transport.html) or
+//! [AsyncTransport](../transport/trait.AsyncTransport.html).
+//!
+//! # Examples
+//! ```
+//! # use sendgrid_rs::client::ClientBuilder;
+//! # use sendgrid_rs::transport::{Request, Response, Transport};
+//! # use sendgrid_rs::{ContactBuilder, MessageBuilder};
+//! // A transport that accepts everything, in place of a real HTTP library
+//! struct Accept;
+//!
+//! impl Transport for Accept {
+//!     type Error = std::io::Error;
+//!
+//!     fn send(&self, _request: &Request) -> Result<Response, Self::Error> {
+//!         Ok(Response::new(202, ""))
+//!     }
+//! }
+//!
+//! let client = ClientBuilder::new("SENDGRID SECRET API KEY", Accept).build();
+//! let message = MessageBuilder::new(
+//!     ContactBuilder::new("from@example.com").build(),
+//!     "Subject Line!",
+//! )
+//! .build();
+//! client.send(&message).unwrap();
+//! ```
+//!
+//! Other API calls, such as those in the [batch](../batch/index.html) module, implement
+//! `Endpoint` and are made with `Client::call`.
+
+use crate::error::SendGridError;
+use crate::message::Message;
+use crate::transport::{AsyncTransport, Method, Request, Response, Transport};
+use serde::de::DeserializeOwned;
+use std::error;
+use std::fmt;
+
+/// The base URL of SendGrid's API, used unless another is set on the `ClientBuilder`
+pub const DEFAULT_BASE_URL: &str = "https://api.sendgrid.com";
+
+/// The path the mail send endpoint is found at, relative to the base URL
+pub const MAIL_SEND_PATH: &str = "/v3/mail/send";
+
+/// The error returned by `Client` when sending fails
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The transport could not complete the request
+    Transport(E),
+    /// SendGrid responded with a non 2xx status code
+    Api(SendGridError),
+    /// SendGrid responded with a 2xx status code, but the body is not what the `Endpoint` expects
+    Parse(serde_json::Error),
+}
+
+impl<E: fmt::Display> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Transport(e) => write!(f, "transport error: {}", e),
+            Error::Api(e) => write!(f, "{}", e),
+            Error::Parse(e) => write!(f, "unexpected response body: {}", e),
+        }
+    }
+}
+
+impl<E: error::Error + 'static> error::Error for Error<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Transport(e) => Some(e),
+            Error::Api(e) => Some(e),
+            Error::Parse(e) => Some(e),
+        }
+    }
+}
+
+/// A SendGrid API call that `Client::call` can make, other than mail send
+pub trait Endpoint {
+    /// What a successful response's JSON body is parsed into. An empty body parses as JSON
+    /// `null`, so endpoints without one use `()`.
+    type Output: DeserializeOwned;
+
+    /// The HTTP method of the call
+    fn method(&self) -> Method;
+
+    /// The path of the call relative to the base URL, including any query string
+    fn path(&self) -> String;
+
+    /// The JSON body of the call, if it has one
+    fn body(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Percent-encodes `segment` for use as a single segment of a URL path
+pub(crate) fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => {
+                encoded.push(char::from(byte))
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Sends requests to SendGrid's API through a transport. Use `ClientBuilder` to construct this.
+pub struct Client<T> {
+    api_key: String,
+    base_url: String,
+    transport: T,
+}
+
+impl<T> Client<T> {
+    /// The base URL requests are sent to
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// The transport requests are sent through
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Builds an authorized `Request` for `path`, which is relative to the base URL
+    pub(crate) fn request(&self, method: Method, path: &str) -> Request {
+        Request::new(method, format!("{}{}", self.base_url, path))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+    }
+
+    fn endpoint_request<E: Endpoint>(&self, endpoint: &E) -> Request {
+        let request = self.request(endpoint.method(), &endpoint.path());
+        match endpoint.body() {
+            Some(body) => request
+                .header("Content-Type", "application/json")
+                .body(body),
+            None => request,
+        }
+    }
+
+    fn mail_send_request(&self, message: &Message) -> Request {
+        self.request(Method::Post, MAIL_SEND_PATH)
+            .header("Content-Type", "application/json")
+            .body(message.to_json())
+    }
+}
+
+fn check_status<E>(response: Response) -> Result<Response, Error<E>> {
+    if response.is_success() {
+        Ok(response)
+    } else {
+        Err(Error::Api(SendGridError::from_response(&response)))
+    }
+}
+
+fn parse_output<O: DeserializeOwned, E>(response: Response) -> Result<O, Error<E>> {
+    let response = check_status(response)?;
+    let body = match response.body().trim() {
+        "" => "null",
+        body => body,
+    };
+    serde_json::from_str(body).map_err(Error::Parse)
+}
+
+impl<T: Transport> Client<T> {
+    /// Sends the `Message` to SendGrid's mail send endpoint. A 2xx response is returned as is,
+    /// anything else is an `Error::Api`.
+    pub fn send(&self, message: &Message) -> Result<Response, Error<T::Error>> {
+        let response = self
+            .transport
+            .send(&self.mail_send_request(message))
+            .map_err(Error::Transport)?;
+        check_status(response)
+    }
+
+    /// Makes an API call, returning its parsed output if SendGrid responds with a 2xx status.
+    pub fn call<E: Endpoint>(&self, endpoint: &E) -> Result<E::Output, Error<T::Error>> {
+        let response = self
+            .transport
+            .send(&self.endpoint_request(endpoint))
+            .map_err(Error::Transport)?;
+        parse_output(response)
+    }
+}
+
+impl<T: AsyncTransport> Client<T> {
+    /// Sends the `Message` to SendGrid's mail send endpoint. A 2xx response is returned as is,
+    /// anything else is an `Error::Api`.
+    pub async fn send_async(&self, message: &Message) -> Result<Response, Error<T::Error>> {
+        let request = self.mail_send_request(message);
+        let response = self
+            .transport
+            .send(&request)
+            .await
+            .map_err(Error::Transport)?;
+        check_status(response)
+    }
+
+    /// Makes an API call, returning its parsed output if SendGrid responds with a 2xx status.
+    pub async fn call_async<E: Endpoint>(
+        &self,
+        endpoint: &E,
+    ) -> Result<E::Output, Error<T::Error>> {
+        let request = self.endpoint_request(endpoint);
+        let response = self
+            .transport
+            .send(&request)
+            .await
+            .map_err(Error::Transport)?;
+        parse_output(response)
+    }
+}
+
+/// Builder pattern for `Client`. Make sure you call `build()` when you're done to consume the
+/// builder and return the underlying `Client`.
+pub struct ClientBuilder<T> {
+    client: Client<T>,
+}
+
+impl<T> ClientBuilder<T> {
+    /// Constructs a `ClientBuilder`. The API key and transport are required.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::client::ClientBuilder;
+    ///
+    /// # let transport = ();
+    /// let builder = ClientBuilder::new("SENDGRID SECRET API KEY", transport);
+    /// ```
+    pub fn new(api_key: impl Into<String>, transport: T) -> Self {
+        ClientBuilder {
+            client: Client {
+                api_key: api_key.into(),
+                base_url: String::from(DEFAULT_BASE_URL),
+                transport,
+            },
+        }
+    }
+
+    /// Sets the base URL requests are sent to, in place of `DEFAULT_BASE_URL`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::client::ClientBuilder;
+    ///
+    /// # let transport = ();
+    /// let builder = ClientBuilder::new("SENDGRID SECRET API KEY", transport)
+    ///     .base_url("http://localhost:3030");
+    /// ```
+    pub fn base_url(mut self, url: impl Into<String>) -> Self {
+        self.client.base_url = url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Consumes the builder and returns the underlying `Client`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::client::ClientBuilder;
+    ///
+    /// # let transport = ();
+    /// let client = ClientBuilder::new("SENDGRID SECRET API KEY", transport).build();
+    /// ```
+    pub fn build(self) -> Client<T> {
+        self.client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientBuilder, Error};
+    use crate::error::ErrorKind;
+    use crate::transport::{AsyncTransport, BoxFuture, Method, Request, Response, Transport};
+    use crate::{ContactBuilder, MessageBuilder};
+    use std::cell::RefCell;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    struct Recorder {
+        requests: RefCell<Vec<Request>>,
+        status: u16,
+    }
+
+    impl Transport for Recorder {
+        type Error = std::io::Error;
+
+        fn send(&self, request: &Request) -> Result<Response, Self::Error> {
+            self.requests.borrow_mut().push(request.clone());
+            Ok(Response::new(self.status, "{}")
+                .header("X-RateLimit-Limit", "600")
+                .header("X-RateLimit-Remaining", "599"))
+        }
+    }
+
+    struct Ready;
+
+    impl AsyncTransport for Ready {
+        type Error = std::io::Error;
+
+        fn send<'a>(
+            &'a self,
+            request: &'a Request,
+        ) -> BoxFuture<'a, Result<Response, Self::Error>> {
+            let status = if request.url().ends_with("/v3/mail/send") {
+                202
+            } else {
+                404
+            };
+            Box::pin(async move { Ok(Response::new(status, "")) })
+        }
+    }
+
+    // `Waker::noop` needs Rust 1.85, so the futures here are polled with a hand-written one
+    fn noop_waker() -> Waker {
+        fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(std::ptr::null(), &VTABLE)
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+        unsafe { Waker::from_raw(clone(std::ptr::null())) }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = noop_waker();
+        let mut context = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    fn message() -> crate::message::Message {
+        MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "Subject").build()
+    }
+
+    #[test]
+    fn builds_authorized_json_request() {
+        let client = ClientBuilder::new(
+            "KEY",
+            Recorder {
+                requests: RefCell::new(vec![]),
+                status: 202,
+            },
+        )
+        .base_url("http://localhost:3030/")
+        .build();
+        let message = message();
+        client.send(&message).unwrap();
+
+        let requests = client.transport().requests.borrow();
+        let request = &requests[0];
+        assert_eq!(request.method(), Method::Post);
+        assert_eq!(request.url(), "http://localhost:3030/v3/mail/send");
+        assert!(request
+            .headers()
+            .contains(&(String::from("Authorization"), String::from("Bearer KEY"))));
+        assert_eq!(request.body_str(), Some(message.to_json().as_str()));
+    }
+
+    #[test]
+    fn non_success_status_is_an_error() {
+        let transport = Recorder {
+            requests: RefCell::new(vec![]),
+            status: 400,
+        };
+        let client = ClientBuilder::new("KEY", &transport).build();
+        match client.send(&message()) {
+            Err(Error::Api(e)) => {
+                assert_eq!(e.kind(), ErrorKind::Validation);
+                assert_eq!(e.header_value("x-ratelimit-limit"), Some("600"));
+                assert_eq!(e.rate_limit().unwrap().remaining(), Some(599));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sends_async() {
+        let client = ClientBuilder::new("KEY", Ready).build();
+        let response = block_on(client.send_async(&message())).unwrap();
+        assert_eq!(response.status(), 202);
+    }
+}
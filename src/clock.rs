@@ -0,0 +1,81 @@
+//! A `Clock` is the source of the current time for anything in this crate that depends on it,
+//! so that time can be faked in tests.
+
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A source of the current time that can also wait
+pub trait Clock {
+    /// The current time
+    fn now(&self) -> SystemTime;
+
+    /// Blocks for `duration`
+    fn sleep(&self, duration: Duration);
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> SystemTime {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration)
+    }
+}
+
+/// The `Clock` backed by the operating system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// Seconds since the Unix epoch, with times before it counted as the epoch itself
+pub(crate) fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// A `Clock` for tests that starts at a fixed time and only moves when slept on, recording each
+/// sleep
+#[cfg(test)]
+pub(crate) struct FakeClock {
+    now: std::cell::Cell<SystemTime>,
+    sleeps: std::cell::RefCell<Vec<Duration>>,
+}
+
+#[cfg(test)]
+impl FakeClock {
+    /// Starts `seconds` after the Unix epoch
+    pub(crate) fn at(seconds: u64) -> Self {
+        FakeClock {
+            now: std::cell::Cell::new(UNIX_EPOCH + Duration::from_secs(seconds)),
+            sleeps: std::cell::RefCell::new(vec![]),
+        }
+    }
+
+    /// Every duration slept so far, in order
+    pub(crate) fn sleeps(&self) -> Vec<Duration> {
+        self.sleeps.borrow().clone()
+    }
+}
+
+#[cfg(test)]
+impl Clock for FakeClock {
+    fn now(&self) -> SystemTime {
+        self.now.get()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+        self.sleeps.borrow_mut().push(duration);
+    }
+}
@@ -0,0 +1,411 @@
+//! Reads a CSV file of recipients into `Personalization`s, one per row.
+//!
+//! The first row names the columns. One column holds each recipient's email address, in any form
+//! `Contact::parse` accepts, and an optional column holds their name. Every other column becomes
+//! `dynamic_template_data` keyed by the column name, or legacy substitutions if the reader is
+//! built with `substitutions()`. Fields follow RFC 4180: they may be quoted to contain commas,
+//! quotes (doubled) or line breaks, and rows may end in CRLF or LF. Blank lines are skipped.
+//!
+//! # Examples
+//! ```
+//! # use sendgrid_rs::csv::CsvReaderBuilder;
+//! let file = "email,name,plan\r\nann@example.com,Ann,pro\r\nbob@example.com,\"Smith, Bob\",free\r\n";
+//!
+//! let personalizations = CsvReaderBuilder::new("email")
+//!     .name_column("name")
+//!     .build()
+//!     .read(file.as_bytes())
+//!     .unwrap();
+//! assert_eq!(personalizations.len(), 2);
+//! ```
+
+use crate::address::AddressError;
+use crate::personalization::{Personalization, PersonalizationBuilder};
+use crate::Contact;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+
+/// The reasons a CSV file can't be read
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CsvError {
+    /// The file couldn't be read or isn't UTF-8
+    Io(io::Error),
+    /// The row starting on this line has a stray or unterminated quote
+    Syntax { line: usize },
+    /// The row starting on this line has a different number of fields than the header
+    FieldCount {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// The header has no column with this name
+    MissingColumn(String),
+    /// The email address of the row starting on this line is invalid
+    Address { line: usize, error: AddressError },
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CsvError::Io(e) => write!(f, "failed to read CSV: {}", e),
+            CsvError::Syntax { line } => write!(f, "line {}: misplaced quote", line),
+            CsvError::FieldCount {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: expected {} fields, found {}",
+                line, expected, found
+            ),
+            CsvError::MissingColumn(name) => write!(f, "no column named {:?}", name),
+            CsvError::Address { line, error } => write!(f, "line {}: {}", line, error),
+        }
+    }
+}
+
+impl Error for CsvError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CsvError::Io(e) => Some(e),
+            CsvError::Address { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CsvError {
+    fn from(error: io::Error) -> Self {
+        CsvError::Io(error)
+    }
+}
+
+/// Maps the rows of a CSV file to `Personalization`s. Use `CsvReaderBuilder` to construct this.
+#[derive(Debug, Clone)]
+pub struct CsvReader {
+    email_column: String,
+    name_column: Option<String>,
+    delimiter: char,
+    substitutions: bool,
+}
+
+/// Builder pattern for `CsvReader`. Make sure you call `build()` when done to consume the builder
+/// and return the underlying `CsvReader`.
+pub struct CsvReaderBuilder {
+    reader: CsvReader,
+}
+
+impl CsvReaderBuilder {
+    /// Constructs a `CsvReaderBuilder`. The required parameter is the name of the column holding
+    /// the email addresses.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::csv::CsvReaderBuilder;
+    ///
+    /// let builder = CsvReaderBuilder::new("email");
+    /// ```
+    pub fn new(email_column: impl Into<String>) -> Self {
+        CsvReaderBuilder {
+            reader: CsvReader {
+                email_column: email_column.into(),
+                name_column: None,
+                delimiter: ',',
+                substitutions: false,
+            },
+        }
+    }
+
+    /// Sets the column holding the recipients' names. A non-empty name overrides any display
+    /// name in the email column.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::csv::CsvReaderBuilder;
+    ///
+    /// let builder = CsvReaderBuilder::new("email").name_column("full_name");
+    /// ```
+    pub fn name_column(mut self, name_column: impl Into<String>) -> Self {
+        self.reader.name_column = Some(name_column.into());
+        self
+    }
+
+    /// Sets the character separating fields, a comma by default
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::csv::CsvReaderBuilder;
+    ///
+    /// let builder = CsvReaderBuilder::new("email").delimiter(';');
+    /// ```
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.reader.delimiter = delimiter;
+        self
+    }
+
+    /// Puts the remaining columns into substitutions instead of `dynamic_template_data`, for
+    /// legacy templates. The column names are used as the substitution keys, e.g. `-first_name-`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::csv::CsvReaderBuilder;
+    ///
+    /// let builder = CsvReaderBuilder::new("email").substitutions();
+    /// ```
+    pub fn substitutions(mut self) -> Self {
+        self.reader.substitutions = true;
+        self
+    }
+
+    /// Consumes the builder and returns the underlying `CsvReader`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::csv::CsvReaderBuilder;
+    ///
+    /// let reader = CsvReaderBuilder::new("email").build();
+    /// ```
+    pub fn build(self) -> CsvReader {
+        self.reader
+    }
+}
+
+impl CsvReader {
+    /// Reads a CSV file with a header row, returning one `Personalization` per row, in order
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::csv::{CsvError, CsvReaderBuilder};
+    /// let reader = CsvReaderBuilder::new("email").substitutions().build();
+    ///
+    /// let file = "email;-code-\nAnn <ann@example.com>;A1\n";
+    /// let personalizations = reader.read(file.as_bytes());
+    /// assert!(matches!(personalizations, Err(CsvError::MissingColumn(_))));
+    ///
+    /// let reader = CsvReaderBuilder::new("email").delimiter(';').substitutions().build();
+    /// let personalizations = reader.read(file.as_bytes()).unwrap();
+    /// assert_eq!(personalizations.len(), 1);
+    /// ```
+    pub fn read(&self, mut input: impl Read) -> Result<Vec<Personalization>, CsvError> {
+        let mut text = String::new();
+        input.read_to_string(&mut text)?;
+        let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+
+        let mut rows = records(text, self.delimiter)?.into_iter();
+        let header: Vec<String> = match rows.next() {
+            Some((_, header)) => header.iter().map(|h| h.trim().to_string()).collect(),
+            None => return Err(CsvError::MissingColumn(self.email_column.clone())),
+        };
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| CsvError::MissingColumn(name.to_string()))
+        };
+        let email = column(&self.email_column)?;
+        let name = self.name_column.as_deref().map(column).transpose()?;
+
+        rows.map(|(line, row)| {
+            if row.len() != header.len() {
+                return Err(CsvError::FieldCount {
+                    line,
+                    expected: header.len(),
+                    found: row.len(),
+                });
+            }
+            let mut contact = Contact::parse(row[email].trim())
+                .map_err(|error| CsvError::Address { line, error })?;
+            if let Some(name) = name.map(|i| row[i].trim()).filter(|n| !n.is_empty()) {
+                contact.name = Some(name.to_string());
+            }
+
+            let mut builder = PersonalizationBuilder::default().to(contact);
+            for (i, (key, value)) in header.iter().zip(row).enumerate() {
+                if i == email || Some(i) == name {
+                    continue;
+                }
+                builder = if self.substitutions {
+                    builder.substitution(key.clone(), value)
+                } else {
+                    builder.dynamic_template_datum(key.clone(), value)
+                };
+            }
+            Ok(builder.build())
+        })
+        .collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    /// At the start of a field
+    Start,
+    Unquoted,
+    Quoted,
+    /// Past the closing quote of a quoted field
+    Closed,
+}
+
+/// Splits `text` into rows of fields, each with the line it starts on
+fn records(text: &str, delimiter: char) -> Result<Vec<(usize, Vec<String>)>, CsvError> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut state = State::Start;
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = text.chars().peekable();
+    loop {
+        let c = chars.next();
+        match (state, c) {
+            (State::Quoted, None) => return Err(CsvError::Syntax { line: start }),
+            (State::Quoted, Some('"')) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (State::Quoted, Some('"')) => state = State::Closed,
+            (State::Quoted, Some(c)) => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+            (State::Start, Some('"')) => state = State::Quoted,
+            (_, Some('\r')) if chars.peek() == Some(&'\n') => {}
+            (_, Some(c)) if c == delimiter => {
+                record.push(std::mem::take(&mut field));
+                state = State::Start;
+            }
+            (_, Some('\n')) | (_, None) => {
+                let blank = record.is_empty() && state == State::Start;
+                if !blank {
+                    record.push(std::mem::take(&mut field));
+                    records.push((start, std::mem::take(&mut record)));
+                }
+                if c.is_none() {
+                    return Ok(records);
+                }
+                line += 1;
+                start = line;
+                state = State::Start;
+            }
+            (State::Closed, Some(_)) | (State::Unquoted, Some('"')) => {
+                return Err(CsvError::Syntax { line: start })
+            }
+            (_, Some(c)) => {
+                field.push(c);
+                state = State::Unquoted;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{records, CsvError, CsvReaderBuilder};
+    use crate::address::AddressError;
+    use serde_json::json;
+
+    #[test]
+    fn splits_records() {
+        let text = "a,\"b,\"\"c\"\"\",\r\n\n\"multi\nline\",,\"\"\nlast";
+        assert_eq!(
+            records(text, ',').unwrap(),
+            vec![
+                (1, vec!["a".into(), "b,\"c\"".into(), "".into()]),
+                (3, vec!["multi\nline".into(), "".into(), "".into()]),
+                (5, vec!["last".into()]),
+            ]
+        );
+        assert!(matches!(
+            records("a\n\"b\nc", ','),
+            Err(CsvError::Syntax { line: 2 })
+        ));
+        assert!(matches!(
+            records("a\n\"b\"c", ','),
+            Err(CsvError::Syntax { line: 2 })
+        ));
+        assert!(matches!(
+            records("a\nb\"c", ','),
+            Err(CsvError::Syntax { line: 2 })
+        ));
+    }
+
+    #[test]
+    fn maps_rows_to_personalizations() {
+        let file = "\u{feff}Email , Name,plan,seats\n\
+                    \"\"\"Lee, Ann\"\" <ann@EXAMPLE.com>\",,pro,3\n\
+                    bob@example.com,\"Smith, Bob\",free,\n";
+        let personalizations = CsvReaderBuilder::new("Email")
+            .name_column("Name")
+            .build()
+            .read(file.as_bytes())
+            .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&personalizations).unwrap(),
+            json!([
+                {
+                    "to": [{"email": "ann@example.com", "name": "Lee, Ann"}],
+                    "dynamic_template_data": {"plan": "pro", "seats": "3"},
+                },
+                {
+                    "to": [{"email": "bob@example.com", "name": "Smith, Bob"}],
+                    "dynamic_template_data": {"plan": "free", "seats": ""},
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn maps_columns_to_substitutions() {
+        let file = "-plan-,address\nPro,ann@example.com\n";
+        let personalizations = CsvReaderBuilder::new("address")
+            .substitutions()
+            .build()
+            .read(file.as_bytes())
+            .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&personalizations).unwrap(),
+            json!([{
+                "to": [{"email": "ann@example.com", "name": null}],
+                "substitutions": {"-plan-": "Pro"},
+            }])
+        );
+    }
+
+    #[test]
+    fn reports_bad_rows() {
+        let reader = CsvReaderBuilder::new("email").name_column("name").build();
+        assert!(matches!(
+            reader.read("email\na@example.com\n".as_bytes()),
+            Err(CsvError::MissingColumn(column)) if column == "name"
+        ));
+        assert!(matches!(
+            reader.read("".as_bytes()),
+            Err(CsvError::MissingColumn(column)) if column == "email"
+        ));
+        assert!(matches!(
+            reader.read("email,name\na@example.com,A\n\nb@example.com\n".as_bytes()),
+            Err(CsvError::FieldCount {
+                line: 4,
+                expected: 2,
+                found: 1
+            })
+        ));
+        assert!(matches!(
+            reader.read("email,name\nnobody,N\n".as_bytes()),
+            Err(CsvError::Address {
+                line: 2,
+                error: AddressError::MissingAt
+            })
+        ));
+        assert!(matches!(
+            reader.read(&[b'e', 0xff][..]),
+            Err(CsvError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,344 @@
+//! Types for the error responses returned by SendGrid's API. When a request fails SendGrid
+//! responds with a body like `{"errors":[{"message":"...","field":"...","help":"..."}]}`, which
+//! is parsed into a `SendGridError` along with a classification of the status code.
+
+use crate::message::Message;
+use crate::retry::RateLimit;
+use crate::transport::Response;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+
+/// The broad category of a failed request, derived from the HTTP status code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// 401 or 403, the API key is missing, invalid or lacks the required scope
+    Auth,
+    /// 400, the request body was rejected
+    Validation,
+    /// 413, the request body is too large
+    PayloadTooLarge,
+    /// 429, too many requests have been made
+    RateLimited,
+    /// 5xx, SendGrid failed to handle the request
+    Server,
+    /// Any other non 2xx status code
+    Other,
+}
+
+impl ErrorKind {
+    /// Classifies an HTTP status code
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::error::ErrorKind;
+    ///
+    /// assert_eq!(ErrorKind::from_status(429), ErrorKind::RateLimited);
+    /// ```
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            400 => ErrorKind::Validation,
+            401 | 403 => ErrorKind::Auth,
+            413 => ErrorKind::PayloadTooLarge,
+            429 => ErrorKind::RateLimited,
+            500..=599 => ErrorKind::Server,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// A single entry of the `errors` array in a SendGrid error response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiError {
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    field: Option<String>,
+    #[serde(default)]
+    help: Option<Value>,
+}
+
+impl ApiError {
+    /// The description of what went wrong
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The field of the request the error refers to, in SendGrid's dotted form
+    /// (e.g. `personalizations.0.to.0.email`)
+    pub fn field(&self) -> Option<&str> {
+        self.field.as_deref()
+    }
+
+    /// Help text or a link to the relevant documentation. This is usually a string, but SendGrid
+    /// does not guarantee it.
+    pub fn help(&self) -> Option<&Value> {
+        self.help.as_ref()
+    }
+
+    /// The field converted to the path format used by
+    /// [ValidationError](../validation/struct.ValidationError.html)
+    /// (e.g. `personalizations[0].to[0].email`)
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::error::SendGridError;
+    ///
+    /// let error = SendGridError::new(
+    ///     400,
+    ///     r#"{"errors":[{"message":"invalid","field":"personalizations.0.to.1.email"}]}"#,
+    /// );
+    /// assert_eq!(
+    ///     error.errors()[0].field_path().as_deref(),
+    ///     Some("personalizations[0].to[1].email")
+    /// );
+    /// ```
+    pub fn field_path(&self) -> Option<String> {
+        let field = self.field.as_deref()?;
+        let mut path = String::new();
+        for segment in segments(field) {
+            if segment.parse::<usize>().is_ok() {
+                path.push_str(&format!("[{}]", segment));
+            } else {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(segment);
+            }
+        }
+        Some(path)
+    }
+
+    /// Finds the part of the `Message` this error refers to, returned as the JSON that was sent
+    /// for that field. Returns `None` if the error has no field or the field is not present in
+    /// the `Message`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::error::SendGridError;
+    /// # use sendgrid_rs::{ContactBuilder, MessageBuilder, PersonalizationBuilder};
+    ///
+    /// let message = MessageBuilder::new(
+    ///         ContactBuilder::new("from@example.com").build(),
+    ///         "Subject Line"
+    ///     )
+    ///     .personalization(
+    ///         PersonalizationBuilder::default()
+    ///             .to(ContactBuilder::new("not-an-email").build())
+    ///             .build()
+    ///     )
+    ///     .build();
+    /// let error = SendGridError::new(
+    ///     400,
+    ///     r#"{"errors":[{"message":"invalid","field":"personalizations.0.to.0.email"}]}"#,
+    /// );
+    /// assert_eq!(error.errors()[0].locate(&message), Some("not-an-email".into()));
+    /// ```
+    pub fn locate(&self, message: &Message) -> Option<Value> {
+        let field = self.field.as_deref()?;
+        let mut value = serde_json::to_value(message).ok()?;
+        for segment in segments(field) {
+            value = match value {
+                Value::Object(mut map) => map.remove(segment)?,
+                Value::Array(mut items) => {
+                    let index = segment.parse::<usize>().ok()?;
+                    if index >= items.len() {
+                        return None;
+                    }
+                    items.swap_remove(index)
+                }
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+}
+
+/// Splits a field in either dotted (`a.0.b`) or bracketed (`a[0].b`) form into its segments
+fn segments(field: &str) -> impl Iterator<Item = &str> {
+    field
+        .split(['.', '[', ']'])
+        .filter(|segment| !segment.is_empty())
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    #[serde(default)]
+    errors: Vec<ApiError>,
+}
+
+/// An error response from SendGrid's API
+#[derive(Debug, Clone, PartialEq)]
+pub struct SendGridError {
+    status: u16,
+    kind: ErrorKind,
+    errors: Vec<ApiError>,
+    body: String,
+    headers: Vec<(String, String)>,
+}
+
+impl SendGridError {
+    /// Constructs a `SendGridError` from a status code and response body. A body that is not a
+    /// SendGrid error document results in an empty `errors()`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::error::{ErrorKind, SendGridError};
+    ///
+    /// let error = SendGridError::new(401, r#"{"errors":[{"message":"bad key"}]}"#);
+    /// assert_eq!(error.kind(), ErrorKind::Auth);
+    /// assert_eq!(error.errors()[0].message(), "bad key");
+    /// ```
+    pub fn new(status: u16, body: impl Into<String>) -> Self {
+        let body = body.into();
+        let errors = serde_json::from_str::<ErrorBody>(&body)
+            .map(|b| b.errors)
+            .unwrap_or_default();
+        SendGridError {
+            status,
+            kind: ErrorKind::from_status(status),
+            errors,
+            body,
+            headers: vec![],
+        }
+    }
+
+    /// Constructs a `SendGridError` from a `Response` returned by a transport, keeping its
+    /// headers
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::error::SendGridError;
+    /// # use sendgrid_rs::transport::Response;
+    ///
+    /// let response = Response::new(429, "")
+    ///     .header("X-RateLimit-Remaining", "0")
+    ///     .header("X-Request-Id", "abc123");
+    /// let error = SendGridError::from_response(&response);
+    /// assert_eq!(error.header_value("x-request-id"), Some("abc123"));
+    /// assert_eq!(error.rate_limit().unwrap().remaining(), Some(0));
+    /// ```
+    pub fn from_response(response: &Response) -> Self {
+        SendGridError {
+            headers: response.headers().to_vec(),
+            ..SendGridError::new(response.status(), response.body())
+        }
+    }
+
+    /// The HTTP status code
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The classification of the status code
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The individual errors SendGrid reported
+    pub fn errors(&self) -> &[ApiError] {
+        &self.errors
+    }
+
+    /// The raw response body
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    /// All response headers, as name/value pairs. Empty unless constructed with
+    /// `from_response`.
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Looks up a response header by name, ignoring case
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The rate limit state reported in the response headers, if any
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        RateLimit::from_headers(&self.headers)
+    }
+}
+
+impl fmt::Display for SendGridError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SendGrid responded with status {}", self.status)?;
+        for (i, error) in self.errors.iter().enumerate() {
+            f.write_str(if i == 0 { ": " } else { "; " })?;
+            match &error.field {
+                Some(field) => write!(f, "{}: {}", field, error.message)?,
+                None => f.write_str(&error.message)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Error for SendGridError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrorKind, SendGridError};
+
+    #[test]
+    fn classifies_status_codes() {
+        let kinds: Vec<_> = [400, 401, 403, 413, 429, 500, 503, 404]
+            .iter()
+            .map(|s| ErrorKind::from_status(*s))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ErrorKind::Validation,
+                ErrorKind::Auth,
+                ErrorKind::Auth,
+                ErrorKind::PayloadTooLarge,
+                ErrorKind::RateLimited,
+                ErrorKind::Server,
+                ErrorKind::Server,
+                ErrorKind::Other,
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_error_body() {
+        let error = SendGridError::new(
+            400,
+            r#"{"errors":[
+                {"message":"The from email does not contain a valid address.","field":"from.email","help":"http://sendgrid.com/docs"},
+                {"message":"Bad Request","field":null,"help":{"url":"http://sendgrid.com"}}
+            ]}"#,
+        );
+        assert_eq!(error.errors().len(), 2);
+        assert_eq!(error.errors()[0].field(), Some("from.email"));
+        assert_eq!(
+            error.errors()[0].field_path().as_deref(),
+            Some("from.email")
+        );
+        assert_eq!(
+            error.errors()[0].help().and_then(|h| h.as_str()),
+            Some("http://sendgrid.com/docs")
+        );
+        assert_eq!(error.errors()[1].field(), None);
+        assert_eq!(
+            error.to_string(),
+            "SendGrid responded with status 400: from.email: The from email does not contain a \
+             valid address.; Bad Request"
+        );
+    }
+
+    #[test]
+    fn tolerates_non_json_body() {
+        let error = SendGridError::new(502, "<html>Bad Gateway</html>");
+        assert_eq!(error.kind(), ErrorKind::Server);
+        assert!(error.errors().is_empty());
+        assert_eq!(error.body(), "<html>Bad Gateway</html>");
+    }
+}
@@ -0,0 +1,100 @@
+//! A small, forgiving scanner for HTML tags, shared by the modules that read or rewrite HTML
+//! `Content`. It only understands enough of HTML to find where a tag ends and what attributes it
+//! has, honouring quoted attribute values that contain `>`.
+
+use std::ops::Range;
+
+/// The length of the tag `html` starts with, up to and including its `>`, skipping quoted
+/// attribute values
+pub(crate) fn tag_end(html: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in html.char_indices().skip(1) {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return Some(i + 1),
+            (None, '<') => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The lowercased names of the attributes of `tag`, each with the byte range of its value within
+/// `tag`, excluding quotes
+pub(crate) fn attributes(tag: &str) -> Vec<(String, Option<Range<usize>>)> {
+    let bytes = tag.as_bytes();
+    let skip = |mut at: usize, stop: &dyn Fn(u8) -> bool| {
+        while at < bytes.len() && !stop(bytes[at]) {
+            at += 1;
+        }
+        at
+    };
+    let mut attributes = vec![];
+    // Skips the tag name
+    let mut at = skip(1, &|b| b.is_ascii_whitespace() || b == b'/' || b == b'>');
+    loop {
+        at = skip(at, &|b| !b.is_ascii_whitespace() && b != b'/');
+        if at >= bytes.len() || bytes[at] == b'>' {
+            return attributes;
+        }
+        let start = at;
+        at = skip(at + 1, &|b| {
+            b.is_ascii_whitespace() || b == b'/' || b == b'>' || b == b'='
+        });
+        let name = tag[start..at].to_ascii_lowercase();
+        let eq = skip(at, &|b| !b.is_ascii_whitespace());
+        if bytes.get(eq) != Some(&b'=') {
+            attributes.push((name, None));
+            continue;
+        }
+        at = skip(eq + 1, &|b| !b.is_ascii_whitespace());
+        let value = match bytes.get(at) {
+            Some(&quote) if quote == b'"' || quote == b'\'' => {
+                let end = skip(at + 1, &|b| b == quote);
+                let value = at + 1..end;
+                at = end + 1;
+                value
+            }
+            _ => {
+                let end = skip(at, &|b| b.is_ascii_whitespace() || b == b'>');
+                let value = at..end;
+                at = end;
+                value
+            }
+        };
+        attributes.push((name, Some(value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{attributes, tag_end};
+
+    #[test]
+    fn ends_tags_outside_quotes() {
+        assert_eq!(tag_end(r#"<img alt="a>b" src='c>d'>rest"#), Some(25));
+        assert_eq!(tag_end("<p>"), Some(3));
+        assert_eq!(tag_end("< 1 < 2 >"), None);
+        assert_eq!(tag_end(r#"<p title="unterminated>"#), None);
+    }
+
+    #[test]
+    fn parses_attributes() {
+        let tag = r#"<IMG alt="a>b" SRC = 'logo.png' width=10 hidden/>"#;
+        let found: Vec<_> = attributes(tag)
+            .into_iter()
+            .map(|(name, value)| (name, value.map(|range| &tag[range])))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (String::from("alt"), Some("a>b")),
+                (String::from("src"), Some("logo.png")),
+                (String::from("width"), Some("10")),
+                (String::from("hidden"), None),
+            ]
+        );
+        assert!(attributes("<br>").is_empty());
+    }
+}
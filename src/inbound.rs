@@ -0,0 +1,945 @@
+//! Parses the emails SendGrid's Inbound Parse webhook POSTs as `multipart/form-data`.
+//!
+//! By default SendGrid splits each email into fields: the raw `headers`, the `from`, `to`, `cc`
+//! and `subject`, the `text` and `html` bodies and one field per attachment, described by
+//! `attachment-info`. The text fields keep the email's own encodings, which SendGrid declares in
+//! `charsets`, and are decoded accordingly. With "POST the raw, full MIME message" enabled, the
+//! whole email arrives in the `email` field instead and is decoded here, including transfer
+//! encodings, per-part charsets and RFC 2047 encoded headers. Either way the result is an
+//! `InboundEmail`.
+//!
+//! # Examples
+//! ```
+//! # use sendgrid_rs::inbound::InboundEmail;
+//! let body = "--xYzZY\r\n\
+//!     Content-Disposition: form-data; name=\"headers\"\r\n\r\n\
+//!     From: Ann <ann@example.com>\r\nTo: support@example.com\r\nSubject: Help\r\n\
+//!     \r\n--xYzZY\r\n\
+//!     Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+//!     My order hasn't arrived.\r\n\
+//!     --xYzZY--\r\n";
+//!
+//! let email = InboundEmail::parse("multipart/form-data; boundary=xYzZY", body.as_bytes()).unwrap();
+//! assert_eq!(email.from().unwrap().email(), "ann@example.com");
+//! assert_eq!(email.subject(), "Help");
+//! assert_eq!(email.text(), Some("My order hasn't arrived."));
+//! ```
+
+use crate::address;
+use crate::attachment::{Attachment, AttachmentBuilder};
+use crate::Contact;
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+use encoding_rs::{Encoding, UTF_8};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// How deeply multipart entities of a raw email are followed
+const MAX_DEPTH: usize = 16;
+
+/// Base64 as found in emails, where the padding is often left out
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// The reasons an Inbound Parse request can't be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InboundError {
+    /// The request isn't `multipart/form-data` with a boundary
+    NotFormData,
+    /// The request has neither the `headers` nor the raw `email` field
+    MissingField(String),
+    /// This JSON field, e.g. `envelope`, can't be parsed
+    InvalidField(String),
+}
+
+impl fmt::Display for InboundError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InboundError::NotFormData => write!(f, "request is not multipart/form-data"),
+            InboundError::MissingField(name) => write!(f, "missing field {}", name),
+            InboundError::InvalidField(name) => write!(f, "invalid field {}", name),
+        }
+    }
+}
+
+impl Error for InboundError {}
+
+/// The SMTP envelope of an `InboundEmail`, which may differ from its headers, e.g. for bcc
+/// recipients
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Envelope {
+    #[serde(default)]
+    from: Option<String>,
+    #[serde(default)]
+    to: Vec<String>,
+}
+
+impl Envelope {
+    /// The address the email was sent from
+    pub fn from(&self) -> Option<&str> {
+        self.from.as_deref()
+    }
+
+    /// The addresses the email was delivered to
+    pub fn to(&self) -> &[String] {
+        &self.to
+    }
+}
+
+/// A file attached to an `InboundEmail`, decoded into its raw bytes
+#[derive(Debug, Clone, PartialEq)]
+pub struct InboundAttachment {
+    filename: String,
+    content_type: String,
+    content_id: Option<String>,
+    content: Vec<u8>,
+}
+
+impl InboundAttachment {
+    /// The filename, or `attachment` followed by its position if the email didn't name it
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// The mime type, e.g. `image/png`
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    /// The content id HTML parts refer to an inline attachment by, without angle brackets
+    pub fn content_id(&self) -> Option<&str> {
+        self.content_id.as_deref()
+    }
+
+    /// The decoded content
+    pub fn content(&self) -> &[u8] {
+        &self.content
+    }
+
+    /// Converts this into an `Attachment`, e.g. to forward it in a `Message`. Attachments with a
+    /// content id stay inline.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::inbound::InboundEmail;
+    /// let raw = b"Content-Type: application/pdf; name=\"invoice.pdf\"\r\n\
+    ///     Content-Transfer-Encoding: base64\r\n\r\nJVBERi0=\r\n";
+    ///
+    /// let email = InboundEmail::parse_mime(raw);
+    /// let attachment = email.attachments()[0].to_attachment();
+    /// ```
+    pub fn to_attachment(&self) -> Attachment {
+        let builder = AttachmentBuilder::from_bytes(&self.content, self.filename.as_str())
+            .attachment_type(self.content_type.as_str());
+        match &self.content_id {
+            Some(id) => builder.disposition("inline").content_id(id.as_str()),
+            None => builder.disposition("attachment"),
+        }
+        .build()
+    }
+}
+
+/// An email received through Inbound Parse
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct InboundEmail {
+    headers: Vec<(String, String)>,
+    from: Option<Contact>,
+    to: Vec<Contact>,
+    cc: Vec<Contact>,
+    subject: String,
+    text: Option<String>,
+    html: Option<String>,
+    attachments: Vec<InboundAttachment>,
+    envelope: Option<Envelope>,
+    dkim: Option<String>,
+    spf: Option<String>,
+    sender_ip: Option<String>,
+    spam_score: Option<f64>,
+    spam_report: Option<String>,
+}
+
+impl InboundEmail {
+    /// Parses an Inbound Parse request from its `Content-Type` header and body, in either the
+    /// default or the raw mode.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::inbound::{InboundEmail, InboundError};
+    /// let body = b"--b\r\nContent-Disposition: form-data; name=\"email\"\r\n\r\n\
+    ///     From: ann@example.com\r\nSubject: =?UTF-8?Q?Caf=C3=A9?=\r\n\r\nHi\r\n--b--";
+    ///
+    /// let email = InboundEmail::parse("multipart/form-data; boundary=b", body).unwrap();
+    /// assert_eq!(email.subject(), "Café");
+    ///
+    /// assert_eq!(
+    ///     InboundEmail::parse("application/json", b"{}"),
+    ///     Err(InboundError::NotFormData)
+    /// );
+    /// ```
+    pub fn parse(content_type: &str, body: &[u8]) -> Result<InboundEmail, InboundError> {
+        let (media_type, params) = parse_params(content_type);
+        let boundary = match param(&params, "boundary") {
+            Some(boundary) if media_type == "multipart/form-data" => boundary,
+            _ => return Err(InboundError::NotFormData),
+        };
+        let fields: Vec<FormField> = split_multipart(body, boundary)
+            .into_iter()
+            .filter_map(FormField::parse)
+            .collect();
+        let field = |name: &str| form_field(&fields, name);
+        let charsets: HashMap<String, String> =
+            json_field(&fields, "charsets")?.unwrap_or_default();
+        let text = |name: &str| {
+            field(name).map(|f| decode_text(f.content, charsets.get(name).map(String::as_str)))
+        };
+
+        let mut email = match field("email") {
+            Some(raw) => InboundEmail::parse_mime(raw.content),
+            None => {
+                let headers = field("headers")
+                    .ok_or_else(|| InboundError::MissingField(String::from("headers")))?;
+                let mut email = InboundEmail::from_headers(parse_headers(headers.content));
+                // SendGrid decodes these from the headers itself, into the declared charsets
+                if let Some(subject) = text("subject") {
+                    email.subject = subject;
+                }
+                if let Some(from) = text("from") {
+                    email.from = contacts(&from).into_iter().next();
+                }
+                if let Some(to) = text("to") {
+                    email.to = contacts(&to);
+                }
+                if let Some(cc) = text("cc") {
+                    email.cc = contacts(&cc);
+                }
+                email.text = text("text");
+                email.html = text("html");
+
+                let info: HashMap<String, AttachmentInfo> =
+                    json_field(&fields, "attachment-info")?.unwrap_or_default();
+                for file in fields.iter().filter(|f| f.filename.is_some()) {
+                    let info = info.get(&file.name);
+                    email.attachments.push(InboundAttachment {
+                        filename: info
+                            .and_then(|i| i.filename.clone())
+                            .or_else(|| file.filename.clone())
+                            .unwrap_or_default(),
+                        content_type: info
+                            .and_then(|i| i.a_type.clone())
+                            .or_else(|| file.content_type.clone())
+                            .unwrap_or_else(|| String::from("application/octet-stream")),
+                        content_id: info
+                            .and_then(|i| i.content_id.as_deref())
+                            .map(trim_angle_brackets),
+                        content: file.content.to_vec(),
+                    });
+                }
+                email
+            }
+        };
+
+        email.envelope = json_field(&fields, "envelope")?;
+        email.dkim = text("dkim");
+        email.spf = text("SPF");
+        email.sender_ip = text("sender_ip");
+        email.spam_score = text("spam_score").and_then(|s| s.trim().parse().ok());
+        email.spam_report = text("spam_report");
+        Ok(email)
+    }
+
+    /// Parses a raw MIME email, as posted in the `email` field in raw mode. Parts that can't be
+    /// decoded are kept as they are rather than rejected.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::inbound::InboundEmail;
+    /// let raw = b"From: \"Ann\" <ann@example.com>\r\n\
+    ///     Content-Type: text/plain; charset=iso-8859-1\r\n\
+    ///     Content-Transfer-Encoding: quoted-printable\r\n\r\n\
+    ///     Gr=FC=DFe\r\n";
+    ///
+    /// let email = InboundEmail::parse_mime(raw);
+    /// assert_eq!(email.from().unwrap().name(), Some("Ann"));
+    /// assert_eq!(email.text(), Some("Grüße\r\n"));
+    /// ```
+    pub fn parse_mime(raw: &[u8]) -> InboundEmail {
+        let (head, body) = split_headers(raw);
+        let mut email = InboundEmail::from_headers(parse_headers(head));
+        let headers = std::mem::take(&mut email.headers);
+        email.read_entity(&headers, body, 0);
+        email.headers = headers;
+        email
+    }
+
+    fn from_headers(headers: Vec<(String, String)>) -> InboundEmail {
+        let list = |name| header(&headers, name).map(contacts).unwrap_or_default();
+        InboundEmail {
+            from: list("from").into_iter().next(),
+            to: list("to"),
+            cc: list("cc"),
+            subject: header(&headers, "subject").unwrap_or_default().to_string(),
+            headers,
+            ..InboundEmail::default()
+        }
+    }
+
+    /// Reads the text, html and attachments of a MIME entity into this email
+    fn read_entity(&mut self, headers: &[(String, String)], body: &[u8], depth: usize) {
+        let (media_type, params) = parse_params(header(headers, "content-type").unwrap_or(""));
+        if media_type.starts_with("multipart/") {
+            if let Some(boundary) = param(&params, "boundary").filter(|_| depth < MAX_DEPTH) {
+                for part in split_multipart(body, boundary) {
+                    let (head, body) = split_headers(part);
+                    self.read_entity(&parse_headers(head), body, depth + 1);
+                }
+                return;
+            }
+        }
+
+        let content = decode_transfer(header(headers, "content-transfer-encoding"), body);
+        let (disposition, disposition_params) =
+            parse_params(header(headers, "content-disposition").unwrap_or(""));
+        let filename = param(&disposition_params, "filename").or_else(|| param(&params, "name"));
+        let is_attachment = disposition == "attachment" || filename.is_some();
+        let charset = param(&params, "charset");
+        match media_type.as_str() {
+            "" | "text/plain" if !is_attachment && self.text.is_none() => {
+                self.text = Some(decode_text(&content, charset));
+            }
+            "text/html" if !is_attachment && self.html.is_none() => {
+                self.html = Some(decode_text(&content, charset));
+            }
+            _ => {
+                let filename = filename
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("attachment{}", self.attachments.len() + 1));
+                let content_type = match media_type.as_str() {
+                    "" => String::from("text/plain"),
+                    media_type => media_type.to_string(),
+                };
+                self.attachments.push(InboundAttachment {
+                    filename,
+                    content_type,
+                    content_id: header(headers, "content-id").map(trim_angle_brackets),
+                    content,
+                });
+            }
+        }
+    }
+
+    /// The email's headers in order, with encoded words decoded
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// The value of the first header with this name, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header(&self.headers, name)
+    }
+
+    /// The sender from the `From` header
+    pub fn from(&self) -> Option<&Contact> {
+        self.from.as_ref()
+    }
+
+    /// The recipients from the `To` header. Empty if the header isn't a valid address list, see
+    /// `header` for its raw value.
+    pub fn to(&self) -> &[Contact] {
+        &self.to
+    }
+
+    /// The recipients from the `Cc` header
+    pub fn cc(&self) -> &[Contact] {
+        &self.cc
+    }
+
+    /// The subject, or an empty string if there is none
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    /// The plain text body
+    pub fn text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    /// The HTML body
+    pub fn html(&self) -> Option<&str> {
+        self.html.as_deref()
+    }
+
+    /// The attachments, in the order they appear in the email
+    pub fn attachments(&self) -> &[InboundAttachment] {
+        &self.attachments
+    }
+
+    /// The SMTP envelope
+    pub fn envelope(&self) -> Option<&Envelope> {
+        self.envelope.as_ref()
+    }
+
+    /// The DKIM verification results, e.g. `{@example.com : pass}`
+    pub fn dkim(&self) -> Option<&str> {
+        self.dkim.as_deref()
+    }
+
+    /// The SPF verification result, e.g. `pass`
+    pub fn spf(&self) -> Option<&str> {
+        self.spf.as_deref()
+    }
+
+    /// The IP address of the server that sent the email
+    pub fn sender_ip(&self) -> Option<&str> {
+        self.sender_ip.as_deref()
+    }
+
+    /// The SpamAssassin score, if spam checking is enabled
+    pub fn spam_score(&self) -> Option<f64> {
+        self.spam_score
+    }
+
+    /// The SpamAssassin report, if spam checking is enabled
+    pub fn spam_report(&self) -> Option<&str> {
+        self.spam_report.as_deref()
+    }
+}
+
+/// A field of the form data
+struct FormField<'a> {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+    content: &'a [u8],
+}
+
+impl<'a> FormField<'a> {
+    fn parse(part: &'a [u8]) -> Option<Self> {
+        let (head, content) = split_headers(part);
+        let headers = parse_headers(head);
+        let (_, params) = parse_params(header(&headers, "content-disposition")?);
+        Some(FormField {
+            name: param(&params, "name")?.to_string(),
+            filename: param(&params, "filename").map(String::from),
+            content_type: header(&headers, "content-type").map(|t| parse_params(t).0),
+            content,
+        })
+    }
+}
+
+/// The text field with this name
+fn form_field<'a, 'b>(fields: &'a [FormField<'b>], name: &str) -> Option<&'a FormField<'b>> {
+    fields
+        .iter()
+        .find(|f| f.name == name && f.filename.is_none())
+}
+
+/// Parses the JSON field with this name, if present
+fn json_field<T: DeserializeOwned>(
+    fields: &[FormField],
+    name: &str,
+) -> Result<Option<T>, InboundError> {
+    form_field(fields, name)
+        .map(|f| serde_json::from_slice(f.content))
+        .transpose()
+        .map_err(|_| InboundError::InvalidField(name.to_string()))
+}
+
+/// An entry of the `attachment-info` field
+#[derive(Deserialize)]
+struct AttachmentInfo {
+    filename: Option<String>,
+    #[serde(rename = "type")]
+    a_type: Option<String>,
+    #[serde(rename = "content-id")]
+    content_id: Option<String>,
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+fn contacts(list: &str) -> Vec<Contact> {
+    address::parse_list(list).unwrap_or_default()
+}
+
+fn trim_angle_brackets(id: &str) -> String {
+    let id = id.trim();
+    id.strip_prefix('<')
+        .and_then(|id| id.strip_suffix('>'))
+        .unwrap_or(id)
+        .to_string()
+}
+
+/// Splits an entity into its header block and body at the first empty line
+fn split_headers(entity: &[u8]) -> (&[u8], &[u8]) {
+    if let Some(body) = entity.strip_prefix(b"\r\n") {
+        return (&[], body);
+    }
+    if let Some(body) = entity.strip_prefix(b"\n") {
+        return (&[], body);
+    }
+    let crlf = find(entity, b"\r\n\r\n").map(|i| (i, i + 4));
+    let lf = find(entity, b"\n\n").map(|i| (i, i + 2));
+    match (crlf, lf) {
+        (Some(a), Some(b)) => {
+            let (end, start) = if a.0 < b.0 { a } else { b };
+            (&entity[..end], &entity[start..])
+        }
+        (Some((end, start)), None) | (None, Some((end, start))) => {
+            (&entity[..end], &entity[start..])
+        }
+        (None, None) => (entity, &[]),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Parses a header block, unfolding continuation lines and decoding encoded words
+fn parse_headers(head: &[u8]) -> Vec<(String, String)> {
+    let head = String::from_utf8_lossy(head);
+    let mut headers: Vec<(String, String)> = vec![];
+    for line in head.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push_str(line);
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.to_string()));
+        }
+    }
+    headers
+        .into_iter()
+        .map(|(name, value)| (name, decode_words(value.trim())))
+        .collect()
+}
+
+/// Splits a header value like `text/plain; charset="utf-8"` into its lowercased value and its
+/// parameters, decoding RFC 2231 extended parameters like `filename*=UTF-8''na%C3%AFve.txt`
+fn parse_params(value: &str) -> (String, Vec<(String, String)>) {
+    let mut segments = vec![];
+    let mut segment = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    for c in value.chars() {
+        match c {
+            _ if escaped => {
+                segment.push(c);
+                escaped = false;
+            }
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => segments.push(std::mem::take(&mut segment)),
+            _ => segment.push(c),
+        }
+    }
+    segments.push(segment);
+
+    let mut segments = segments.into_iter();
+    let value = segments.next().unwrap_or_default().trim().to_lowercase();
+    let params = segments
+        .filter_map(|segment| {
+            let (name, value) = segment.split_once('=')?;
+            let name = name.trim().to_lowercase();
+            let value = value.trim();
+            Some(match name.strip_suffix('*') {
+                Some(name) => (name.to_string(), decode_extended(value)),
+                None => (name, value.to_string()),
+            })
+        })
+        .collect();
+    (value, params)
+}
+
+fn param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// Decodes an RFC 2231 `charset'language'percent-encoded` value
+fn decode_extended(value: &str) -> String {
+    let mut parts = value.splitn(3, '\'');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(charset), Some(_), Some(encoded)) => {
+            let mut bytes = vec![];
+            let mut rest = encoded.as_bytes();
+            while let Some((&byte, tail)) = rest.split_first() {
+                match (byte, hex_pair(tail)) {
+                    (b'%', Some(decoded)) => {
+                        bytes.push(decoded);
+                        rest = &tail[2..];
+                    }
+                    _ => {
+                        bytes.push(byte);
+                        rest = tail;
+                    }
+                }
+            }
+            decode_text(&bytes, Some(charset))
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// Decodes the RFC 2047 encoded words in a header value, dropping the whitespace between
+/// adjacent words
+fn decode_words(value: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let (before, candidate) = rest.split_at(start);
+        match encoded_word(candidate) {
+            Some((word, len)) => {
+                if !(after_word && before.trim().is_empty()) {
+                    decoded.push_str(before);
+                }
+                decoded.push_str(&word);
+                rest = &candidate[len..];
+                after_word = true;
+            }
+            None => {
+                decoded.push_str(before);
+                decoded.push_str("=?");
+                rest = &candidate[2..];
+                after_word = false;
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Decodes the encoded word `=?charset?encoding?text?=` at the start of `input`, returning it and
+/// its length
+fn encoded_word(input: &str) -> Option<(String, usize)> {
+    let inner = input.strip_prefix("=?")?;
+    let (charset, rest) = inner.split_once('?')?;
+    let (encoding, rest) = rest.split_once('?')?;
+    let end = rest.find("?=")?;
+    let text = &rest[..end];
+    if text.contains(char::is_whitespace) {
+        return None;
+    }
+    let bytes = match encoding {
+        "B" | "b" => BASE64.decode(text).ok()?,
+        "Q" | "q" => decode_quoted_printable(text.as_bytes(), true),
+        _ => return None,
+    };
+    let len = input.len() - rest.len() + end + 2;
+    // A charset may name a language as in `UTF-8*en`
+    let charset = charset.split('*').next().unwrap_or(charset);
+    Some((decode_text(&bytes, Some(charset)), len))
+}
+
+/// Decodes `bytes` from the charset with this label, falling back to UTF-8 for unknown charsets
+/// and replacing invalid sequences
+fn decode_text(bytes: &[u8], charset: Option<&str>) -> String {
+    let encoding = charset
+        .and_then(|c| Encoding::for_label(c.trim().as_bytes()))
+        .unwrap_or(UTF_8);
+    encoding.decode(bytes).0.into_owned()
+}
+
+fn decode_transfer(encoding: Option<&str>, body: &[u8]) -> Vec<u8> {
+    match encoding.map(|e| e.trim().to_lowercase()).as_deref() {
+        Some("base64") => {
+            let encoded: Vec<u8> = body
+                .iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect();
+            BASE64.decode(encoded).unwrap_or_else(|_| body.to_vec())
+        }
+        Some("quoted-printable") => decode_quoted_printable(body, false),
+        _ => body.to_vec(),
+    }
+}
+
+/// Decodes quoted-printable, or with `q_encoding` the variant used in encoded words where `_` is
+/// a space
+fn decode_quoted_printable(input: &[u8], q_encoding: bool) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(input.len());
+    let mut rest = input;
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        match byte {
+            b'=' => {
+                if let Some(tail) = rest.strip_prefix(b"\r\n").or(rest.strip_prefix(b"\n")) {
+                    rest = tail;
+                } else if let Some(value) = hex_pair(rest) {
+                    decoded.push(value);
+                    rest = &rest[2..];
+                } else {
+                    decoded.push(byte);
+                }
+            }
+            b'_' if q_encoding => decoded.push(b' '),
+            _ => decoded.push(byte),
+        }
+    }
+    decoded
+}
+
+fn hex_pair(input: &[u8]) -> Option<u8> {
+    let hex = std::str::from_utf8(input.get(..2)?).ok()?;
+    u8::from_str_radix(hex, 16).ok()
+}
+
+/// Splits a multipart body into its parts. A missing closing delimiter is tolerated.
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = vec![];
+    let mut start = None;
+    let mut line_start = 0;
+    while line_start < body.len() {
+        let line_end = body[line_start..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(body.len(), |i| line_start + i);
+        let line = &body[line_start..line_end];
+        if let Some(after) = line.strip_prefix(delimiter.as_bytes()) {
+            let closing = after.starts_with(b"--");
+            if closing || after.iter().all(u8::is_ascii_whitespace) {
+                if let Some(start) = start {
+                    // The line break before a delimiter belongs to the delimiter
+                    let mut end = line_start;
+                    if end > start && body[end - 1] == b'\n' {
+                        end -= 1;
+                        if end > start && body[end - 1] == b'\r' {
+                            end -= 1;
+                        }
+                    }
+                    parts.push(&body[start..end]);
+                }
+                if closing {
+                    return parts;
+                }
+                start = Some((line_end + 1).min(body.len()));
+            }
+        }
+        line_start = line_end + 1;
+    }
+    if let Some(start) = start {
+        parts.push(&body[start..]);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_words, parse_params, split_multipart, InboundEmail, InboundError};
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+
+    fn form(fields: &[(&str, Option<&str>, &[u8])]) -> Vec<u8> {
+        let mut body = b"preamble\r\n".to_vec();
+        for (name, filename, content) in fields {
+            body.extend_from_slice(b"--BOUNDARY\r\nContent-Disposition: form-data; name=\"");
+            body.extend_from_slice(name.as_bytes());
+            body.push(b'"');
+            if let Some(filename) = filename {
+                body.extend_from_slice(b"; filename=\"");
+                body.extend_from_slice(filename.as_bytes());
+                body.extend_from_slice(b"\"\r\nContent-Type: application/octet-stream");
+            }
+            body.extend_from_slice(b"\r\n\r\n");
+            body.extend_from_slice(content);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(b"--BOUNDARY--\r\n");
+        body
+    }
+
+    const CONTENT_TYPE: &str = "multipart/form-data; boundary=BOUNDARY";
+
+    #[test]
+    fn parses_default_mode() {
+        let body = form(&[
+            (
+                "headers",
+                None,
+                b"From: \"Lee, Ann\" <ann@example.com>\r\nTo: a@example.com,\r\n b@example.com\r\n\
+                  Message-ID: <1@example.com>\r\nSubject: =?ISO-8859-1?Q?Gr=FC=DFe?=\r\n",
+            ),
+            ("dkim", None, b"{@example.com : pass}"),
+            ("SPF", None, b"pass"),
+            ("sender_ip", None, b"10.0.0.1"),
+            ("spam_score", None, b"0.012"),
+            ("envelope", None, br#"{"to":["inbox@example.com"],"from":"ann@example.com"}"#),
+            (
+                "charsets",
+                None,
+                br#"{"subject":"UTF-8","text":"iso-8859-1","html":"windows-1251","from":"UTF-8"}"#,
+            ),
+            ("subject", None, "Grüße".as_bytes()),
+            ("from", None, b"Ann <ann@example.com>"),
+            ("text", None, b"Gr\xfc\xdfe\r\n"),
+            ("html", None, b"<p>\xcf\xf0\xe8\xe2\xe5\xf2</p>"),
+            ("attachments", None, b"2"),
+            (
+                "attachment-info",
+                None,
+                br#"{"attachment1":{"filename":"logo.png","name":"logo.png","type":"image/png","content-id":"<logo>"},
+                     "attachment2":{"filename":"notes.txt","type":"text/plain"}}"#,
+            ),
+            ("attachment1", Some("logo.png"), b"\x89PNG\r\n\x1a\n"),
+            ("attachment2", Some("notes.txt"), b"notes"),
+        ]);
+
+        let email = InboundEmail::parse(CONTENT_TYPE, &body).unwrap();
+        assert_eq!(email.subject(), "Grüße");
+        assert_eq!(email.from().unwrap().name(), Some("Ann"));
+        let to: Vec<_> = email.to().iter().map(|c| c.email()).collect();
+        assert_eq!(to, vec!["a@example.com", "b@example.com"]);
+        assert_eq!(email.header("message-id"), Some("<1@example.com>"));
+        assert_eq!(email.text(), Some("Grüße\r\n"));
+        assert_eq!(email.html(), Some("<p>Привет</p>"));
+        assert_eq!(email.dkim(), Some("{@example.com : pass}"));
+        assert_eq!(email.spf(), Some("pass"));
+        assert_eq!(email.sender_ip(), Some("10.0.0.1"));
+        assert_eq!(email.spam_score(), Some(0.012));
+        let envelope = email.envelope().unwrap();
+        assert_eq!(envelope.from(), Some("ann@example.com"));
+        assert_eq!(envelope.to(), ["inbox@example.com"]);
+
+        let attachments = email.attachments();
+        assert_eq!(attachments.len(), 2);
+        assert_eq!(attachments[0].filename(), "logo.png");
+        assert_eq!(attachments[0].content_type(), "image/png");
+        assert_eq!(attachments[0].content_id(), Some("logo"));
+        assert_eq!(attachments[0].content(), b"\x89PNG\r\n\x1a\n");
+        assert_eq!(attachments[1].content_id(), None);
+
+        let attachment = serde_json::to_value(attachments[0].to_attachment()).unwrap();
+        assert_eq!(attachment["content"], STANDARD.encode(b"\x89PNG\r\n\x1a\n"));
+        assert_eq!(attachment["disposition"], "inline");
+        assert_eq!(attachment["content_id"], "logo");
+    }
+
+    #[test]
+    fn parses_raw_mode() {
+        let raw = b"From: =?UTF-8?B?SsO8cmdlbg==?= <j@example.com>\r\n\
+            To: inbox@example.com\r\n\
+            Subject: =?UTF-8?Q?Re:_Caf=C3=A9?= =?UTF-8?Q?_menu?=\r\n\
+            Content-Type: multipart/mixed; boundary=\"outer\"\r\n\
+            \r\n\
+            This is a multi-part message in MIME format.\r\n\
+            --outer\r\n\
+            Content-Type: multipart/alternative; boundary=inner\r\n\
+            \r\n\
+            --inner\r\n\
+            Content-Type: text/plain; charset=\"iso-8859-1\"\r\n\
+            Content-Transfer-Encoding: quoted-printable\r\n\
+            \r\n\
+            Caf=E9 =\r\n\
+            au lait\r\n\
+            --inner\r\n\
+            Content-Type: text/html; charset=utf-8\r\n\
+            Content-Transfer-Encoding: base64\r\n\
+            \r\n\
+            PHA+Q2Fmw6k8L3A+\r\n\
+            --inner--\r\n\
+            --outer\r\n\
+            Content-Type: image/png\r\n\
+            Content-ID: <img1@example.com>\r\n\
+            Content-Transfer-Encoding: base64\r\n\
+            \r\n\
+            iVBORw0K\r\n\
+            GgoA\r\n\
+            --outer\r\n\
+            Content-Type: text/plain\r\n\
+            Content-Disposition: attachment; filename*=UTF-8''na%C3%AFve.txt\r\n\
+            \r\n\
+            plain attachment\r\n\
+            --outer--\r\n";
+        let body = form(&[
+            ("email", None, raw),
+            ("envelope", None, br#"{"to":["inbox@example.com"]}"#),
+            (
+                "charsets",
+                None,
+                br#"{"to":"UTF-8","subject":"UTF-8","from":"UTF-8"}"#,
+            ),
+        ]);
+
+        let email = InboundEmail::parse(CONTENT_TYPE, &body).unwrap();
+        assert_eq!(email.subject(), "Re: Café menu");
+        assert_eq!(email.from().unwrap().name(), Some("Jürgen"));
+        assert_eq!(email.to()[0].email(), "inbox@example.com");
+        assert_eq!(email.text(), Some("Café au lait"));
+        assert_eq!(email.html(), Some("<p>Café</p>"));
+        assert_eq!(email.envelope().unwrap().from(), None);
+
+        let attachments = email.attachments();
+        assert_eq!(attachments.len(), 2);
+        assert_eq!(attachments[0].filename(), "attachment1");
+        assert_eq!(attachments[0].content_type(), "image/png");
+        assert_eq!(attachments[0].content_id(), Some("img1@example.com"));
+        assert_eq!(attachments[0].content(), b"\x89PNG\r\n\x1a\n\0");
+        assert_eq!(attachments[1].filename(), "naïve.txt");
+        assert_eq!(attachments[1].content(), b"plain attachment");
+    }
+
+    #[test]
+    fn rejects_invalid_requests() {
+        assert_eq!(
+            InboundEmail::parse("multipart/form-data", b""),
+            Err(InboundError::NotFormData)
+        );
+        assert_eq!(
+            InboundEmail::parse(CONTENT_TYPE, &form(&[("text", None, b"hi")])),
+            Err(InboundError::MissingField(String::from("headers")))
+        );
+        assert_eq!(
+            InboundEmail::parse(
+                CONTENT_TYPE,
+                &form(&[("headers", None, b""), ("envelope", None, b"{")])
+            ),
+            Err(InboundError::InvalidField(String::from("envelope")))
+        );
+    }
+
+    #[test]
+    fn splits_multipart_bodies() {
+        let body = b"--b\r\none\r\n--b \r\n\r\ntwo\n--bb\n--b\r\nthree";
+        assert_eq!(
+            split_multipart(body, "b"),
+            vec![&b"one"[..], &b"\r\ntwo\n--bb"[..], &b"three"[..]]
+        );
+        assert_eq!(split_multipart(b"no parts", "b"), Vec::<&[u8]>::new());
+    }
+
+    #[test]
+    fn decodes_header_values() {
+        assert_eq!(
+            decode_words("=?utf-8?q?a?= =?utf-8?q?b?= c =?bogus =?UTF-8*en?B?w6k=?="),
+            "ab c =?bogus é"
+        );
+        let (value, params) =
+            parse_params("Attachment; FileName=\"a \\\"b\\\"; c\"; size=3; name*=''x%20y");
+        assert_eq!(value, "attachment");
+        assert_eq!(
+            params,
+            vec![
+                (String::from("filename"), String::from("a \"b\"; c")),
+                (String::from("size"), String::from("3")),
+                (String::from("name"), String::from("x y")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Helpers for inline images: generating content ids, and finding and rewriting the `src` of
+//! `<img>` tags in HTML `Content`. Used by `MessageBuilder::inline_image` and validation.
+
+use crate::html::{attributes, tag_end};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::ops::Range;
+
+/// Generates a content id for the `index`th attachment of a `Message`, unique within the
+/// `Message` and unlikely to collide with ids elsewhere in a mail client
+pub(crate) fn content_id(index: usize, filename: &str, content: &str) -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    filename.hash(&mut hasher);
+    content.hash(&mut hasher);
+    let stem: String = filename
+        .chars()
+        .take_while(|c| *c != '.')
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect();
+    let stem = if stem.is_empty() { "image" } else { &stem };
+    format!("{}.{}.{:016x}@sendgrid-rs", stem, index, hasher.finish())
+}
+
+/// The byte ranges of the `src` attribute values of every `<img>` tag in `html`
+pub(crate) fn img_srcs(html: &str) -> Vec<Range<usize>> {
+    let lower = html.to_ascii_lowercase();
+    let mut ranges = vec![];
+    let mut from = 0;
+    while let Some(start) = lower[from..].find("<img").map(|i| i + from) {
+        from = start + "<img".len();
+        let is_img =
+            lower[from..].starts_with(|c: char| c == '>' || c == '/' || c.is_ascii_whitespace());
+        let len = match tag_end(&html[start..]) {
+            Some(len) if is_img => len,
+            _ => continue,
+        };
+        let tag = &html[start..start + len];
+        let src = attributes(tag)
+            .into_iter()
+            .find(|(name, _)| name == "src")
+            .and_then(|(_, value)| value);
+        if let Some(value) = src {
+            ranges.push(start + value.start..start + value.end);
+        }
+        from = start + len;
+    }
+    ranges
+}
+
+/// Rewrites the `src` of every `<img>` tag in `html` for which `rewrite` returns a replacement
+pub(crate) fn rewrite_img_srcs(html: &str, rewrite: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut last = 0;
+    for range in img_srcs(html) {
+        if let Some(replacement) = rewrite(&html[range.clone()]) {
+            out.push_str(&html[last..range.start]);
+            out.push_str(&replacement);
+            last = range.end;
+        }
+    }
+    out.push_str(&html[last..]);
+    out
+}
+
+/// The content ids referenced by `cid:` image sources in `html`
+pub(crate) fn cid_references(html: &str) -> impl Iterator<Item = &str> {
+    img_srcs(html).into_iter().filter_map(move |range| {
+        let src = &html[range];
+        if src.len() > 4 && src[..4].eq_ignore_ascii_case("cid:") {
+            Some(&src[4..])
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cid_references, content_id, rewrite_img_srcs};
+
+    #[test]
+    fn rewrites_matching_sources() {
+        let html =
+            r#"<p><IMG alt="x" SRC = "logo.png"><img data-src="logo.png" src='chart.png'/></p>"#;
+        let rewritten = rewrite_img_srcs(html, |src| match src {
+            "logo.png" => Some(String::from("cid:logo")),
+            _ => None,
+        });
+        assert_eq!(
+            rewritten,
+            r#"<p><IMG alt="x" SRC = "cid:logo"><img data-src="logo.png" src='chart.png'/></p>"#
+        );
+    }
+
+    #[test]
+    fn rewrites_sources_after_quoted_brackets() {
+        let html = r#"<img alt="a>b" src="logo.png"><imgx src="logo.png">"#;
+        let rewritten = rewrite_img_srcs(html, |src| match src {
+            "logo.png" => Some(String::from("cid:logo")),
+            _ => None,
+        });
+        assert_eq!(
+            rewritten,
+            r#"<img alt="a>b" src="cid:logo"><imgx src="logo.png">"#
+        );
+    }
+
+    #[test]
+    fn finds_cid_references() {
+        let html = r#"<img src="cid:a"><img src="b.png"><img src="CID:c">"#;
+        assert_eq!(cid_references(html).collect::<Vec<_>>(), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn generates_distinct_content_ids() {
+        let a = content_id(0, "logo.png", "AAAA");
+        let b = content_id(1, "logo.png", "AAAA");
+        assert!(a.starts_with("logo.0."));
+        assert!(a.ends_with("@sendgrid-rs"));
+        assert_ne!(a, b);
+    }
+}
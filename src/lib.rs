@@ -1,13 +1,20 @@
 //! This crate is a wrapper around SendGrid's v3 API using builder patterns to construct a payload
-//! to send. This crate does not have batteries included, does not perform any validations, and
+//! to send. This crate does not have batteries included, only validates when asked to, and
 //! makes no assumptions other than what's specified in SendGrid's API documentation. To actually
-//! call the API, you must use some other mechnism for the HTTP connection (such as the reqwest
-//! crate).
+//! call the API, use a [Client](client/struct.Client.html) with a
+//! [Transport](transport/trait.Transport.html) for the HTTP library of your choice. The `ureq`
+//! and `reqwest` cargo features provide transports for those crates.
 //!
 //! Everything stems from [Message](message/struct.Message.html) which you can construct using a
 //! [MessageBuilder](message/struct.MessageBuilder.html). When you're done with the
 //! `MessageBuilder` call `build()` to get the underlying `Message` and `to_json()` to get the
-//! entire `Message` output as a JSON string.
+//! entire `Message` output as a JSON string. Call `try_build()` instead to check the `Message`
+//! against SendGrid's API rules before it is sent.
+//!
+//! With the `derive` feature, `#[derive(TemplateData)]` turns a struct into typed
+//! `dynamic_template_data`, see [TemplateData](template_data/trait.TemplateData.html). With the
+//! `handlebars` feature, the [preview](preview/index.html) module renders dynamic templates
+//! locally.
 //!
 //! # Examples
 //! ```
@@ -28,26 +35,65 @@
 //! .to_json();
 //! ```
 
-use serde::Serialize;
+use crate::address::{AddressError, EmailAddress};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+// Lets the derive macros' `::sendgrid_rs` paths resolve in this crate's own tests
+#[cfg(test)]
+extern crate self as sendgrid_rs;
 
+pub mod address;
 pub mod attachment;
+pub mod batch;
+pub mod batcher;
+pub mod client;
+pub mod clock;
+pub mod csv;
+pub mod error;
+mod html;
+pub mod inbound;
+mod inline;
 pub mod mail_settings;
 pub mod message;
+pub mod mime;
+#[cfg(feature = "mock")]
+pub mod mock;
 pub mod personalization;
+#[cfg(feature = "handlebars")]
+pub mod preview;
+pub mod reply;
+pub mod retry;
+#[cfg(feature = "signature")]
+pub mod signature;
+pub mod smtp;
+pub mod substitution;
+pub mod suppression;
+pub mod template_data;
 pub mod tracking_settings;
+pub mod transport;
+pub mod validation;
+pub mod webhook;
 
 pub use crate::attachment::AttachmentBuilder;
+pub use crate::batcher::BatcherBuilder;
+pub use crate::client::{Client, ClientBuilder};
 pub use crate::mail_settings::MailSettingsBuilder;
 pub use crate::message::MessageBuilder;
 pub use crate::personalization::PersonalizationBuilder;
+pub use crate::template_data::TemplateData;
 pub use crate::tracking_settings::{GaTrackingSettingBuilder, TrackingSettingsBuilder};
+pub use crate::validation::{ValidationError, ValidationErrors};
+#[cfg(feature = "derive")]
+pub use sendgrid_rs_derive::TemplateData;
 
 /// Type used for SendGrid's asm fields for managing subscriptions
 /// Use `AsmBuilder` to construct this when adding it to a `Message`
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Asm {
-    group_id: i32,
-    groups_to_display: Vec<i32>,
+    pub(crate) group_id: i32,
+    #[serde(default)]
+    pub(crate) groups_to_display: Vec<i32>,
 }
 
 /// A builder pattern for constructing `Asm`
@@ -105,11 +151,11 @@ impl AsmBuilder {
 
 /// `Content` is the struct used to add content fields on SendGrid's API
 /// This is essentially a key/value store that serializes into the correct format
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Content {
     #[serde(rename = "type")]
-    c_type: String,
-    value: String,
+    pub(crate) c_type: String,
+    pub(crate) value: String,
 }
 
 impl Content {
@@ -127,14 +173,24 @@ impl Content {
             value: value.into(),
         }
     }
+
+    /// The content type (mime type), e.g. `text/html`
+    pub fn content_type(&self) -> &str {
+        &self.c_type
+    }
+
+    /// The content itself
+    pub fn value(&self) -> &str {
+        &self.value
+    }
 }
 
 /// Struct that holds the data needed for the 'contact' section in the SendGrid API.
 /// Use a `ContactBuilder` to construct this.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Contact {
-    email: String,
-    name: Option<String>,
+    pub(crate) email: String,
+    pub(crate) name: Option<String>,
 }
 
 impl Contact {
@@ -144,6 +200,50 @@ impl Contact {
             name,
         }
     }
+
+    /// Parses a `Contact` from an email address, optionally with a display name as in
+    /// `"Jane Doe" <jane@example.com>` or `Jane Doe <jane@example.com>`. Unlike
+    /// `ContactBuilder::new`, the address is validated and its domain normalized, see
+    /// [EmailAddress](address/struct.EmailAddress.html).
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::Contact;
+    ///
+    /// let contact = Contact::parse("\"Doe, Jane\" <Jane@Example.COM>").unwrap();
+    /// assert_eq!(contact.email(), "Jane@example.com");
+    /// assert_eq!(contact.name(), Some("Doe, Jane"));
+    ///
+    /// assert!(Contact::parse("Jane Doe").is_err());
+    /// ```
+    pub fn parse(input: &str) -> Result<Contact, AddressError> {
+        let (name, address) = address::parse_mailbox(input)?;
+        Ok(Contact::new(address, name))
+    }
+
+    /// The email address
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
+    /// The display name, if any
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+impl FromStr for Contact {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Contact::parse(s)
+    }
+}
+
+impl From<EmailAddress> for Contact {
+    fn from(address: EmailAddress) -> Self {
+        Contact::new(address, None)
+    }
 }
 
 /// Builder pattern for `Contact`. Make sure you call `build()` when you're done to consume the
@@ -1,14 +1,14 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 /// Struct to store data and serialize to SendGrid's API for the mail_settings node
 /// Use MailSettingsBuilder to construct this
-#[derive(Serialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct MailSettings {
-    bcc: Option<BccSetting>,
-    bypass_list_management: Option<BypassListSetting>,
-    footer: Option<FooterSetting>,
-    sandbox_mode: Option<SandboxModeSetting>,
-    spam_check: Option<SpamCheckSetting>,
+    pub(crate) bcc: Option<BccSetting>,
+    pub(crate) bypass_list_management: Option<BypassListSetting>,
+    pub(crate) footer: Option<FooterSetting>,
+    pub(crate) sandbox_mode: Option<SandboxModeSetting>,
+    pub(crate) spam_check: Option<SpamCheckSetting>,
 }
 
 /// Builder pattern for creating `MailSettings` Make sure you call `build()` to consume this
@@ -122,40 +122,40 @@ impl MailSettingsBuilder {
 
 /// Struct used for serializing the Bcc node into SendGrid's API format. Use `MailSettingsBuilder`
 /// to configure this.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct BccSetting {
-    enable: bool,
-    email: String,
+    pub(crate) enable: bool,
+    pub(crate) email: String,
 }
 
 /// Struct used for serializing the BypassList node into SendGrid's API format. Use
 /// `MailSettingsBuilder` to configure this.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct BypassListSetting {
-    enable: bool,
+    pub(crate) enable: bool,
 }
 
 /// Struct used for serializing the Footer node into SendGrid's API format. Use
 /// `MailSettingsBuilder` to configure this.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct FooterSetting {
-    enable: bool,
-    text: Option<String>,
-    html: Option<String>,
+    pub(crate) enable: bool,
+    pub(crate) text: Option<String>,
+    pub(crate) html: Option<String>,
 }
 
 /// Struct used for serializing the SandboxMode node into SendGrid's API format. Use
 /// `MailSettingsBuilder` to configure this.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct SandboxModeSetting {
-    enable: bool,
+    pub(crate) enable: bool,
 }
 
 /// Struct used for serializing the SpamCheck node into SendGrid's API format. Use
 /// `MailSettingsBuilder` to configure this.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct SpamCheckSetting {
-    enable: bool,
-    threshold: Option<i32>,
-    post_to_url: Option<String>,
+    pub(crate) enable: bool,
+    pub(crate) threshold: Option<i32>,
+    pub(crate) post_to_url: Option<String>,
 }
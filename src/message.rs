@@ -1,48 +1,53 @@
 use crate::attachment::Attachment;
+use crate::clock::{self, Clock, SystemClock};
+use crate::inline;
 use crate::mail_settings::MailSettings;
-use crate::personalization::Personalization;
+use crate::personalization::{Personalization, PersonalizationBuilder};
 use crate::tracking_settings::TrackingSettings;
-use crate::{Asm, Contact, Content};
-use serde::Serialize;
+use crate::validation::{self, ValidationErrors};
+use crate::{Asm, Contact, Content, TemplateData};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::SystemTime;
 
 /// Message is the wrapper around the entire payload to be sent to SendGrid's API.
 /// Use [MessageBuilder](struct.MessageBuilder.html) to properly construct this. The `to_json`
 /// method is available to turn this struct into the request body to send to SendGrid
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Message {
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    personalizations: Vec<Personalization>,
-    from: Contact,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) personalizations: Vec<Personalization>,
+    pub(crate) from: Contact,
     #[serde(skip_serializing_if = "Option::is_none")]
-    reply_to: Option<Contact>,
-    subject: String,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    content: Vec<Content>,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    attachments: Vec<Attachment>,
+    pub(crate) reply_to: Option<Contact>,
+    pub(crate) subject: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) content: Vec<Content>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) attachments: Vec<Attachment>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    template_id: Option<String>,
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
-    sections: HashMap<String, String>,
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
-    headers: HashMap<String, String>,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    categories: Vec<String>,
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
-    custom_args: HashMap<String, String>,
+    pub(crate) template_id: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) sections: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) headers: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) categories: Vec<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) custom_args: HashMap<String, String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    send_at: Option<i32>,
+    pub(crate) send_at: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    batch_id: Option<String>,
+    pub(crate) batch_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    asm: Option<Asm>,
+    pub(crate) asm: Option<Asm>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    ip_pool_name: Option<String>,
+    pub(crate) ip_pool_name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    mail_settings: Option<MailSettings>,
+    pub(crate) mail_settings: Option<MailSettings>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    tracking_settings: Option<TrackingSettings>,
+    pub(crate) tracking_settings: Option<TrackingSettings>,
 }
 
 impl Message {
@@ -53,6 +58,152 @@ impl Message {
     pub fn to_json(&self) -> String {
         serde_json::to_string(&self).expect("could not properly serialize into JSON")
     }
+
+    /// `from_json` parses a `Message` from JSON in the same format SendGrid's API accepts, which
+    /// is also the format produced by `to_json`. Use this to read back stored payloads.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::message::Message;
+    ///
+    /// let message = Message::from_json(
+    ///     r#"{"from":{"email":"from@example.com","name":null},"subject":"Subject Line"}"#,
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn from_json(json: &str) -> serde_json::Result<Message> {
+        serde_json::from_str(json)
+    }
+
+    /// `size` measures the JSON `to_json` produces, broken down by section, without building the
+    /// string. SendGrid rejects requests larger than `validation::MAX_MESSAGE_SIZE`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::{AttachmentBuilder, MessageBuilder, ContactBuilder, Content};
+    ///
+    /// let message = MessageBuilder::new(
+    ///         ContactBuilder::new("from@example.com").build(),
+    ///         "Subject Line"
+    ///     )
+    ///     .content(Content::new("text/plain", "Email Body"))
+    ///     .attachment(AttachmentBuilder::from_bytes(vec![0; 3000], "file.bin").build())
+    ///     .build();
+    /// let size = message.size();
+    /// assert_eq!(size.total(), message.to_json().len());
+    /// assert!(size.attachments() > 4000);
+    /// assert_eq!(size.personalizations(), 0);
+    /// ```
+    pub fn size(&self) -> MessageSize {
+        MessageSize {
+            total: json_size(self),
+            content: section_size(&self.content),
+            attachments: section_size(&self.attachments),
+            personalizations: section_size(&self.personalizations),
+        }
+    }
+
+    /// `validate` checks the `Message` against the rules SendGrid's API enforces and returns
+    /// every violation found, each with the path of the offending field.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::{MessageBuilder, ContactBuilder};
+    ///
+    /// let message = MessageBuilder::new(
+    ///         ContactBuilder::new("from@example.com").build(),
+    ///         "Subject Line"
+    ///     )
+    ///     .build();
+    /// assert!(message.validate().is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        self.validate_with_clock(&SystemClock)
+    }
+
+    /// Like `validate`, but checks `send_at` times against `clock` instead of the system time.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::{MessageBuilder, ContactBuilder};
+    /// # use sendgrid_rs::clock::SystemClock;
+    ///
+    /// let message = MessageBuilder::new(
+    ///         ContactBuilder::new("from@example.com").build(),
+    ///         "Subject Line"
+    ///     )
+    ///     .build();
+    /// assert!(message.validate_with_clock(&SystemClock).is_err());
+    /// ```
+    pub fn validate_with_clock(&self, clock: &impl Clock) -> Result<(), ValidationErrors> {
+        validation::validate(self, clock.now())
+    }
+}
+
+/// The size in bytes of a `Message`'s JSON, as returned by `Message::size`. Each section counts
+/// the JSON value of that field, so the sections of a `Message` without it count 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageSize {
+    total: usize,
+    content: usize,
+    attachments: usize,
+    personalizations: usize,
+}
+
+impl MessageSize {
+    /// The size of the whole `Message`
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// The size of the `content` array
+    pub fn content(&self) -> usize {
+        self.content
+    }
+
+    /// The size of the `attachments` array
+    pub fn attachments(&self) -> usize {
+        self.attachments
+    }
+
+    /// The size of the `personalizations` array
+    pub fn personalizations(&self) -> usize {
+        self.personalizations
+    }
+
+    /// The size of everything else: the other fields and the JSON punctuation around them
+    pub fn other(&self) -> usize {
+        self.total - self.content - self.attachments - self.personalizations
+    }
+}
+
+/// The length of `value` serialized as JSON
+fn json_size<T: Serialize>(value: &T) -> usize {
+    struct Counter(usize);
+
+    impl Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    serde_json::to_writer(&mut counter, value).expect("could not properly serialize into JSON");
+    counter.0
+}
+
+/// The size of a section of `Message`, which isn't serialized when empty
+fn section_size<T: Serialize>(section: &[T]) -> usize {
+    if section.is_empty() {
+        0
+    } else {
+        json_size(&section)
+    }
 }
 
 /// A `builder pattern` type for constructing `Message`
@@ -65,8 +216,11 @@ impl Message {
 /// At least one personalization is required
 /// From is required, but handled in the constructor
 /// Subject is required, but handled in the constructor
+///
+/// `build` does not check these, use `try_build` to validate the `Message` as it is built.
 pub struct MessageBuilder {
     message: Message,
+    inline_images: Vec<(String, String)>,
 }
 
 impl MessageBuilder {
@@ -107,6 +261,7 @@ impl MessageBuilder {
                 mail_settings: None,
                 tracking_settings: None,
             },
+            inline_images: vec![],
         }
     }
 
@@ -151,6 +306,53 @@ impl MessageBuilder {
         self
     }
 
+    /// Adds a `Personalization` whose dynamic template substitutions come from a `TemplateData`
+    /// type, and sets the template id of the `Message` if the type is tied to one.
+    ///
+    /// # Panics
+    /// Panics if `data` does not serialize into a JSON object, see
+    /// `PersonalizationBuilder::template_data`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::{MessageBuilder, ContactBuilder, PersonalizationBuilder, TemplateData};
+    /// # use serde::Serialize;
+    /// #[derive(Serialize)]
+    /// struct Welcome {
+    ///     first_name: String,
+    /// }
+    ///
+    /// impl TemplateData for Welcome {
+    ///     fn template_id() -> Option<&'static str> {
+    ///         Some("d-0123456789abcdef0123456789abcdef")
+    ///     }
+    /// }
+    ///
+    /// let message = MessageBuilder::new(
+    ///         ContactBuilder::new("from@example.com").build(),
+    ///         "Subject Line"
+    ///     )
+    ///     .template_personalization(
+    ///         PersonalizationBuilder::default()
+    ///             .to(ContactBuilder::new("to@example.com").build()),
+    ///         &Welcome { first_name: String::from("Alice") }
+    ///     )
+    ///     .build();
+    /// ```
+    pub fn template_personalization<T: TemplateData>(
+        mut self,
+        personalization: PersonalizationBuilder,
+        data: &T,
+    ) -> Self {
+        if let Some(id) = T::template_id() {
+            self.message.template_id = Some(String::from(id));
+        }
+        self.message
+            .personalizations
+            .push(personalization.template_data(data).build());
+        self
+    }
+
     /// Adds a reply_to `Contact` to the `Message`
     /// Use a `ContactBuilder` to construct the `Contact`
     ///
@@ -213,6 +415,44 @@ impl MessageBuilder {
         self
     }
 
+    /// Embeds an image inline. The `Attachment` gets disposition `inline` and, unless it already
+    /// has one, a generated unique content id. When the `Message` is built, every
+    /// `<img src="...">` in the HTML `Content` whose `src` equals `src` is rewritten to reference
+    /// the attachment with `cid:`.
+    ///
+    /// If no `<img>` matches `src`, the image is still attached but isn't shown in the body.
+    /// `try_build` and `Message::validate` report this as
+    /// `ValidationErrorKind::UnreferencedInlineAttachment`, unless a template is used.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::{MessageBuilder, ContactBuilder, AttachmentBuilder, Content};
+    ///
+    /// let message = MessageBuilder::new(
+    ///         ContactBuilder::new("from@example.com").build(),
+    ///         "Subject Line"
+    ///         )
+    ///         .content(Content::new("text/html", r#"<img src="images/logo.png">"#))
+    ///         .inline_image(
+    ///             "images/logo.png",
+    ///             AttachmentBuilder::from_bytes(b"\x89PNG\r\n\x1a\n", "logo.png").build()
+    ///         )
+    ///         .build();
+    /// assert!(message.to_json().contains(r#"<img src=\"cid:logo."#));
+    /// ```
+    pub fn inline_image(mut self, src: impl Into<String>, mut attachment: Attachment) -> Self {
+        let index = self.message.attachments.len();
+        let cid = match &attachment.content_id {
+            Some(cid) => cid.clone(),
+            None => inline::content_id(index, &attachment.filename, &attachment.content),
+        };
+        attachment.content_id = Some(cid.clone());
+        attachment.disposition = Some(String::from("inline"));
+        self.inline_images.push((src.into(), cid));
+        self.message.attachments.push(attachment);
+        self
+    }
+
     /// Sets the template id the `Message` will use.
     ///
     /// # Examples
@@ -281,7 +521,8 @@ impl MessageBuilder {
         self
     }
 
-    /// Adds a custom arg to the `Message`
+    /// Adds a custom arg to the `Message`. See
+    /// [EVENT_FIELDS](../webhook/constant.EVENT_FIELDS.html) for keys to avoid.
     ///
     /// # Examples
     /// ```
@@ -298,24 +539,28 @@ impl MessageBuilder {
         self
     }
 
-    /// Adds a send_at time to the `Message`
+    /// Schedules the `Message` to be sent at `time`, which SendGrid accepts up to 72 hours ahead.
+    /// Anything that converts into a `SystemTime` works, including `chrono::DateTime` and
+    /// `time::OffsetDateTime`. It is sent as a Unix timestamp.
     ///
     /// # Examples
     /// ```
     /// # use sendgrid_rs::{MessageBuilder, ContactBuilder};
+    /// # use std::time::{Duration, SystemTime};
     ///
     /// let builder = MessageBuilder::new(
     ///         ContactBuilder::new("from@example.com").build(),
     ///         "Subject Line"
     ///         )
-    ///         .send_at(3600);
+    ///         .send_at(SystemTime::now() + Duration::from_secs(3600));
     /// ```
-    pub fn send_at(mut self, time: i32) -> Self {
-        self.message.send_at = Some(time);
+    pub fn send_at(mut self, time: impl Into<SystemTime>) -> Self {
+        self.message.send_at = Some(clock::unix_seconds(time.into()));
         self
     }
 
-    /// Adds a batch_id to the `Message`
+    /// Adds a batch_id to the `Message`. Create one with `batch::CreateBatchId` to be able to
+    /// pause or cancel scheduled messages together.
     ///
     /// # Examples
     /// ```
@@ -422,7 +667,216 @@ impl MessageBuilder {
     ///         )
     ///         .build();
     /// ```
-    pub fn build(self) -> Message {
+    pub fn build(mut self) -> Message {
+        if !self.inline_images.is_empty() {
+            let inline_images = &self.inline_images;
+            for content in &mut self.message.content {
+                if content.c_type.eq_ignore_ascii_case("text/html") {
+                    content.value = inline::rewrite_img_srcs(&content.value, |src| {
+                        inline_images
+                            .iter()
+                            .find(|(path, _)| path == src)
+                            .map(|(_, cid)| format!("cid:{}", cid))
+                    });
+                }
+            }
+        }
         self.message
     }
+
+    /// Consumes the `MessageBuilder` and returns the `Message` if it passes validation. Otherwise
+    /// every violation SendGrid would reject the `Message` for is returned.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::{MessageBuilder, ContactBuilder, Content, PersonalizationBuilder};
+    ///
+    /// let message = MessageBuilder::new(
+    ///         ContactBuilder::new("from@example.com").build(),
+    ///         "Subject Line"
+    ///         )
+    ///         .content(Content::new("text/plain", "Email Body"))
+    ///         .personalization(
+    ///             PersonalizationBuilder::default()
+    ///                 .to(ContactBuilder::new("to@example.com").build())
+    ///                 .build()
+    ///         )
+    ///         .try_build()
+    ///         .unwrap();
+    /// ```
+    pub fn try_build(self) -> Result<Message, ValidationErrors> {
+        self.try_build_with_clock(&SystemClock)
+    }
+
+    /// Like `try_build`, but checks `send_at` times against `clock` instead of the system time.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::{MessageBuilder, ContactBuilder, Content, PersonalizationBuilder};
+    /// # use sendgrid_rs::clock::SystemClock;
+    ///
+    /// let message = MessageBuilder::new(
+    ///         ContactBuilder::new("from@example.com").build(),
+    ///         "Subject Line"
+    ///         )
+    ///         .content(Content::new("text/plain", "Email Body"))
+    ///         .personalization(
+    ///             PersonalizationBuilder::default()
+    ///                 .to(ContactBuilder::new("to@example.com").build())
+    ///                 .build()
+    ///         )
+    ///         .try_build_with_clock(&SystemClock)
+    ///         .unwrap();
+    /// ```
+    pub fn try_build_with_clock(self, clock: &impl Clock) -> Result<Message, ValidationErrors> {
+        let message = self.build();
+        message.validate_with_clock(clock)?;
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Message;
+    use crate::{
+        AsmBuilder, AttachmentBuilder, ContactBuilder, Content, MailSettingsBuilder,
+        MessageBuilder, PersonalizationBuilder, TrackingSettingsBuilder,
+    };
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn full_message() -> Message {
+        MessageBuilder::new(
+            ContactBuilder::new("from@example.com").name("from").build(),
+            "Subject Line",
+        )
+        .personalization(
+            PersonalizationBuilder::default()
+                .to(ContactBuilder::new("to@example.com").name("to").build())
+                .cc(ContactBuilder::new("cc@example.com").build())
+                .bcc(ContactBuilder::new("bcc@example.com").build())
+                .subject("Personal Subject")
+                .header("X-Header", "value")
+                .substitution("-name-", "To")
+                .dynamic_template_datum("first_name", "To")
+                .custom_arg("user_id", "42")
+                .send_at(UNIX_EPOCH + Duration::from_secs(1_600_000_000))
+                .build(),
+        )
+        .reply_to(ContactBuilder::new("reply@example.com").build())
+        .content(Content::new("text/plain", "Hello -name-"))
+        .content(Content::new("text/html", "<p>Hello -name-</p>"))
+        .attachment(
+            AttachmentBuilder::new("SGVsbG8gV29ybGQh", "file.txt")
+                .attachment_type("text/plain")
+                .disposition("inline")
+                .content_id("file")
+                .build(),
+        )
+        .template_id("0001")
+        .section("-section-", "Section")
+        .header("X-Message", "value")
+        .category("Marketing")
+        .custom_arg("campaign", "spring")
+        .send_at(UNIX_EPOCH + Duration::from_secs(1_600_000_000))
+        .batch_id("abc123")
+        .asm(AsmBuilder::new(1).group_to_display(2).build())
+        .ip_pool_name("marketing_pool")
+        .mail_settings(
+            MailSettingsBuilder::default()
+                .bcc("bcc@example.com")
+                .bypass_list_management()
+                .footer(
+                    Some(String::from("text")),
+                    Some(String::from("<p>html</p>")),
+                )
+                .sandbox_mode()
+                .spam_check(Some(5), Some(String::from("http://post_url")))
+                .build(),
+        )
+        .tracking_settings(
+            TrackingSettingsBuilder::default()
+                .click_tracking(true)
+                .open_tracking("[OPEN_TAG]")
+                .substitution_tag("[UNSUBSCRIBE]", None, Some(String::from("<p>unsub</p>")))
+                .build(),
+        )
+        .build()
+    }
+
+    #[test]
+    fn round_trips_full_message() {
+        let message = full_message();
+        let parsed = Message::from_json(&message.to_json()).unwrap();
+        assert_eq!(parsed, message);
+    }
+
+    #[test]
+    fn round_trips_minimal_message() {
+        let message =
+            MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "s").build();
+        assert_eq!(Message::from_json(&message.to_json()).unwrap(), message);
+    }
+
+    #[test]
+    fn parses_golden_json() {
+        let json = r#"{
+            "personalizations": [{"to": [{"email": "to@example.com"}]}],
+            "from": {"email": "from@example.com", "name": "from"},
+            "subject": "Subject Line",
+            "content": [{"type": "text/plain", "value": "Hello"}],
+            "attachments": [{"content": "SGVsbG8=", "type": "text/plain", "filename": "a.txt"}],
+            "asm": {"group_id": 1}
+        }"#;
+        let expected = MessageBuilder::new(
+            ContactBuilder::new("from@example.com").name("from").build(),
+            "Subject Line",
+        )
+        .personalization(
+            PersonalizationBuilder::default()
+                .to(ContactBuilder::new("to@example.com").build())
+                .build(),
+        )
+        .content(Content::new("text/plain", "Hello"))
+        .attachment(
+            AttachmentBuilder::new("SGVsbG8=", "a.txt")
+                .attachment_type("text/plain")
+                .build(),
+        )
+        .asm(AsmBuilder::new(1).build())
+        .build();
+        assert_eq!(Message::from_json(json).unwrap(), expected);
+    }
+
+    #[test]
+    fn derives_template_data() {
+        #[derive(serde::Serialize, sendgrid_rs_derive::TemplateData)]
+        #[sendgrid(template_id = "d-0123456789abcdef0123456789abcdef")]
+        struct Welcome<'a> {
+            first_name: &'a str,
+            #[serde(rename = "isMember")]
+            is_member: bool,
+        }
+
+        let message = MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "")
+            .template_personalization(
+                PersonalizationBuilder::default().to(ContactBuilder::new("to@example.com").build()),
+                &Welcome {
+                    first_name: "Alice",
+                    is_member: true,
+                },
+            )
+            .build();
+        assert_eq!(
+            message.template_id.as_deref(),
+            Some("d-0123456789abcdef0123456789abcdef")
+        );
+        assert_eq!(
+            message.personalizations[0].dynamic_template_data["first_name"],
+            "Alice"
+        );
+        assert_eq!(
+            message.personalizations[0].dynamic_template_data["isMember"],
+            true
+        );
+    }
 }
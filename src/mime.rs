@@ -0,0 +1,709 @@
+//! Renders a `Message` as the RFC 5322 / MIME document a single `Personalization` would receive,
+//! for previewing, archiving, or handing to an SMTP server. The output is a complete `.eml` file
+//! with CRLF line endings.
+//!
+//! Each `Content` becomes a part of a `multipart/alternative`, attachments with an `inline`
+//! disposition and a `content_id` are placed next to it in a `multipart/related`, and all other
+//! attachments are added in a `multipart/mixed`. The personalization's substitutions and the
+//! message's sections are applied to the subject and content, see the
+//! [substitution](../substitution/index.html) module.
+//!
+//! Header text containing a CR or LF is refused rather than written out, since it would start a
+//! new header. Custom headers can't replace the ones the renderer generates.
+//!
+//! # Examples
+//! ```
+//! # use sendgrid_rs::{ContactBuilder, Content, MessageBuilder, PersonalizationBuilder};
+//! # use sendgrid_rs::mime;
+//! let personalization = PersonalizationBuilder::default()
+//!     .to(ContactBuilder::new("to@example.com").build())
+//!     .substitution("-name-", "Alice")
+//!     .build();
+//! let message = MessageBuilder::new(
+//!     ContactBuilder::new("from@example.com").build(),
+//!     "Hello -name-",
+//! )
+//! .content(Content::new("text/plain", "Hi -name-!"))
+//! .build();
+//! let eml = mime::render(&message, &personalization).unwrap();
+//! assert!(eml.contains("Subject: Hello Alice\r\n"));
+//! ```
+
+use crate::attachment::Attachment;
+use crate::clock::{Clock, SystemClock};
+use crate::message::Message;
+use crate::personalization::Personalization;
+use crate::substitution::{self, SubstitutionError};
+use crate::validation;
+use crate::{Contact, Content};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::error::Error;
+use std::fmt;
+use std::hash::Hasher;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The reasons a `Message` can't be rendered
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum RenderError {
+    /// The `Message` has no `Content`, e.g. because it relies on a template stored at SendGrid
+    MissingContent,
+    /// An attachment's content is not valid base64
+    InvalidAttachment(String),
+    /// The substitutions or sections can't be applied
+    Substitution(SubstitutionError),
+    /// The named header, or a header of a part, contains a line break or its name is not a valid
+    /// header name
+    InvalidHeader(String),
+    /// A custom header would replace a header the renderer generates, or one of the
+    /// [RESERVED_HEADERS](../validation/constant.RESERVED_HEADERS.html)
+    ReservedHeader(String),
+    /// The named header has a line longer than SMTP's 998 octet limit that can't be folded
+    LineTooLong(String),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::MissingContent => write!(f, "the message has no content to render"),
+            RenderError::InvalidAttachment(filename) => {
+                write!(f, "attachment {} is not valid base64", filename)
+            }
+            RenderError::Substitution(e) => write!(f, "{}", e),
+            RenderError::InvalidHeader(name) => {
+                write!(f, "header {} contains a line break or invalid name", name)
+            }
+            RenderError::ReservedHeader(name) => {
+                write!(f, "header {} is generated and can't be set", name)
+            }
+            RenderError::LineTooLong(name) => {
+                write!(f, "header {} has a line too long to fold", name)
+            }
+        }
+    }
+}
+
+impl Error for RenderError {}
+
+/// Headers the renderer always generates, which custom headers can't replace
+const GENERATED_HEADERS: &[&str] = &["date", "message-id", "mime-version"];
+
+/// Renders the `Message` as received by the recipients of `personalization`, dated now
+pub fn render(message: &Message, personalization: &Personalization) -> Result<String, RenderError> {
+    render_with_clock(message, personalization, &SystemClock)
+}
+
+/// Renders the `Message` as received by the recipients of `personalization`, dated by `clock`
+pub fn render_with_clock(
+    message: &Message,
+    personalization: &Personalization,
+    clock: &impl Clock,
+) -> Result<String, RenderError> {
+    if message.content.is_empty() {
+        return Err(RenderError::MissingContent);
+    }
+    for attachment in &message.attachments {
+        if STANDARD
+            .decode(strip_whitespace(&attachment.content))
+            .is_err()
+        {
+            return Err(RenderError::InvalidAttachment(attachment.filename.clone()));
+        }
+    }
+
+    let now = clock.now();
+    let mut renderer = Renderer {
+        seed: seed(message, personalization, now),
+        boundaries: 0,
+    };
+    let substituted =
+        substitution::apply(message, personalization).map_err(RenderError::Substitution)?;
+    let subject = substituted.subject();
+    check_headers(message, personalization, subject, substituted.content())?;
+
+    let mut headers: Vec<(String, String)> = vec![
+        (String::from("From"), mailbox(&message.from)),
+        (String::from("Date"), format_date(now)),
+        (String::from("Subject"), encode_word(subject)),
+        (
+            String::from("Message-ID"),
+            format!(
+                "<{:016x}@{}>",
+                renderer.seed,
+                domain(&message.from.email).unwrap_or("localhost")
+            ),
+        ),
+    ];
+    if !personalization.to.is_empty() {
+        headers.push((String::from("To"), mailbox_list(&personalization.to)));
+    }
+    if !personalization.cc.is_empty() {
+        headers.push((String::from("Cc"), mailbox_list(&personalization.cc)));
+    }
+    if let Some(reply_to) = &message.reply_to {
+        headers.push((String::from("Reply-To"), mailbox(reply_to)));
+    }
+    let mut custom: Vec<_> = message
+        .headers
+        .iter()
+        .filter(|(k, _)| !personalization.headers.contains_key(*k))
+        .chain(personalization.headers.iter())
+        .collect();
+    custom.sort();
+    for (name, value) in custom {
+        headers.push((name.clone(), encode_word(value)));
+    }
+    headers.push((String::from("MIME-Version"), String::from("1.0")));
+
+    let body = renderer.body(message, substituted.content());
+    let mut eml = String::new();
+    for (name, value) in headers {
+        eml.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    eml.push_str(&body);
+    Ok(eml)
+}
+
+/// Refuses header text that would break out of its header, and custom headers that would replace
+/// generated ones
+fn check_headers(
+    message: &Message,
+    personalization: &Personalization,
+    subject: &str,
+    content: &[Content],
+) -> Result<(), RenderError> {
+    fn check(header: &str, text: &str) -> Result<(), RenderError> {
+        if text.contains(['\r', '\n']) {
+            Err(RenderError::InvalidHeader(header.to_string()))
+        } else {
+            Ok(())
+        }
+    }
+    fn check_contact(header: &str, contact: &Contact) -> Result<(), RenderError> {
+        check(header, &contact.email)?;
+        check(header, contact.name.as_deref().unwrap_or_default())
+    }
+
+    check("Subject", subject)?;
+    check_contact("From", &message.from)?;
+    if let Some(reply_to) = &message.reply_to {
+        check_contact("Reply-To", reply_to)?;
+    }
+    for contact in &personalization.to {
+        check_contact("To", contact)?;
+    }
+    for contact in &personalization.cc {
+        check_contact("Cc", contact)?;
+    }
+    for (name, value) in message.headers.iter().chain(&personalization.headers) {
+        let valid_name =
+            !name.is_empty() && name.bytes().all(|b| b.is_ascii_graphic() && b != b':');
+        if !valid_name {
+            return Err(RenderError::InvalidHeader(name.clone()));
+        }
+        if validation::is_reserved_header(name)
+            || GENERATED_HEADERS
+                .iter()
+                .any(|generated| name.eq_ignore_ascii_case(generated))
+        {
+            return Err(RenderError::ReservedHeader(name.clone()));
+        }
+        check(name, value)?;
+    }
+    for content in content {
+        check("Content-Type", &content.c_type)?;
+    }
+    for attachment in &message.attachments {
+        check("Content-Disposition", &attachment.filename)?;
+        check(
+            "Content-Type",
+            attachment.a_type.as_deref().unwrap_or_default(),
+        )?;
+        check(
+            "Content-ID",
+            attachment.content_id.as_deref().unwrap_or_default(),
+        )?;
+    }
+    Ok(())
+}
+
+struct Renderer {
+    seed: u64,
+    boundaries: u32,
+}
+
+impl Renderer {
+    fn body(&mut self, message: &Message, content: &[Content]) -> String {
+        let contents: Vec<String> = content.iter().map(content_part).collect();
+        let mut body = self.multipart("alternative", contents);
+
+        let (inline, attached): (Vec<&Attachment>, Vec<&Attachment>) =
+            message.attachments.iter().partition(|a| is_inline(a));
+        if !inline.is_empty() {
+            let mut parts = vec![body];
+            parts.extend(inline.into_iter().map(attachment_part));
+            body = self.multipart("related", parts);
+        }
+        if !attached.is_empty() {
+            let mut parts = vec![body];
+            parts.extend(attached.into_iter().map(attachment_part));
+            body = self.multipart("mixed", parts);
+        }
+        body
+    }
+
+    /// Wraps the parts in a multipart entity, or returns a lone part as is
+    fn multipart(&mut self, subtype: &str, mut parts: Vec<String>) -> String {
+        if parts.len() == 1 {
+            return parts.remove(0);
+        }
+        self.boundaries += 1;
+        let boundary = format!("=_{:016x}_{}", self.seed, self.boundaries);
+        let mut entity = format!(
+            "Content-Type: multipart/{}; boundary=\"{}\"\r\n\r\n",
+            subtype, boundary
+        );
+        for part in parts {
+            entity.push_str(&format!("--{}\r\n{}\r\n", boundary, part));
+        }
+        entity.push_str(&format!("--{}--\r\n", boundary));
+        entity
+    }
+}
+
+fn is_inline(attachment: &Attachment) -> bool {
+    attachment.content_id.is_some()
+        && attachment
+            .disposition
+            .as_deref()
+            .is_some_and(|d| d.eq_ignore_ascii_case("inline"))
+}
+
+fn content_part(content: &Content) -> String {
+    format!(
+        "Content-Type: {}; charset=utf-8\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\n{}\r\n",
+        without_charset(&content.c_type),
+        quoted_printable(&content.value)
+    )
+}
+
+/// Removes any `charset` parameter from a content type, since the value is always UTF-8
+fn without_charset(c_type: &str) -> String {
+    let mut params = c_type.split(';').map(str::trim);
+    let mut stripped = params.next().unwrap_or_default().to_string();
+    for param in params {
+        let name = param.split('=').next().unwrap_or_default().trim();
+        if !param.is_empty() && !name.eq_ignore_ascii_case("charset") {
+            stripped.push_str("; ");
+            stripped.push_str(param);
+        }
+    }
+    stripped
+}
+
+fn attachment_part(attachment: &Attachment) -> String {
+    let content_type = attachment
+        .a_type
+        .as_deref()
+        .unwrap_or("application/octet-stream");
+    let disposition = if is_inline(attachment) {
+        "inline"
+    } else {
+        "attachment"
+    };
+    let filename = quote(&encode_word(&attachment.filename));
+    let mut part = format!(
+        "Content-Type: {}; name={}\r\nContent-Disposition: {}; filename={}\r\nContent-Transfer-Encoding: base64\r\n",
+        content_type, filename, disposition, filename
+    );
+    if let Some(id) = &attachment.content_id {
+        part.push_str(&format!("Content-ID: <{}>\r\n", id));
+    }
+    part.push_str("\r\n");
+    let content = strip_whitespace(&attachment.content);
+    for line in content.as_bytes().chunks(76) {
+        part.push_str(std::str::from_utf8(line).unwrap_or_default());
+        part.push_str("\r\n");
+    }
+    part
+}
+
+fn strip_whitespace(text: &str) -> String {
+    text.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+fn domain(email: &str) -> Option<&str> {
+    email
+        .rsplit_once('@')
+        .map(|(_, d)| d)
+        .filter(|d| !d.is_empty())
+}
+
+/// Formats a `Contact` as an RFC 5322 mailbox, e.g. `"Name" <email>`
+pub(crate) fn mailbox(contact: &Contact) -> String {
+    match contact.name.as_deref().filter(|n| !n.is_empty()) {
+        Some(name) if name.is_ascii() => format!("{} <{}>", quote(name), contact.email),
+        Some(name) => format!("{} <{}>", encode_word(name), contact.email),
+        None => contact.email.clone(),
+    }
+}
+
+fn mailbox_list(contacts: &[Contact]) -> String {
+    contacts.iter().map(mailbox).collect::<Vec<_>>().join(", ")
+}
+
+/// Quotes a phrase if it contains characters that are special in headers
+fn quote(text: &str) -> String {
+    let special = text
+        .chars()
+        .any(|c| "()<>[]:;@\\,.\"".contains(c) || c.is_whitespace());
+    if special && !text.starts_with("=?") {
+        format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Encodes non-ASCII header text as RFC 2047 encoded words
+pub(crate) fn encode_word(text: &str) -> String {
+    if text.is_ascii() {
+        return text.to_string();
+    }
+    // Keep each encoded word within the 75 character limit without splitting a character
+    let mut words = vec![];
+    let mut chunk = String::new();
+    for c in text.chars() {
+        if chunk.len() + c.len_utf8() > 45 {
+            words.push(format!("=?UTF-8?B?{}?=", STANDARD.encode(&chunk)));
+            chunk.clear();
+        }
+        chunk.push(c);
+    }
+    words.push(format!("=?UTF-8?B?{}?=", STANDARD.encode(&chunk)));
+    words.join("\r\n ")
+}
+
+/// Encodes text as quoted-printable with CRLF line breaks
+fn quoted_printable(text: &str) -> String {
+    let mut encoded = String::new();
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            encoded.push_str("\r\n");
+        }
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let bytes = line.as_bytes();
+        let mut width = 0;
+        for (j, byte) in bytes.iter().enumerate() {
+            let last = j + 1 == bytes.len();
+            let literal = match byte {
+                b' ' | b'\t' => !last,
+                b'=' => false,
+                33..=126 => true,
+                _ => false,
+            };
+            let piece = if literal {
+                (*byte as char).to_string()
+            } else {
+                format!("={:02X}", byte)
+            };
+            if width + piece.len() > 75 {
+                encoded.push_str("=\r\n");
+                width = 0;
+            }
+            width += piece.len();
+            encoded.push_str(&piece);
+        }
+    }
+    encoded
+}
+
+/// Formats a time as an RFC 5322 date in UTC
+pub(crate) fn format_date(time: SystemTime) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let days = (secs / 86_400) as i64;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} +0000",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        secs % 86_400 / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Converts days since the Unix epoch to a (year, month, day) date in the proleptic Gregorian
+/// calendar
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// A stable hash of what is being rendered, used for the Message-ID and boundaries
+fn seed(message: &Message, personalization: &Personalization, now: SystemTime) -> u64 {
+    // FNV-1a, which is stable across runs unlike the standard library's default hasher
+    struct Fnv(u64);
+    impl Hasher for Fnv {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+        fn write(&mut self, bytes: &[u8]) {
+            for byte in bytes {
+                self.0 ^= u64::from(*byte);
+                self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+    }
+    let mut hasher = Fnv(0xcbf2_9ce4_8422_2325);
+    hasher.write(message.to_json().as_bytes());
+    hasher.write(
+        serde_json::to_string(personalization)
+            .unwrap_or_default()
+            .as_bytes(),
+    );
+    let nanos = now
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    hasher.write(&nanos.to_le_bytes());
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_date, quoted_printable, render_with_clock, without_charset, RenderError};
+    use crate::clock::{Clock, FakeClock};
+    use crate::{
+        AttachmentBuilder, ContactBuilder, Content, MessageBuilder, PersonalizationBuilder,
+    };
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(format_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 +0000");
+        assert_eq!(
+            format_date(FakeClock::at(1_600_000_000).now()),
+            "Sun, 13 Sep 2020 12:26:40 +0000"
+        );
+        assert_eq!(
+            format_date(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "Tue, 29 Feb 2000 00:00:00 +0000"
+        );
+    }
+
+    #[test]
+    fn encodes_quoted_printable() {
+        assert_eq!(quoted_printable("a=b \nc\u{e9}"), "a=3Db=20\r\nc=C3=A9");
+        let long = "x".repeat(100);
+        assert_eq!(
+            quoted_printable(&long),
+            format!("{}=\r\n{}", "x".repeat(75), "x".repeat(25))
+        );
+    }
+
+    #[test]
+    fn renders_nested_multiparts() {
+        let message = MessageBuilder::new(
+            ContactBuilder::new("from@example.com")
+                .name("Sender, Inc")
+                .build(),
+            "Hello -name-",
+        )
+        .reply_to(ContactBuilder::new("reply@example.com").build())
+        .header("X-Campaign", "spring")
+        .content(Content::new("text/plain", "Hi -name-"))
+        .content(Content::new("text/html", "<img src=\"cid:logo\">"))
+        .attachment(
+            AttachmentBuilder::new("iVBORw0KGgo=", "logo.png")
+                .attachment_type("image/png")
+                .disposition("inline")
+                .content_id("logo")
+                .build(),
+        )
+        .attachment(AttachmentBuilder::new("SGVsbG8gV29ybGQh", "file.txt").build())
+        .build();
+        let personalization = PersonalizationBuilder::default()
+            .to(ContactBuilder::new("to@example.com")
+                .name("Zo\u{eb}")
+                .build())
+            .cc(ContactBuilder::new("cc@example.com").build())
+            .bcc(ContactBuilder::new("hidden@example.com").build())
+            .substitution("-name-", "Zo\u{eb}")
+            .build();
+
+        let eml =
+            render_with_clock(&message, &personalization, &FakeClock::at(1_600_000_000)).unwrap();
+        assert!(eml.contains("From: \"Sender, Inc\" <from@example.com>\r\n"));
+        assert!(eml.contains("To: =?UTF-8?B?Wm/Dqw==?= <to@example.com>\r\n"));
+        assert!(eml.contains("Cc: cc@example.com\r\n"));
+        assert!(!eml.contains("hidden@example.com"));
+        assert!(eml.contains("Subject: =?UTF-8?B?SGVsbG8gWm/Dqw==?=\r\n"));
+        assert!(eml.contains("Date: Sun, 13 Sep 2020 12:26:40 +0000\r\n"));
+        assert!(eml.contains("X-Campaign: spring\r\n"));
+        assert!(eml.contains("MIME-Version: 1.0\r\n"));
+        assert!(eml.contains("Hi Zo=C3=AB\r\n"));
+
+        let mixed = eml.find("multipart/mixed").unwrap();
+        let related = eml.find("multipart/related").unwrap();
+        let alternative = eml.find("multipart/alternative").unwrap();
+        assert!(mixed < related && related < alternative);
+        assert!(eml.contains("Content-Disposition: inline; filename=\"logo.png\"\r\n"));
+        assert!(eml.contains("Content-ID: <logo>\r\n"));
+        assert!(eml.contains("Content-Disposition: attachment; filename=\"file.txt\"\r\n"));
+
+        assert_eq!(
+            render_with_clock(&message, &personalization, &FakeClock::at(1_600_000_000)).unwrap(),
+            eml
+        );
+    }
+
+    #[test]
+    fn refuses_line_breaks_in_headers() {
+        let personalization = PersonalizationBuilder::default()
+            .to(ContactBuilder::new("to@example.com").build())
+            .build();
+        let render = |message: MessageBuilder| {
+            render_with_clock(
+                &message.content(Content::new("text/plain", "Body")).build(),
+                &personalization,
+                &FakeClock::at(1_600_000_000),
+            )
+        };
+        let from = || ContactBuilder::new("from@example.com");
+
+        assert_eq!(
+            render(MessageBuilder::new(
+                from().build(),
+                "Hi\r\nBcc: x@evil.example"
+            )),
+            Err(RenderError::InvalidHeader(String::from("Subject")))
+        );
+        assert_eq!(
+            render(MessageBuilder::new(from().name("A\nB").build(), "Hi")),
+            Err(RenderError::InvalidHeader(String::from("From")))
+        );
+        assert_eq!(
+            render(MessageBuilder::new(from().build(), "Hi").header("X-Tag", "a\r\nBcc: x")),
+            Err(RenderError::InvalidHeader(String::from("X-Tag")))
+        );
+        assert_eq!(
+            render(MessageBuilder::new(from().build(), "Hi").header("X-A\r\nBcc", "x")),
+            Err(RenderError::InvalidHeader(String::from("X-A\r\nBcc")))
+        );
+        assert_eq!(
+            render(
+                MessageBuilder::new(from().build(), "Hi")
+                    .attachment(AttachmentBuilder::new("SGk=", "a.txt\r\nX-Evil: 1").build())
+            ),
+            Err(RenderError::InvalidHeader(String::from(
+                "Content-Disposition"
+            )))
+        );
+
+        let substituted = PersonalizationBuilder::default()
+            .to(ContactBuilder::new("to@example.com").build())
+            .substitution("-name-", "x\r\nBcc: y")
+            .build();
+        assert_eq!(
+            render_with_clock(
+                &MessageBuilder::new(from().build(), "Hi -name-")
+                    .content(Content::new("text/plain", "Body"))
+                    .build(),
+                &substituted,
+                &FakeClock::at(1_600_000_000)
+            ),
+            Err(RenderError::InvalidHeader(String::from("Subject")))
+        );
+    }
+
+    #[test]
+    fn refuses_to_replace_generated_headers() {
+        for name in [
+            "From",
+            "to",
+            "Date",
+            "Message-ID",
+            "MIME-Version",
+            "Content-Type",
+        ] {
+            let message = MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "s")
+                .header(name, "spoofed")
+                .content(Content::new("text/plain", "Body"))
+                .build();
+            assert_eq!(
+                render_with_clock(
+                    &message,
+                    &PersonalizationBuilder::default().build(),
+                    &FakeClock::at(1_600_000_000)
+                ),
+                Err(RenderError::ReservedHeader(name.to_string()))
+            );
+        }
+
+        let personalization = PersonalizationBuilder::default()
+            .header("Date", "Mon, 01 Jan 2001 00:00:00 +0000")
+            .build();
+        let message = MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "s")
+            .content(Content::new("text/plain", "Body"))
+            .build();
+        assert_eq!(
+            render_with_clock(&message, &personalization, &FakeClock::at(1_600_000_000)),
+            Err(RenderError::ReservedHeader(String::from("Date")))
+        );
+    }
+
+    #[test]
+    fn replaces_charset_with_utf8() {
+        assert_eq!(without_charset("text/plain"), "text/plain");
+        assert_eq!(
+            without_charset("text/plain; CHARSET = iso-8859-1; format=flowed"),
+            "text/plain; format=flowed"
+        );
+
+        let message = MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "s")
+            .content(Content::new("text/plain; charset=iso-8859-1", "Caf\u{e9}"))
+            .build();
+        let eml = render_with_clock(
+            &message,
+            &PersonalizationBuilder::default().build(),
+            &FakeClock::at(1_600_000_000),
+        )
+        .unwrap();
+        assert!(eml.contains("Content-Type: text/plain; charset=utf-8\r\n"));
+        assert!(!eml.contains("iso-8859-1"));
+        assert!(eml.contains("Caf=C3=A9\r\n"));
+    }
+
+    #[test]
+    fn requires_content() {
+        let message = MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "s")
+            .template_id("d-0001")
+            .build();
+        assert_eq!(
+            render_with_clock(
+                &message,
+                &PersonalizationBuilder::default().build(),
+                &FakeClock::at(1_600_000_000)
+            ),
+            Err(RenderError::MissingContent)
+        );
+    }
+}
@@ -0,0 +1,520 @@
+//! An in-process stand-in for SendGrid's API, for integration tests that can't reach the real
+//! thing. Enable the `mock` cargo feature to use this.
+//!
+//! A `MockServer` listens on a local port and implements `POST /v3/mail/send`. Requests without
+//! an API key are rejected with a 401, and bodies that don't parse as a `Message` or fail
+//! [Message::validate](../message/struct.Message.html#method.validate) are rejected with a 400 in
+//! SendGrid's error format. Bodies larger than
+//! [MAX_MESSAGE_SIZE](../validation/constant.MAX_MESSAGE_SIZE.html) are rejected with a 413
+//! without being read. Accepted messages are recorded for assertions. Point a
+//! [ClientBuilder](../client/struct.ClientBuilder.html) at `MockServer::base_url` to use it.
+//!
+//! # Examples
+//! ```
+//! # use sendgrid_rs::mock::MockServer;
+//! # use sendgrid_rs::ClientBuilder;
+//! # let transport = ();
+//! let server = MockServer::start().unwrap();
+//! let client = ClientBuilder::new("API KEY", transport)
+//!     .base_url(server.base_url())
+//!     .build();
+//! // Send messages with the client, then
+//! assert!(server.messages().is_empty());
+//! ```
+
+use crate::client::MAIL_SEND_PATH;
+use crate::message::Message;
+use crate::validation::MAX_MESSAGE_SIZE;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long the server waits on a stalled client before dropping the connection. Each connection
+/// is handled on its own thread, so this only frees that thread.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct State {
+    messages: Vec<Message>,
+    failures: VecDeque<u16>,
+}
+
+/// A local HTTP server that behaves like SendGrid's mail send endpoint. The server stops when
+/// this is dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Starts the server on a free local port
+    pub fn start() -> io::Result<MockServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State::default()));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let state = Arc::clone(&state);
+            let shutdown = Arc::clone(&shutdown);
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if shutdown.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        // A client that stalls or disconnects early only affects its own request
+                        let state = Arc::clone(&state);
+                        thread::spawn(move || handle_connection(stream, &state));
+                    }
+                }
+            })
+        };
+
+        Ok(MockServer {
+            addr,
+            state,
+            shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    /// The base URL to configure a `Client` with, e.g. `http://127.0.0.1:49152`
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Every `Message` accepted so far, in the order received
+    pub fn messages(&self) -> Vec<Message> {
+        self.state().messages.clone()
+    }
+
+    /// Makes the next request fail with `status` instead of being handled normally. Calls queue
+    /// up, so calling this twice fails the next two requests. 400, 401, 403, 413, 429 and 5xx
+    /// statuses respond with a body in SendGrid's error format, and 429 also sets the rate limit
+    /// headers.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::mock::MockServer;
+    ///
+    /// let server = MockServer::start().unwrap();
+    /// server.fail_next(429);
+    /// server.fail_next(500);
+    /// ```
+    pub fn fail_next(&self, status: u16) {
+        self.state().failures.push_back(status);
+    }
+
+    /// Forgets recorded messages and queued failures
+    pub fn reset(&self) {
+        let mut state = self.state();
+        state.messages.clear();
+        state.failures.clear();
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake the accept loop so it sees the shutdown flag
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl HttpRequest {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn content_length(&self) -> usize {
+        self.header("Content-Length")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+struct HttpResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl HttpResponse {
+    fn new(status: u16, body: impl Into<String>) -> Self {
+        HttpResponse {
+            status,
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        HttpResponse::errors(status, vec![(message.to_string(), None)])
+    }
+
+    fn errors(status: u16, errors: Vec<(String, Option<String>)>) -> Self {
+        let errors: Vec<_> = errors
+            .into_iter()
+            .map(|(message, field)| json!({ "message": message, "field": field, "help": null }))
+            .collect();
+        HttpResponse::new(status, json!({ "errors": errors }).to_string())
+    }
+}
+
+fn handle_connection(stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match read_request(&mut reader)? {
+        Some(request) => handle_request(&request, state),
+        None => return Ok(()),
+    };
+    write_response(stream, &response)
+}
+
+fn read_request(reader: &mut impl BufRead) -> io::Result<Option<HttpRequest>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = vec![];
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let mut request = HttpRequest {
+        method,
+        path,
+        headers,
+        body: vec![],
+    };
+    // An oversized body is left unread, handle_request rejects it
+    let length = request.content_length();
+    if length <= MAX_MESSAGE_SIZE {
+        request.body = vec![0; length];
+        reader.read_exact(&mut request.body)?;
+    }
+    Ok(Some(request))
+}
+
+fn handle_request(request: &HttpRequest, state: &Mutex<State>) -> HttpResponse {
+    if request.content_length() > MAX_MESSAGE_SIZE {
+        return failure(413);
+    }
+    let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(status) = state.failures.pop_front() {
+        return failure(status);
+    }
+
+    let path = request.path.split('?').next().unwrap_or_default();
+    if path != MAIL_SEND_PATH {
+        return HttpResponse::error(404, "not found");
+    }
+    if request.method != "POST" {
+        return HttpResponse::error(405, "method not allowed");
+    }
+    let authorized = request
+        .header("Authorization")
+        .and_then(|a| a.strip_prefix("Bearer "))
+        .is_some_and(|key| !key.trim().is_empty());
+    if !authorized {
+        return failure(401);
+    }
+
+    let message = match std::str::from_utf8(&request.body)
+        .map_err(|e| e.to_string())
+        .and_then(|body| Message::from_json(body).map_err(|e| e.to_string()))
+    {
+        Ok(message) => message,
+        Err(e) => return HttpResponse::error(400, &e),
+    };
+    if let Err(errors) = message.validate() {
+        return HttpResponse::errors(
+            400,
+            errors
+                .iter()
+                .map(|e| (e.kind().to_string(), Some(sendgrid_field(e.path()))))
+                .collect(),
+        );
+    }
+
+    state.messages.push(message);
+    HttpResponse::new(202, "")
+}
+
+/// Converts a `ValidationError` path into SendGrid's dotted field format
+fn sendgrid_field(path: &str) -> String {
+    path.replace('[', ".").replace(']', "")
+}
+
+fn failure(status: u16) -> HttpResponse {
+    let message = match status {
+        400 => "Bad Request",
+        401 => "The provided authorization grant is invalid, expired, or revoked",
+        403 => "access forbidden",
+        413 => "Payload Too Large",
+        429 => "too many requests",
+        500..=599 => "internal server error",
+        _ => "mock failure",
+    };
+    let mut response = HttpResponse::error(status, message);
+    if status == 429 {
+        let reset = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() + 1)
+            .unwrap_or_default();
+        response.headers = vec![
+            (String::from("X-RateLimit-Limit"), String::from("600")),
+            (String::from("X-RateLimit-Remaining"), String::from("0")),
+            (String::from("X-RateLimit-Reset"), reset.to_string()),
+        ];
+    }
+    response
+}
+
+fn write_response(mut stream: TcpStream, response: &HttpResponse) -> io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason(response.status),
+        response.body.len()
+    );
+    if !response.body.is_empty() {
+        head.push_str("Content-Type: application/json\r\n");
+    }
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(response.body.as_bytes())?;
+    stream.flush()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MockServer, READ_TIMEOUT};
+    use crate::client::{ClientBuilder, Error};
+    use crate::error::ErrorKind;
+    use crate::message::Message;
+    use crate::transport::{Request, Response, Transport};
+    use crate::validation::MAX_MESSAGE_SIZE;
+    use crate::{ContactBuilder, Content, MessageBuilder, PersonalizationBuilder};
+    use std::io::{self, Read, Write};
+    use std::net::TcpStream;
+    use std::time::Instant;
+
+    /// A minimal blocking HTTP/1.1 transport, so these tests don't need an HTTP library
+    struct RawTransport;
+
+    impl Transport for RawTransport {
+        type Error = io::Error;
+
+        fn send(&self, request: &Request) -> Result<Response, Self::Error> {
+            let url = request.url().trim_start_matches("http://");
+            let (host, path) = url.split_at(url.find('/').unwrap_or(url.len()));
+            let mut stream = TcpStream::connect(host)?;
+            let body = request.body_str().unwrap_or_default();
+            let mut head = format!(
+                "{} {} HTTP/1.1\r\nHost: {}\r\n",
+                request.method(),
+                path,
+                host
+            );
+            for (name, value) in request.headers() {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            head.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+            stream.write_all(head.as_bytes())?;
+
+            let mut raw = String::new();
+            stream.read_to_string(&mut raw)?;
+            let (head, body) = raw.split_once("\r\n\r\n").unwrap();
+            let mut lines = head.lines();
+            let status = lines.next().unwrap()[9..12].parse().unwrap();
+            let mut response = Response::new(status, body);
+            for line in lines {
+                let (name, value) = line.split_once(':').unwrap();
+                response = response.header(name.trim(), value.trim());
+            }
+            Ok(response)
+        }
+    }
+
+    fn message(to: &str) -> Message {
+        MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "Subject")
+            .content(Content::new("text/plain", "Body"))
+            .personalization(
+                PersonalizationBuilder::default()
+                    .to(ContactBuilder::new(to).build())
+                    .build(),
+            )
+            .build()
+    }
+
+    #[test]
+    fn records_accepted_messages() {
+        let server = MockServer::start().unwrap();
+        let client = ClientBuilder::new("KEY", RawTransport)
+            .base_url(server.base_url())
+            .build();
+        client.send(&message("a@example.com")).unwrap();
+        client.send(&message("b@example.com")).unwrap();
+        assert_eq!(
+            server.messages(),
+            vec![message("a@example.com"), message("b@example.com")]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_messages_like_sendgrid() {
+        let server = MockServer::start().unwrap();
+        let client = ClientBuilder::new("KEY", RawTransport)
+            .base_url(server.base_url())
+            .build();
+        let invalid = message("");
+        match client.send(&invalid) {
+            Err(Error::Api(e)) => {
+                assert_eq!(e.kind(), ErrorKind::Validation);
+                assert_eq!(e.errors()[0].field(), Some("personalizations.0.to.0.email"));
+                assert_eq!(e.errors()[0].locate(&invalid), Some("".into()));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(server.messages().is_empty());
+    }
+
+    #[test]
+    fn requires_api_key() {
+        let server = MockServer::start().unwrap();
+        let client = ClientBuilder::new("", RawTransport)
+            .base_url(server.base_url())
+            .build();
+        match client.send(&message("a@example.com")) {
+            Err(Error::Api(e)) => assert_eq!(e.kind(), ErrorKind::Auth),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn returns_queued_failures() {
+        let server = MockServer::start().unwrap();
+        let client = ClientBuilder::new("KEY", RawTransport)
+            .base_url(server.base_url())
+            .build();
+        server.fail_next(429);
+        server.fail_next(500);
+        match client.send(&message("a@example.com")) {
+            Err(Error::Api(e)) => assert_eq!(e.kind(), ErrorKind::RateLimited),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match client.send(&message("a@example.com")) {
+            Err(Error::Api(e)) => assert_eq!(e.kind(), ErrorKind::Server),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        client.send(&message("a@example.com")).unwrap();
+        assert_eq!(server.messages().len(), 1);
+    }
+
+    #[test]
+    fn rejects_oversized_bodies_unread() {
+        let server = MockServer::start().unwrap();
+        let mut stream =
+            TcpStream::connect(server.base_url().trim_start_matches("http://")).unwrap();
+        write!(
+            stream,
+            "POST /v3/mail/send HTTP/1.1\r\nAuthorization: Bearer KEY\r\nContent-Length: {}\r\n\r\n",
+            usize::MAX
+        )
+        .unwrap();
+        let mut raw = String::new();
+        stream.read_to_string(&mut raw).unwrap();
+        assert!(raw.starts_with("HTTP/1.1 413 "), "{}", raw);
+
+        let mut stream =
+            TcpStream::connect(server.base_url().trim_start_matches("http://")).unwrap();
+        write!(
+            stream,
+            "POST /v3/mail/send HTTP/1.1\r\nAuthorization: Bearer KEY\r\nContent-Length: {}\r\n\r\n",
+            MAX_MESSAGE_SIZE + 1
+        )
+        .unwrap();
+        let mut raw = String::new();
+        stream.read_to_string(&mut raw).unwrap();
+        assert!(raw.starts_with("HTTP/1.1 413 "), "{}", raw);
+        assert!(server.messages().is_empty());
+    }
+
+    #[test]
+    fn stalled_client_does_not_block_others() {
+        let server = MockServer::start().unwrap();
+        let mut stalled =
+            TcpStream::connect(server.base_url().trim_start_matches("http://")).unwrap();
+        stalled
+            .write_all(b"POST /v3/mail/send HTTP/1.1\r\n")
+            .unwrap();
+
+        let client = ClientBuilder::new("KEY", RawTransport)
+            .base_url(server.base_url())
+            .build();
+        let start = Instant::now();
+        client.send(&message("a@example.com")).unwrap();
+        assert!(start.elapsed() < READ_TIMEOUT / 5);
+        assert_eq!(server.messages().len(), 1);
+    }
+}
@@ -1,29 +1,39 @@
-use crate::Contact;
-use serde::Serialize;
+use crate::clock;
+use crate::{Contact, TemplateData};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
+use std::time::SystemTime;
 
 /// Used to structure and serialize the personalization node in Sendgrid's API call. Use
 /// `PersonalizationBuilder` to construct this.
-#[derive(Serialize, Default, Debug)]
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
 pub struct Personalization {
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    to: Vec<Contact>,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    cc: Vec<Contact>,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    bcc: Vec<Contact>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) to: Vec<Contact>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) cc: Vec<Contact>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) bcc: Vec<Contact>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    subject: Option<String>,
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
-    headers: HashMap<String, String>,
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
-    substitutions: HashMap<String, String>,
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
-    dynamic_template_data: HashMap<String, String>,
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
-    custom_args: HashMap<String, String>,
+    pub(crate) subject: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) headers: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) substitutions: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) dynamic_template_data: HashMap<String, Value>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) custom_args: HashMap<String, String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    send_at: Option<i32>,
+    pub(crate) send_at: Option<u64>,
+}
+
+impl Personalization {
+    /// The number of recipients in this `Personalization`, counting to, cc and bcc
+    pub(crate) fn recipient_count(&self) -> usize {
+        self.to.len() + self.cc.len() + self.bcc.len()
+    }
 }
 
 /// Builder pattern for `Personalization`. Make sure you call `build()` when done to consume this
@@ -149,47 +159,136 @@ impl PersonalizationBuilder {
         self
     }
 
-    /// Add a single dynamic template substitution
+    /// Add a single dynamic template substitution. The value can be anything that converts into a
+    /// JSON value, such as a string, number, bool, or a `serde_json::Value` built with
+    /// `serde_json::json!` for nested objects and arrays.
     ///
     /// # Parameters
     /// key: impl Into<String>
-    /// value: impl Into<String>
+    /// value: impl Into<serde_json::Value>
     ///
     /// # Examples
     /// ```
     /// # use sendgrid_rs::PersonalizationBuilder;
+    /// # use serde_json::json;
     ///
     /// let builder = PersonalizationBuilder::default()
-    ///               .dynamic_template_datum("Key", "Value");
+    ///               .dynamic_template_datum("Key", "Value")
+    ///               .dynamic_template_datum("is_member", true)
+    ///               .dynamic_template_datum("items", json!([{"name": "Widget", "qty": 2}]));
     /// ```
-    pub fn dynamic_template_datum<S: Into<String>>(mut self, key: S, value: S) -> Self {
+    pub fn dynamic_template_datum(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+    ) -> Self {
         self.personalization
             .dynamic_template_data
             .insert(key.into(), value.into());
         self
     }
 
-    /// Assign multiple dynamic template substitutions, overwriting all synamic template
-    /// substitutions with supplied data
+    /// Assign multiple dynamic template substitutions, overwriting all dynamic template
+    /// substitutions with supplied data. The data can be any collection of key value pairs whose
+    /// values convert into JSON values, such as a `HashMap<String, String>` or a
+    /// `serde_json::Map`. Use `try_dynamic_template_data` or `template_data` for a struct.
     ///
     /// # Parameters
-    /// data: HashMap<String, String>
+    /// data: impl IntoIterator<Item = (impl Into<String>, impl Into<serde_json::Value>)>
     ///
     /// # Examples
     /// ```
     /// # use sendgrid_rs::PersonalizationBuilder;
+    /// # use serde_json::json;
     /// # use std::collections::HashMap;
     ///
     /// let substitutions: HashMap<String, String> = HashMap::new();
     /// let builder = PersonalizationBuilder::default()
     ///               .dynamic_template_data(substitutions);
+    ///
+    /// let builder = PersonalizationBuilder::default()
+    ///               .dynamic_template_data(vec![("number", json!(1)), ("items", json!(["Widget"]))]);
     /// ```
-    pub fn dynamic_template_data(mut self, data: HashMap<String, String>) -> Self {
-        self.personalization.dynamic_template_data = data;
+    pub fn dynamic_template_data<K, V>(mut self, data: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<Value>,
+    {
+        self.personalization.dynamic_template_data = data
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect();
         self
     }
 
-    /// Set a custom_arg
+    /// Assign multiple dynamic template substitutions from any type that serializes into a JSON
+    /// object, such as a struct deriving `Serialize`, overwriting all dynamic template
+    /// substitutions. Returns an error if `data` fails to serialize or does not serialize into a
+    /// JSON object.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::PersonalizationBuilder;
+    /// # use serde::Serialize;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Order {
+    ///     number: u32,
+    ///     items: Vec<String>,
+    /// }
+    ///
+    /// let builder = PersonalizationBuilder::default()
+    ///     .try_dynamic_template_data(Order { number: 1, items: vec![String::from("Widget")] })
+    ///     .unwrap();
+    ///
+    /// assert!(PersonalizationBuilder::default()
+    ///     .try_dynamic_template_data(vec![1, 2, 3])
+    ///     .is_err());
+    /// ```
+    pub fn try_dynamic_template_data<T: Serialize>(
+        mut self,
+        data: T,
+    ) -> Result<Self, serde_json::Error> {
+        match serde_json::to_value(data)? {
+            Value::Object(map) => {
+                self.personalization.dynamic_template_data = map.into_iter().collect();
+                Ok(self)
+            }
+            _ => Err(serde::ser::Error::custom(
+                "dynamic template data must serialize into a JSON object",
+            )),
+        }
+    }
+
+    /// Assign the dynamic template substitutions from a `TemplateData` type, overwriting all
+    /// dynamic template substitutions. Use `MessageBuilder::template_personalization` to also set
+    /// the template id the type is tied to.
+    ///
+    /// # Panics
+    /// Panics if a hand-written `TemplateData` impl does not serialize into a JSON object.
+    /// `#[derive(TemplateData)]` only accepts structs with named fields, which always do.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::{PersonalizationBuilder, TemplateData};
+    /// # use serde::Serialize;
+    /// #[derive(Serialize)]
+    /// struct Welcome {
+    ///     first_name: String,
+    /// }
+    ///
+    /// impl TemplateData for Welcome {}
+    ///
+    /// let builder = PersonalizationBuilder::default()
+    ///               .template_data(&Welcome { first_name: String::from("Alice") });
+    /// ```
+    pub fn template_data<T: TemplateData>(self, data: &T) -> Self {
+        self.try_dynamic_template_data(data)
+            .expect("template data must serialize into a JSON object")
+    }
+
+    /// Set a custom_arg. See [EVENT_FIELDS](../webhook/constant.EVENT_FIELDS.html) for keys to
+    /// avoid.
     ///
     /// # Parameters
     /// key: impl Into<String>
@@ -209,17 +308,20 @@ impl PersonalizationBuilder {
         self
     }
 
-    /// Set a send_at time in seconds
+    /// Schedules this personalization to be sent at `time`, overriding the `Message`'s send_at.
+    /// SendGrid accepts times up to 72 hours ahead. Anything that converts into a `SystemTime`
+    /// works, including `chrono::DateTime` and `time::OffsetDateTime`.
     ///
     /// # Examples
     /// ```
     /// # use sendgrid_rs::PersonalizationBuilder;
+    /// # use std::time::{Duration, SystemTime};
     ///
     /// let builder = PersonalizationBuilder::default()
-    ///               .send_at(3600);
+    ///               .send_at(SystemTime::now() + Duration::from_secs(3600));
     /// ```
-    pub fn send_at(mut self, time: i32) -> Self {
-        self.personalization.send_at = Some(time);
+    pub fn send_at(mut self, time: impl Into<SystemTime>) -> Self {
+        self.personalization.send_at = Some(clock::unix_seconds(time.into()));
         self
     }
 
@@ -236,3 +338,50 @@ impl PersonalizationBuilder {
         self.personalization
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::PersonalizationBuilder;
+    use serde::Serialize;
+    use serde_json::json;
+
+    #[test]
+    fn serializes_structured_dynamic_template_data() {
+        #[derive(Serialize)]
+        struct Item {
+            name: &'static str,
+            price: f64,
+        }
+
+        #[derive(Serialize)]
+        struct Order {
+            number: u32,
+            paid: bool,
+            items: Vec<Item>,
+        }
+
+        let personalization = PersonalizationBuilder::default()
+            .try_dynamic_template_data(Order {
+                number: 7,
+                paid: true,
+                items: vec![Item {
+                    name: "Widget",
+                    price: 9.5,
+                }],
+            })
+            .unwrap()
+            .dynamic_template_datum("first_name", "Alice")
+            .build();
+        assert_eq!(
+            serde_json::to_value(&personalization).unwrap(),
+            json!({
+                "dynamic_template_data": {
+                    "number": 7,
+                    "paid": true,
+                    "items": [{"name": "Widget", "price": 9.5}],
+                    "first_name": "Alice"
+                }
+            })
+        );
+    }
+}
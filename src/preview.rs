@@ -0,0 +1,737 @@
+//! Renders dynamic templates locally, so what recipients will see can be checked without calling
+//! SendGrid. Enabled with the `handlebars` feature.
+//!
+//! Templates use SendGrid's Handlebars dialect: substitutions, `if`, `unless` and `each`, plus
+//! SendGrid's `equals`, `notEquals`, `greaterThan`, `lessThan`, `and`, `or`, `length`, `insert`
+//! and `formatDate` helpers. Unlike SendGrid, a substitution with no matching dynamic template
+//! data is an error rather than an empty string, so a mistyped key fails loudly.
+
+use crate::mime::civil_from_days;
+use crate::personalization::Personalization;
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext, RenderError,
+    RenderErrorReason, Renderable, ScopedJson,
+};
+use serde_json::{Map, Value};
+use std::error::Error;
+use std::fmt;
+
+/// The part of a `DynamicTemplate` being rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Part {
+    /// The subject line
+    Subject,
+    /// The HTML body
+    Html,
+    /// The plain text body
+    Plain,
+}
+
+impl fmt::Display for Part {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Part::Subject => write!(f, "subject"),
+            Part::Html => write!(f, "html"),
+            Part::Plain => write!(f, "plain"),
+        }
+    }
+}
+
+/// The kind of problem a `DynamicTemplate` failed to render with
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum PreviewErrorKind {
+    /// The template is not valid Handlebars
+    Syntax(String),
+    /// The template references a variable the dynamic template data does not contain
+    MissingVariable(String),
+    /// A helper was misused, such as an unknown helper or a parameter of the wrong type
+    Helper(String),
+}
+
+impl fmt::Display for PreviewErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreviewErrorKind::Syntax(message) => write!(f, "invalid template: {}", message),
+            PreviewErrorKind::MissingVariable(path) => {
+                write!(f, "no dynamic template data for {}", path)
+            }
+            PreviewErrorKind::Helper(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Returned when a `DynamicTemplate` fails to render, with where in the template it failed
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreviewError {
+    part: Part,
+    kind: PreviewErrorKind,
+    line: Option<usize>,
+    column: Option<usize>,
+}
+
+impl PreviewError {
+    fn new(part: Part, error: RenderError) -> Self {
+        let (line, column) = (error.line_no, error.column_no);
+        let kind = match error.reason() {
+            RenderErrorReason::MissingVariable(path) => PreviewErrorKind::MissingVariable(
+                path.clone()
+                    .unwrap_or_else(|| String::from("a helper parameter")),
+            ),
+            RenderErrorReason::TemplateError(error) => {
+                PreviewErrorKind::Syntax(error.reason().to_string())
+            }
+            reason => PreviewErrorKind::Helper(reason.to_string()),
+        };
+        PreviewError {
+            part,
+            kind,
+            line,
+            column,
+        }
+    }
+
+    /// The part of the template that failed to render
+    pub fn part(&self) -> Part {
+        self.part
+    }
+
+    /// What went wrong
+    pub fn kind(&self) -> &PreviewErrorKind {
+        &self.kind
+    }
+
+    /// The line of the template the error is on, if known
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+
+    /// The column of the template the error is on, if known
+    pub fn column(&self) -> Option<usize> {
+        self.column
+    }
+}
+
+impl fmt::Display for PreviewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(
+                f,
+                "{} line {}, column {}: {}",
+                self.part, line, column, self.kind
+            ),
+            _ => write!(f, "{}: {}", self.part, self.kind),
+        }
+    }
+}
+
+impl Error for PreviewError {}
+
+/// The parts of a SendGrid dynamic template: a subject line and HTML and plain text bodies. Use
+/// `DynamicTemplateBuilder` to construct this.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DynamicTemplate {
+    subject: Option<String>,
+    html: Option<String>,
+    plain: Option<String>,
+}
+
+impl DynamicTemplate {
+    /// Renders every part of the template with a `Personalization`'s dynamic template data.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::PersonalizationBuilder;
+    /// # use sendgrid_rs::preview::DynamicTemplateBuilder;
+    ///
+    /// let template = DynamicTemplateBuilder::default()
+    ///     .subject("Welcome, {{insert first_name \"default=friend\"}}!")
+    ///     .html("<p>{{#equals plan \"pro\"}}Thanks for going pro.{{else}}Hi.{{/equals}}</p>")
+    ///     .build();
+    /// let personalization = PersonalizationBuilder::default()
+    ///     .dynamic_template_datum("plan", "pro")
+    ///     .build();
+    ///
+    /// let rendered = template.render(&personalization).unwrap();
+    /// assert_eq!(rendered.subject(), Some("Welcome, friend!"));
+    /// assert_eq!(rendered.html(), Some("<p>Thanks for going pro.</p>"));
+    /// assert_eq!(rendered.plain(), None);
+    /// ```
+    pub fn render(&self, personalization: &Personalization) -> Result<Rendered, PreviewError> {
+        let data: Map<String, Value> = personalization
+            .dynamic_template_data
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        let data = Value::Object(data);
+        let render = |part, template: &Option<String>| {
+            template
+                .as_ref()
+                .map(|template| render(part, template, &data))
+                .transpose()
+        };
+        Ok(Rendered {
+            subject: render(Part::Subject, &self.subject)?,
+            html: render(Part::Html, &self.html)?,
+            plain: render(Part::Plain, &self.plain)?,
+        })
+    }
+}
+
+/// Builder pattern for `DynamicTemplate`. Make sure you call `build()` when done to consume this
+/// and return the underlying `DynamicTemplate`. Use default() to construct.
+#[derive(Default)]
+pub struct DynamicTemplateBuilder {
+    template: DynamicTemplate,
+}
+
+impl DynamicTemplateBuilder {
+    /// Sets the subject line template
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::preview::DynamicTemplateBuilder;
+    ///
+    /// let builder = DynamicTemplateBuilder::default()
+    ///               .subject("Your order {{order_number}}");
+    /// ```
+    pub fn subject(mut self, template: impl Into<String>) -> Self {
+        self.template.subject = Some(template.into());
+        self
+    }
+
+    /// Sets the HTML body template. Substitutions are HTML escaped unless they use triple braces.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::preview::DynamicTemplateBuilder;
+    ///
+    /// let builder = DynamicTemplateBuilder::default()
+    ///               .html("<p>Hello {{first_name}}</p>");
+    /// ```
+    pub fn html(mut self, template: impl Into<String>) -> Self {
+        self.template.html = Some(template.into());
+        self
+    }
+
+    /// Sets the plain text body template
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::preview::DynamicTemplateBuilder;
+    ///
+    /// let builder = DynamicTemplateBuilder::default()
+    ///               .plain("Hello {{first_name}}");
+    /// ```
+    pub fn plain(mut self, template: impl Into<String>) -> Self {
+        self.template.plain = Some(template.into());
+        self
+    }
+
+    /// Consume the builder and return the underlying `DynamicTemplate`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::preview::DynamicTemplateBuilder;
+    ///
+    /// let template = DynamicTemplateBuilder::default().build();
+    /// ```
+    pub fn build(self) -> DynamicTemplate {
+        self.template
+    }
+}
+
+/// The rendered parts of a `DynamicTemplate`. A part is `None` if the template does not have it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rendered {
+    subject: Option<String>,
+    html: Option<String>,
+    plain: Option<String>,
+}
+
+impl Rendered {
+    /// The rendered subject line
+    pub fn subject(&self) -> Option<&str> {
+        self.subject.as_deref()
+    }
+
+    /// The rendered HTML body
+    pub fn html(&self) -> Option<&str> {
+        self.html.as_deref()
+    }
+
+    /// The rendered plain text body
+    pub fn plain(&self) -> Option<&str> {
+        self.plain.as_deref()
+    }
+}
+
+fn render(part: Part, template: &str, data: &Value) -> Result<String, PreviewError> {
+    let mut registry = Handlebars::new();
+    registry.set_strict_mode(true);
+    if part != Part::Html {
+        registry.register_escape_fn(handlebars::no_escape);
+    }
+    registry.register_helper("equals", Box::new(Condition::Equals));
+    registry.register_helper("notEquals", Box::new(Condition::NotEquals));
+    registry.register_helper("greaterThan", Box::new(Condition::GreaterThan));
+    registry.register_helper("lessThan", Box::new(Condition::LessThan));
+    registry.register_helper("and", Box::new(Condition::And));
+    registry.register_helper("or", Box::new(Condition::Or));
+    registry.register_helper("length", Box::new(Length));
+    registry.register_helper("insert", Box::new(Insert));
+    registry.register_helper("formatDate", Box::new(FormatDate));
+    registry
+        .render_template(template, data)
+        .map_err(|e| PreviewError::new(part, e))
+}
+
+/// A block helper rendering its block if its parameters satisfy a condition, and its `else`
+/// block otherwise. Missing variables count as null so optional data can be tested.
+enum Condition {
+    Equals,
+    NotEquals,
+    GreaterThan,
+    LessThan,
+    And,
+    Or,
+}
+
+impl Condition {
+    fn name(&self) -> &'static str {
+        match self {
+            Condition::Equals => "equals",
+            Condition::NotEquals => "notEquals",
+            Condition::GreaterThan => "greaterThan",
+            Condition::LessThan => "lessThan",
+            Condition::And => "and",
+            Condition::Or => "or",
+        }
+    }
+
+    fn test(&self, params: &[&Value]) -> Result<bool, RenderError> {
+        let pair = || match params {
+            [a, b] => Ok((*a, *b)),
+            _ => Err(RenderErrorReason::Other(format!(
+                "{} takes 2 parameters, got {}",
+                self.name(),
+                params.len()
+            ))),
+        };
+        Ok(match self {
+            Condition::Equals => loosely_equal(pair()?),
+            Condition::NotEquals => !loosely_equal(pair()?),
+            Condition::GreaterThan => {
+                let (a, b) = numbers(self.name(), pair()?)?;
+                a > b
+            }
+            Condition::LessThan => {
+                let (a, b) = numbers(self.name(), pair()?)?;
+                a < b
+            }
+            Condition::And => params.iter().all(|v| truthy(v)),
+            Condition::Or => params.iter().any(|v| truthy(v)),
+        })
+    }
+}
+
+impl HelperDef for Condition {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        r: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let params: Vec<&Value> = h.params().iter().map(|p| p.value()).collect();
+        let block = if self.test(&params)? {
+            h.template()
+        } else {
+            h.inverse()
+        };
+        match block {
+            Some(block) => block.render(r, ctx, rc, out),
+            None => Ok(()),
+        }
+    }
+}
+
+/// `{{length items}}`, the number of elements in an array or object or characters in a string
+struct Length;
+
+impl HelperDef for Length {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let param = required(h, "length", 0)?;
+        let length = match param {
+            Value::Array(items) => items.len(),
+            Value::Object(fields) => fields.len(),
+            Value::String(s) => s.chars().count(),
+            Value::Null => 0,
+            _ => return Err(RenderErrorReason::InvalidParamType("array").into()),
+        };
+        Ok(ScopedJson::Derived(Value::from(length)))
+    }
+}
+
+/// `{{insert name "default=Customer"}}`, a substitution with a fallback for missing or empty data
+struct Insert;
+
+impl HelperDef for Insert {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let value = h
+            .param(0)
+            .ok_or(RenderErrorReason::ParamNotFoundForIndex("insert", 0))?;
+        let default = match h.param(1).map(|p| p.value()) {
+            Some(Value::String(s)) => s.strip_prefix("default=").unwrap_or(s).to_owned(),
+            Some(_) => return Err(RenderErrorReason::InvalidParamType("string").into()),
+            None => String::new(),
+        };
+        let value = value.value();
+        if value.is_null() || value.as_str() == Some("") {
+            Ok(ScopedJson::Derived(Value::String(default)))
+        } else {
+            Ok(ScopedJson::Derived(value.clone()))
+        }
+    }
+}
+
+/// `{{formatDate timestamp "MMMM D, YYYY" "-0800"}}`, formats an ISO 8601 date or a Unix
+/// timestamp in seconds, optionally shifted to a UTC offset
+struct FormatDate;
+
+impl HelperDef for FormatDate {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let invalid = |message: String| RenderError::from(RenderErrorReason::Other(message));
+        let timestamp = match required(h, "formatDate", 0)? {
+            Value::Number(n) => n
+                .as_i64()
+                .ok_or_else(|| invalid(format!("invalid date {}", n)))?,
+            Value::String(s) => {
+                parse_iso8601(s).ok_or_else(|| invalid(format!("invalid date {:?}", s)))?
+            }
+            _ => return Err(RenderErrorReason::InvalidParamType("date").into()),
+        };
+        let format = match required(h, "formatDate", 1)? {
+            Value::String(s) => s,
+            _ => return Err(RenderErrorReason::InvalidParamType("string").into()),
+        };
+        let offset = match h.param(2).map(|p| p.value()) {
+            None => 0,
+            Some(Value::String(s)) => {
+                parse_offset(s).ok_or_else(|| invalid(format!("invalid offset {:?}", s)))?
+            }
+            Some(_) => return Err(RenderErrorReason::InvalidParamType("string").into()),
+        };
+        Ok(ScopedJson::Derived(Value::String(format_date(
+            timestamp, offset, format,
+        ))))
+    }
+}
+
+/// Parameter `index` of a helper, which must be present in the data
+fn required<'a>(
+    h: &'a Helper<'_>,
+    name: &'static str,
+    index: usize,
+) -> Result<&'a Value, RenderError> {
+    let param = h
+        .param(index)
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex(name, index))?;
+    if param.is_value_missing() {
+        return Err(RenderError::strict_error(param.relative_path()));
+    }
+    Ok(param.value())
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(_) => true,
+    }
+}
+
+/// Equality that, like SendGrid, treats `"1"` and `1` as equal
+fn loosely_equal((a, b): (&Value, &Value)) -> bool {
+    match (a, b) {
+        (Value::String(s), Value::Number(n)) | (Value::Number(n), Value::String(s)) => {
+            s.trim().parse::<f64>().ok() == n.as_f64()
+        }
+        (Value::Number(x), Value::Number(y)) => x.as_f64() == y.as_f64(),
+        _ => a == b,
+    }
+}
+
+fn numbers(name: &'static str, (a, b): (&Value, &Value)) -> Result<(f64, f64), RenderError> {
+    let number = |v: &Value| match v {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    };
+    match (number(a), number(b)) {
+        (Some(a), Some(b)) => Ok((a, b)),
+        _ => Err(RenderErrorReason::Other(format!(
+            "{} compares numbers, got {} and {}",
+            name, a, b
+        ))
+        .into()),
+    }
+}
+
+/// Parses `YYYY-MM-DD` optionally followed by `THH:MM[:SS[.fff]]` and `Z` or an offset, returning
+/// seconds since the Unix epoch
+fn parse_iso8601(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let (date, time) = match s.find(['T', ' ']) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    let mut fields = date.splitn(3, '-');
+    let year: i64 = fields.next()?.parse().ok()?;
+    let month: u32 = fields.next()?.parse().ok()?;
+    let day: u32 = fields.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut seconds = days_from_civil(year, month, day) * 86_400;
+    if let Some(time) = time {
+        let (clock, offset) = match time.find(['Z', '+', '-']) {
+            Some(i) if &time[i..] == "Z" => (&time[..i], 0),
+            Some(i) => (&time[..i], parse_offset(&time[i..])?),
+            None => (time, 0),
+        };
+        let clock = clock.split('.').next()?;
+        let mut parts = clock.split(':');
+        let hours: i64 = parts.next()?.parse().ok()?;
+        let minutes: i64 = parts.next()?.parse().ok()?;
+        let secs: i64 = parts.next().map_or(Some(0), |s| s.parse().ok())?;
+        seconds += hours * 3600 + minutes * 60 + secs - offset;
+    }
+    Some(seconds)
+}
+
+/// Parses a UTC offset such as `-0800`, `+05:30` or `Z` into seconds
+fn parse_offset(s: &str) -> Option<i64> {
+    if s == "Z" {
+        return Some(0);
+    }
+    let sign = match s.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = s[1..].chars().filter(|c| *c != ':').collect();
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i64 = digits[..2].parse().ok()?;
+    let minutes: i64 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Days since the Unix epoch of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let month = i64::from(month);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Formats a timestamp with SendGrid's date tokens, such as `YYYY`, `MMMM`, `dddd`, `hh` and `A`
+fn format_date(timestamp: i64, offset: i64, format: &str) -> String {
+    const MONTHS: [&str; 12] = [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ];
+    const DAYS: [&str; 7] = [
+        "Sunday",
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+        "Thursday",
+        "Friday",
+        "Saturday",
+    ];
+    // Longest first so `MMMM` is not read as two `MM`
+    const TOKENS: [&str; 21] = [
+        "YYYY", "MMMM", "dddd", "MMM", "ddd", "YY", "MM", "DD", "HH", "hh", "mm", "ss", "ZZ", "M",
+        "D", "H", "h", "m", "s", "A", "a",
+    ];
+
+    let local = timestamp + offset;
+    let days = local.div_euclid(86_400);
+    let secs = local.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    let (hour, minute, second) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    let hour12 = if hour % 12 == 0 { 12 } else { hour % 12 };
+    let weekday = DAYS[(days + 4).rem_euclid(7) as usize];
+    let month_name = MONTHS[month as usize - 1];
+    let sign = if offset < 0 { '-' } else { '+' };
+    let (offset_hours, offset_minutes) = (offset.abs() / 3600, offset.abs() % 3600 / 60);
+
+    let mut out = String::new();
+    let mut rest = format;
+    while !rest.is_empty() {
+        match TOKENS.iter().find(|t| rest.starts_with(*t)) {
+            Some(token) => {
+                out.push_str(&match *token {
+                    "YYYY" => format!("{:04}", year),
+                    "YY" => format!("{:02}", year.rem_euclid(100)),
+                    "MMMM" => String::from(month_name),
+                    "MMM" => String::from(&month_name[..3]),
+                    "MM" => format!("{:02}", month),
+                    "M" => month.to_string(),
+                    "DD" => format!("{:02}", day),
+                    "D" => day.to_string(),
+                    "dddd" => String::from(weekday),
+                    "ddd" => String::from(&weekday[..3]),
+                    "HH" => format!("{:02}", hour),
+                    "H" => hour.to_string(),
+                    "hh" => format!("{:02}", hour12),
+                    "h" => hour12.to_string(),
+                    "mm" => format!("{:02}", minute),
+                    "m" => minute.to_string(),
+                    "ss" => format!("{:02}", second),
+                    "s" => second.to_string(),
+                    "A" => String::from(if hour < 12 { "AM" } else { "PM" }),
+                    "a" => String::from(if hour < 12 { "am" } else { "pm" }),
+                    _ => format!("{}{:02}{:02}", sign, offset_hours, offset_minutes),
+                });
+                rest = &rest[token.len()..];
+            }
+            None => {
+                let c = rest.chars().next().unwrap_or_default();
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_date, parse_iso8601, DynamicTemplateBuilder, Part, PreviewErrorKind};
+    use crate::PersonalizationBuilder;
+    use serde_json::json;
+
+    #[test]
+    fn renders_sendgrid_helpers() {
+        let template = DynamicTemplateBuilder::default()
+            .subject("{{length items}} items for {{insert name \"default=you\"}}")
+            .plain(concat!(
+                "{{#each items}}{{name}}{{#greaterThan qty 1}} x{{qty}}{{/greaterThan}}\n{{/each}}",
+                "{{#notEquals total 0}}Total: {{total}}{{/notEquals}}",
+                "{{#and vip total}} (VIP){{/and}}{{#or gift missing}} gift{{else}} no gift{{/or}}",
+            ))
+            .html("<b>{{name}}</b> {{{name}}}")
+            .build();
+        let personalization = PersonalizationBuilder::default()
+            .dynamic_template_datum("name", "<Ann>")
+            .dynamic_template_datum(
+                "items",
+                json!([{"name": "Widget", "qty": 2}, {"name": "Gadget", "qty": 1}]),
+            )
+            .dynamic_template_datum("total", "12.50")
+            .dynamic_template_datum("vip", true)
+            .build();
+
+        let rendered = template.render(&personalization).unwrap();
+        assert_eq!(rendered.subject(), Some("2 items for <Ann>"));
+        assert_eq!(
+            rendered.plain(),
+            Some("Widget x2\nGadget\nTotal: 12.50 (VIP) no gift")
+        );
+        assert_eq!(rendered.html(), Some("<b>&lt;Ann&gt;</b> <Ann>"));
+    }
+
+    #[test]
+    fn reports_missing_variables() {
+        let template = DynamicTemplateBuilder::default()
+            .subject("Hi {{first_name}}")
+            .html("<p>\n  {{order.number}}\n</p>")
+            .build();
+        let personalization = PersonalizationBuilder::default()
+            .dynamic_template_datum("first_name", "Ann")
+            .dynamic_template_datum("order", json!({}))
+            .build();
+
+        let error = template.render(&personalization).unwrap_err();
+        assert_eq!(error.part(), Part::Html);
+        assert_eq!(
+            error.kind(),
+            &PreviewErrorKind::MissingVariable(String::from("order.number"))
+        );
+        assert_eq!(error.line(), Some(2));
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let template = DynamicTemplateBuilder::default()
+            .plain("{{#equals a b}}unclosed")
+            .build();
+        let error = template
+            .render(&PersonalizationBuilder::default().build())
+            .unwrap_err();
+        assert!(matches!(error.kind(), PreviewErrorKind::Syntax(_)));
+    }
+
+    #[test]
+    fn formats_dates() {
+        let timestamp = parse_iso8601("2020-01-01T23:00:00.000Z").unwrap();
+        assert_eq!(timestamp, 1_577_919_600);
+        assert_eq!(
+            format_date(timestamp, 0, "dddd, MMMM D, YYYY hh:mm:ss A ZZ"),
+            "Wednesday, January 1, 2020 11:00:00 PM +0000"
+        );
+        assert_eq!(
+            format_date(timestamp, -8 * 3600, "MM/DD/YY h:mm a"),
+            "01/01/20 3:00 pm"
+        );
+        assert_eq!(parse_iso8601("2020-01-02T07:00:00+08:00"), Some(timestamp));
+
+        let template = DynamicTemplateBuilder::default()
+            .plain("{{formatDate shipped \"MMM D\" \"+0900\"}}")
+            .build();
+        let personalization = PersonalizationBuilder::default()
+            .dynamic_template_datum("shipped", timestamp)
+            .build();
+        let rendered = template.render(&personalization).unwrap();
+        assert_eq!(rendered.plain(), Some("Jan 2"));
+    }
+}
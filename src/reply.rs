@@ -0,0 +1,805 @@
+//! Builds replies that mail clients thread into the original conversation.
+//!
+//! A reply names the `Message-ID` of the email it answers in its `In-Reply-To` header, and the
+//! whole chain of ids in `References`. A `ReplyBuilder` sets both with `MessageBuilder::header`,
+//! prefixes the subject with a single `Re:` and quotes the original body below the reply. Built
+//! from an [InboundEmail](../inbound/struct.InboundEmail.html), it also addresses the reply to
+//! the original's `Reply-To` or sender.
+//!
+//! The original's HTML comes from an untrusted sender, so only the contents of its `<body>` are
+//! quoted, reduced to an allowlist of formatting elements such as `<p>`, `<a>`, `<img>` and
+//! tables. Comments and elements such as `<script>`, `<style>`, `<iframe>`, `<object>`, `<svg>`
+//! and `<xmp>` are dropped with their content, other tags are dropped keeping their content,
+//! closing tags without a matching open tag are dropped, and tags left open are closed.
+//! Attributes are also allowlisted, so `style`, `class` and event handlers are dropped, links
+//! keep only `http`, `https`, `mailto` and fragment URLs, and images are kept only if their
+//! source is a `cid:` or `data:image/` URL, so remote images can't track when the reply is read.
+//!
+//! # Examples
+//! ```
+//! # use sendgrid_rs::inbound::InboundEmail;
+//! # use sendgrid_rs::reply::ReplyBuilder;
+//! # use sendgrid_rs::ContactBuilder;
+//! let original = InboundEmail::parse_mime(
+//!     b"From: Ann <ann@example.com>\r\n\
+//!       Subject: RE: Order #1234\r\n\
+//!       Message-ID: <2@example.com>\r\n\
+//!       References: <1@example.com>\r\n\r\n\
+//!       Where is my order?\r\n",
+//! );
+//!
+//! let message = ReplyBuilder::new(ContactBuilder::new("support@example.com").build(), &original)
+//!     .text("It ships tomorrow.")
+//!     .build()
+//!     .unwrap()
+//!     .build();
+//! let json = message.to_json();
+//! assert!(json.contains(r#""subject":"Re: Order #1234""#));
+//! assert!(json.contains(r#""In-Reply-To":"<2@example.com>""#));
+//! assert!(json.contains(r#""References":"<1@example.com> <2@example.com>""#));
+//! assert!(json.contains(r#"It ships tomorrow.\n\nAnn <ann@example.com> wrote:\n> Where is my order?"#));
+//! ```
+
+use crate::address;
+use crate::html::{attributes, tag_end};
+use crate::inbound::InboundEmail;
+use crate::message::MessageBuilder;
+use crate::personalization::PersonalizationBuilder;
+use crate::validation;
+use crate::{Contact, Content};
+use std::error::Error;
+use std::fmt;
+
+/// The reasons a reply can't be built
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReplyError {
+    /// A header is one of the headers SendGrid doesn't allow overriding, see
+    /// `validation::RESERVED_HEADERS`
+    ReservedHeader(String),
+}
+
+impl fmt::Display for ReplyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplyError::ReservedHeader(name) => write!(f, "{} is a reserved header", name),
+        }
+    }
+}
+
+impl Error for ReplyError {}
+
+/// Builder pattern for a reply. Make sure you call `build()` when done to consume the builder
+/// and return a `MessageBuilder` for the reply.
+pub struct ReplyBuilder {
+    from: Contact,
+    to: Option<Contact>,
+    subject: String,
+    message_id: Option<String>,
+    references: Vec<String>,
+    original: Option<Original>,
+    text: Option<String>,
+    html: Option<String>,
+    headers: Vec<(String, String)>,
+}
+
+/// The body of the email being replied to
+struct Original {
+    attribution: String,
+    text: Option<String>,
+    html: Option<String>,
+}
+
+impl ReplyBuilder {
+    /// Constructs a reply from `from` to an inbound email, quoting its body. The reply is
+    /// addressed to the original's `Reply-To` or `From` address. Only the safe parts of the
+    /// original's HTML are quoted, see the module documentation.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::inbound::InboundEmail;
+    /// # use sendgrid_rs::reply::ReplyBuilder;
+    /// # use sendgrid_rs::ContactBuilder;
+    /// let original = InboundEmail::parse_mime(b"From: ann@example.com\r\n\r\nHello");
+    ///
+    /// let builder = ReplyBuilder::new(ContactBuilder::new("support@example.com").build(), &original);
+    /// ```
+    pub fn new(from: Contact, original: &InboundEmail) -> Self {
+        let reply_to = original
+            .header("reply-to")
+            .and_then(|list| address::parse_list(list).ok())
+            .and_then(|list| list.into_iter().next());
+        let author = original.from().map(|from| match from.name() {
+            Some(name) => format!("{} <{}>", name, from.email()),
+            None => from.email().to_string(),
+        });
+        let attribution = match (original.header("date"), author) {
+            (Some(date), Some(author)) => format!("On {}, {} wrote:", date, author),
+            (None, Some(author)) => format!("{} wrote:", author),
+            (_, None) => String::from("The original message:"),
+        };
+
+        let mut reply = ReplyBuilder::from_message_id(from, "", original.subject());
+        reply.message_id = original.header("message-id").and_then(normalize_message_id);
+        reply.references = original
+            .header("references")
+            .map(|references| {
+                references
+                    .split_whitespace()
+                    .filter_map(normalize_message_id)
+                    .collect()
+            })
+            .unwrap_or_default();
+        reply.to = reply_to.or_else(|| original.from().cloned());
+        reply.original = Some(Original {
+            attribution,
+            text: original.text().map(String::from),
+            html: original.html().map(String::from),
+        });
+        reply
+    }
+
+    /// Constructs a reply from `from` to the email with this `Message-ID` and subject, when the
+    /// original isn't at hand. Add the recipients with `MessageBuilder::personalization`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::reply::ReplyBuilder;
+    /// # use sendgrid_rs::ContactBuilder;
+    /// let message = ReplyBuilder::from_message_id(
+    ///     ContactBuilder::new("support@example.com").build(),
+    ///     "1@example.com",
+    ///     "Re: Re: Your order",
+    /// )
+    /// .text("Thanks!")
+    /// .build()
+    /// .unwrap()
+    /// .build();
+    /// let json = message.to_json();
+    /// assert!(json.contains(r#""subject":"Re: Your order""#));
+    /// assert!(json.contains(r#""In-Reply-To":"<1@example.com>""#));
+    /// ```
+    pub fn from_message_id(
+        from: Contact,
+        message_id: impl AsRef<str>,
+        subject: impl AsRef<str>,
+    ) -> Self {
+        ReplyBuilder {
+            from,
+            to: None,
+            subject: reply_subject(subject.as_ref()),
+            references: vec![],
+            message_id: normalize_message_id(message_id.as_ref()),
+            original: None,
+            text: None,
+            html: None,
+            headers: vec![],
+        }
+    }
+
+    /// Sets the plain text of the reply, placed above the quoted original
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::reply::ReplyBuilder;
+    /// # use sendgrid_rs::ContactBuilder;
+    /// let builder = ReplyBuilder::from_message_id(
+    ///     ContactBuilder::new("support@example.com").build(),
+    ///     "1@example.com",
+    ///     "Your order",
+    /// )
+    /// .text("Thanks!");
+    /// ```
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+
+    /// Sets the HTML of the reply, placed above the quoted original
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::reply::ReplyBuilder;
+    /// # use sendgrid_rs::ContactBuilder;
+    /// let builder = ReplyBuilder::from_message_id(
+    ///     ContactBuilder::new("support@example.com").build(),
+    ///     "1@example.com",
+    ///     "Your order",
+    /// )
+    /// .html("<p>Thanks!</p>");
+    /// ```
+    pub fn html(mut self, html: impl Into<String>) -> Self {
+        self.html = Some(html.into());
+        self
+    }
+
+    /// Leaves the original body out of the reply
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::inbound::InboundEmail;
+    /// # use sendgrid_rs::reply::ReplyBuilder;
+    /// # use sendgrid_rs::ContactBuilder;
+    /// let original = InboundEmail::parse_mime(b"From: ann@example.com\r\n\r\nHello");
+    ///
+    /// let builder = ReplyBuilder::new(ContactBuilder::new("support@example.com").build(), &original)
+    ///     .without_quote();
+    /// ```
+    pub fn without_quote(mut self) -> Self {
+        self.original = None;
+        self
+    }
+
+    /// Adds a header to the reply. `build` fails if it is one SendGrid doesn't allow overriding.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::reply::{ReplyBuilder, ReplyError};
+    /// # use sendgrid_rs::ContactBuilder;
+    /// let reply = ReplyBuilder::from_message_id(
+    ///     ContactBuilder::new("support@example.com").build(),
+    ///     "1@example.com",
+    ///     "Your order",
+    /// )
+    /// .header("X-Ticket", "1234")
+    /// .header("Reply-To", "other@example.com")
+    /// .build();
+    /// assert_eq!(reply.err(), Some(ReplyError::ReservedHeader(String::from("Reply-To"))));
+    /// ```
+    pub fn header<S: Into<String>>(mut self, key: S, value: S) -> Self {
+        self.headers.push((key.into(), value.into()));
+        self
+    }
+
+    /// Consumes the builder and returns a `MessageBuilder` with the reply's subject, threading
+    /// headers and content, and its recipient if known
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::reply::ReplyBuilder;
+    /// # use sendgrid_rs::ContactBuilder;
+    /// let message = ReplyBuilder::from_message_id(
+    ///     ContactBuilder::new("support@example.com").build(),
+    ///     "1@example.com",
+    ///     "Your order",
+    /// )
+    /// .build()
+    /// .unwrap()
+    /// .build();
+    /// ```
+    pub fn build(self) -> Result<MessageBuilder, ReplyError> {
+        if let Some((name, _)) = self
+            .headers
+            .iter()
+            .find(|(name, _)| validation::is_reserved_header(name))
+        {
+            return Err(ReplyError::ReservedHeader(name.clone()));
+        }
+
+        let mut builder = MessageBuilder::new(self.from, self.subject);
+        if let Some(id) = self.message_id {
+            let mut references = self.references;
+            if !references.contains(&id) {
+                references.push(id.clone());
+            }
+            builder = builder
+                .header(String::from("In-Reply-To"), id)
+                .header(String::from("References"), references.join(" "));
+        }
+        for (name, value) in self.headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(to) = self.to {
+            builder = builder.personalization(PersonalizationBuilder::default().to(to).build());
+        }
+
+        let original = self.original.as_ref();
+        if self.text.is_some() || self.html.is_none() {
+            let mut text = self.text.unwrap_or_default();
+            if let Some(original) = original {
+                if !text.is_empty() {
+                    text.push_str("\n\n");
+                }
+                text.push_str(&original.attribution);
+                text.push('\n');
+                text.push_str(&quote_text(original.text.as_deref().unwrap_or("")));
+            }
+            builder = builder.content(Content::new(String::from("text/plain"), text));
+        }
+        if let Some(mut html) = self.html {
+            if let Some(original) = original {
+                let quoted = match (&original.html, &original.text) {
+                    (Some(html), _) => quotable_html(html),
+                    (None, Some(text)) => escape_html(text).replace('\n', "<br>\n"),
+                    (None, None) => String::new(),
+                };
+                html.push_str(&format!(
+                    "\n<p>{}</p>\n<blockquote type=\"cite\">\n{}\n</blockquote>",
+                    escape_html(&original.attribution),
+                    quoted
+                ));
+            }
+            builder = builder.content(Content::new(String::from("text/html"), html));
+        }
+        Ok(builder)
+    }
+}
+
+/// Prefixes a subject with a single `Re:`, dropping any it already has
+fn reply_subject(subject: &str) -> String {
+    let mut rest = subject.trim();
+    while let Some(tail) = rest
+        .get(..3)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("re:"))
+        .map(|_| rest[3..].trim_start())
+    {
+        rest = tail;
+    }
+    if rest.is_empty() {
+        String::from("Re:")
+    } else {
+        format!("Re: {}", rest)
+    }
+}
+
+/// Normalizes a message id to `<id>`, or `None` if it is empty
+fn normalize_message_id(id: &str) -> Option<String> {
+    let id = id.trim().trim_start_matches('<').trim_end_matches('>');
+    if id.is_empty() {
+        None
+    } else {
+        Some(format!("<{}>", id))
+    }
+}
+
+/// Prefixes each line with `> `, or `>` for empty lines
+fn quote_text(text: &str) -> String {
+    text.trim_end()
+        .lines()
+        .map(|line| {
+            if line.is_empty() {
+                String::from(">")
+            } else {
+                format!("> {}", line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Elements kept in quoted HTML. The tags of other elements are dropped, keeping what they
+/// enclose unless they are one of `DROPPED_ELEMENTS`.
+const ALLOWED_ELEMENTS: &[&str] = &[
+    "a",
+    "abbr",
+    "b",
+    "blockquote",
+    "br",
+    "caption",
+    "center",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "dd",
+    "del",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "font",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "s",
+    "small",
+    "span",
+    "strike",
+    "strong",
+    "sub",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "tt",
+    "u",
+    "ul",
+];
+
+/// Elements dropped from quoted HTML along with their content
+const DROPPED_ELEMENTS: &[&str] = &[
+    "applet",
+    "head",
+    "iframe",
+    "math",
+    "noembed",
+    "noframes",
+    "noscript",
+    "object",
+    "plaintext",
+    "script",
+    "select",
+    "style",
+    "svg",
+    "template",
+    "textarea",
+    "title",
+    "xmp",
+];
+
+/// Attributes kept on allowed elements
+const ALLOWED_ATTRIBUTES: &[&str] = &[
+    "align",
+    "alt",
+    "border",
+    "cellpadding",
+    "cellspacing",
+    "cite",
+    "color",
+    "colspan",
+    "dir",
+    "face",
+    "height",
+    "href",
+    "lang",
+    "rowspan",
+    "size",
+    "src",
+    "title",
+    "valign",
+    "width",
+];
+
+/// Allowed elements that have no closing tag
+const VOID_ELEMENTS: &[&str] = &["br", "col", "hr", "img"];
+
+/// Reduces untrusted HTML to the contents of its `<body>` that can sit inside a `<blockquote>`,
+/// see the module documentation
+fn quotable_html(html: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let start = lower
+        .match_indices("<body")
+        .find(|(i, _)| {
+            lower[i + "<body".len()..]
+                .starts_with(|c: char| c == '>' || c == '/' || c.is_ascii_whitespace())
+        })
+        .and_then(|(i, _)| tag_end(&html[i..]).map(|end| i + end))
+        .unwrap_or(0);
+    let end = lower[start..]
+        .find("</body")
+        .map_or(html.len(), |i| start + i);
+
+    let mut out = String::new();
+    let mut open: Vec<String> = vec![];
+    let mut at = start;
+    while at < end {
+        let lt = match html[at..end].find('<') {
+            Some(i) => at + i,
+            None => {
+                out.push_str(&html[at..end]);
+                break;
+            }
+        };
+        out.push_str(&html[at..lt]);
+        if lower[lt..end].starts_with("<!--") {
+            at = lower[lt..end]
+                .find("-->")
+                .map_or(end, |i| lt + i + "-->".len());
+            continue;
+        }
+        let tag = match tag_end(&html[lt..end]) {
+            Some(len) => &html[lt..lt + len],
+            None => {
+                out.push_str("&lt;");
+                at = lt + 1;
+                continue;
+            }
+        };
+        at = lt + tag.len();
+
+        let closing = tag.starts_with("</");
+        let name: String = tag[if closing { 2 } else { 1 }..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        if DROPPED_ELEMENTS.contains(&name.as_str()) {
+            if !closing {
+                let close = format!("</{}", name);
+                at = lower[at..end].find(&close).map_or(end, |i| {
+                    let close_at = at + i;
+                    tag_end(&html[close_at..end]).map_or(end, |len| close_at + len)
+                });
+            }
+            continue;
+        }
+        if !ALLOWED_ELEMENTS.contains(&name.as_str()) {
+            continue;
+        }
+        if closing {
+            // A closing tag without a matching open tag could close the reply's blockquote
+            if let Some(i) = open.iter().rposition(|n| *n == name) {
+                for name in open.drain(i..).rev() {
+                    out.push_str(&format!("</{}>", name));
+                }
+            }
+        } else {
+            let mut kept = format!("<{}", name);
+            let mut has_src = false;
+            for (attribute, value) in attributes(tag) {
+                if !ALLOWED_ATTRIBUTES.contains(&attribute.as_str()) {
+                    continue;
+                }
+                let value = value.map_or("", |range| &tag[range]);
+                if ["cite", "href", "src"].contains(&attribute.as_str())
+                    && !safe_url(&attribute, value)
+                {
+                    continue;
+                }
+                has_src |= attribute == "src";
+                kept.push_str(&format!(
+                    " {}=\"{}\"",
+                    attribute,
+                    value.replace('"', "&quot;")
+                ));
+            }
+            if name == "img" && !has_src {
+                continue;
+            }
+            out.push_str(&kept);
+            out.push('>');
+            if !VOID_ELEMENTS.contains(&name.as_str()) {
+                open.push(name);
+            }
+        }
+    }
+    for name in open.iter().rev() {
+        out.push_str(&format!("</{}>", name));
+    }
+    out.trim().to_string()
+}
+
+/// Returns true if the URL in a `src`, or a `href` or `cite` attribute, can be quoted
+fn safe_url(attribute: &str, url: &str) -> bool {
+    let url = url.trim().to_ascii_lowercase();
+    let schemes: &[&str] = if attribute == "src" {
+        &["cid:", "data:image/"]
+    } else {
+        &["http://", "https://", "mailto:", "#"]
+    };
+    schemes.iter().any(|scheme| url.starts_with(scheme))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{quotable_html, reply_subject, ReplyBuilder, ReplyError};
+    use crate::inbound::InboundEmail;
+    use crate::ContactBuilder;
+    use serde_json::{json, Value};
+
+    fn support() -> crate::Contact {
+        ContactBuilder::new("support@example.com")
+            .name("Support")
+            .build()
+    }
+
+    fn json(reply: ReplyBuilder) -> Value {
+        serde_json::to_value(reply.build().unwrap().build()).unwrap()
+    }
+
+    #[test]
+    fn normalizes_subjects() {
+        assert_eq!(reply_subject("Hello"), "Re: Hello");
+        assert_eq!(reply_subject(" re:RE: Re:  Hello "), "Re: Hello");
+        assert_eq!(reply_subject("Reply needed"), "Re: Reply needed");
+        assert_eq!(reply_subject("Re:"), "Re:");
+        assert_eq!(reply_subject(""), "Re:");
+    }
+
+    #[test]
+    fn quotes_only_safe_body_html() {
+        let document = r#"<!DOCTYPE html>
+<html><head><title>Hi</title><style>p { display: none }</style>
+<meta charset="utf-8"></head>
+<body class="x"><!-- tracking --><script>alert("<p>")</script>
+<p title="a > b">Hello <b>there</p></blockquote></div><div>unclosed<br>
+<SCRIPT src="x.js"></SCRIPT>1 < 2</body></html>"#;
+        assert_eq!(
+            quotable_html(document),
+            "<p title=\"a > b\">Hello <b>there</b></p><div>unclosed<br>\n1 &lt; 2</div>"
+        );
+        assert_eq!(quotable_html("<p>fragment"), "<p>fragment</p>");
+        assert_eq!(quotable_html("<div/>text"), "<div>text</div>");
+
+        let original = InboundEmail::parse_mime(
+            b"From: ann@example.com\r\n\
+              Content-Type: text/html\r\n\r\n\
+              <html><head><style>body { color: red }</style></head>\
+              <body><p>Hi</p></blockquote><div></body></html>\r\n",
+        );
+        let message = json(ReplyBuilder::new(support(), &original).html("<p>Reply</p>"));
+        assert_eq!(
+            message["content"][0]["value"],
+            "<p>Reply</p>\n<p>ann@example.com wrote:</p>\n\
+             <blockquote type=\"cite\">\n<p>Hi</p><div></div>\n</blockquote>"
+        );
+    }
+
+    #[test]
+    fn quotes_only_allowed_elements_and_attributes() {
+        let cases = [
+            (
+                r#"<div style="position:fixed;top:0" class="x" onclick="steal()">a</div>"#,
+                "<div>a</div>",
+            ),
+            (
+                r#"<p ONMOUSEOVER=steal() align=center>a</p>"#,
+                r#"<p align="center">a</p>"#,
+            ),
+            (
+                r#"<a href="javascript:steal()" title='say "hi"'>a</a>"#,
+                r#"<a title="say &quot;hi&quot;">a</a>"#,
+            ),
+            (
+                r#"<a href=" HTTPS://example.com/">a</a><a href="mailto:a@example.com">b</a>"#,
+                r#"<a href=" HTTPS://example.com/">a</a><a href="mailto:a@example.com">b</a>"#,
+            ),
+            (
+                r##"<a href="data:text/html,<script>">a</a><a href="#top">b</a>"##,
+                r##"<a>a</a><a href="#top">b</a>"##,
+            ),
+            (
+                r#"a<iframe srcdoc="<script>steal()</script>">b</iframe>c"#,
+                "ac",
+            ),
+            (
+                r#"<form action="https://evil.example.com"><input name="password">a</form>"#,
+                "a",
+            ),
+            (
+                r#"<object data="x.swf"><embed src="x.swf">a</object>b"#,
+                "b",
+            ),
+            (
+                r#"<svg onload="steal()"><script>steal()</script></svg>a"#,
+                "a",
+            ),
+            (r#"a<xmp><p>b</p></xmp>c"#, "ac"),
+            (r#"a<plaintext></blockquote>b"#, "a"),
+            (
+                r#"<img src="https://tracker.example.com/open.gif" width="1">a"#,
+                "a",
+            ),
+            (
+                r#"<img src="cid:logo" alt="Logo" onerror="steal()"><img src=data:image/png;base64,AA>"#,
+                r#"<img src="cid:logo" alt="Logo"><img src="data:image/png;base64,AA">"#,
+            ),
+            (
+                r#"<blockquote cite="javascript:steal()">a</blockquote>"#,
+                "<blockquote>a</blockquote>",
+            ),
+        ];
+        for (html, quoted) in cases.iter() {
+            assert_eq!(quotable_html(html), *quoted, "quoting {}", html);
+        }
+    }
+
+    #[test]
+    fn replies_to_inbound_email() {
+        let original = InboundEmail::parse_mime(
+            b"From: \"Lee, Ann\" <ann@example.com>\r\n\
+              Reply-To: tickets@example.com\r\n\
+              Date: Tue, 1 Oct 2024 10:00:00 +0000\r\n\
+              Subject: Re: Refund\r\n\
+              Message-ID: <3@example.com>\r\n\
+              References: <1@example.com>\r\n <2@example.com>\r\n\
+              Content-Type: multipart/alternative; boundary=b\r\n\r\n\
+              --b\r\nContent-Type: text/plain\r\n\r\nPlease refund.\r\n\r\nThanks\r\n\
+              --b\r\nContent-Type: text/html\r\n\r\n<p>Please refund.</p>\r\n--b--\r\n",
+        );
+
+        let message = json(
+            ReplyBuilder::new(support(), &original)
+                .text("Done.")
+                .html("<p>Done.</p>")
+                .header("X-Ticket", "7"),
+        );
+        assert_eq!(message["subject"], "Re: Refund");
+        assert_eq!(
+            message["headers"],
+            json!({
+                "In-Reply-To": "<3@example.com>",
+                "References": "<1@example.com> <2@example.com> <3@example.com>",
+                "X-Ticket": "7",
+            })
+        );
+        assert_eq!(
+            message["personalizations"][0]["to"][0]["email"],
+            "tickets@example.com"
+        );
+        assert_eq!(
+            message["content"][0],
+            json!({
+                "type": "text/plain",
+                "value": "Done.\n\nOn Tue, 1 Oct 2024 10:00:00 +0000, Lee, Ann <ann@example.com> wrote:\n\
+                          > Please refund.\n>\n> Thanks",
+            })
+        );
+        assert_eq!(
+            message["content"][1],
+            json!({
+                "type": "text/html",
+                "value": "<p>Done.</p>\n\
+                          <p>On Tue, 1 Oct 2024 10:00:00 +0000, Lee, Ann &lt;ann@example.com&gt; wrote:</p>\n\
+                          <blockquote type=\"cite\">\n<p>Please refund.</p>\n</blockquote>",
+            })
+        );
+    }
+
+    #[test]
+    fn threads_without_original() {
+        let original = InboundEmail::parse_mime(b"From: ann@example.com\r\nSubject: Hi\r\n\r\n<b>");
+
+        let message = json(ReplyBuilder::new(support(), &original).html("<p>Hello</p>"));
+        assert!(message.get("headers").is_none());
+        assert_eq!(
+            message["personalizations"][0]["to"][0]["email"],
+            "ann@example.com"
+        );
+        assert_eq!(
+            message["content"],
+            json!([{
+                "type": "text/html",
+                "value": "<p>Hello</p>\n<p>ann@example.com wrote:</p>\n<blockquote type=\"cite\">\n&lt;b&gt;\n</blockquote>",
+            }])
+        );
+
+        let message = json(
+            ReplyBuilder::from_message_id(support(), " <9@example.com> ", "Hi")
+                .text("Hello")
+                .without_quote(),
+        );
+        assert_eq!(message["headers"]["References"], "<9@example.com>");
+        assert!(message.get("personalizations").is_none());
+        assert_eq!(
+            message["content"],
+            json!([{"type": "text/plain", "value": "Hello"}])
+        );
+    }
+
+    #[test]
+    fn rejects_reserved_headers() {
+        for name in ["from", "Subject", "DKIM-Signature", "x-sg-eid"] {
+            let reply = ReplyBuilder::from_message_id(support(), "1@example.com", "Hi")
+                .header(name, "x")
+                .build();
+            assert_eq!(
+                reply.err(),
+                Some(ReplyError::ReservedHeader(name.to_string()))
+            );
+        }
+    }
+}
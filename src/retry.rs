@@ -0,0 +1,435 @@
+//! Retrying of requests SendGrid asks to be retried. A 429 response is retried once the rate limit
+//! resets, according to its `X-RateLimit-Reset` header, and a 5xx response is retried with
+//! exponential backoff and jitter. Anything else, including a 400, is returned as is.
+//!
+//! Wrap a [Transport](../transport/trait.Transport.html) in a `RetryTransport` to retry blocking
+//! requests. For an [AsyncTransport](../transport/trait.AsyncTransport.html), call
+//! `RetryPolicy::delay` after each response and wait with your runtime's timer.
+
+use crate::clock::{Clock, SystemClock};
+use crate::transport::{Request, Response, Transport};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The rate limit state SendGrid reports in the `X-RateLimit-*` headers of a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    limit: Option<u64>,
+    remaining: Option<u64>,
+    reset: Option<SystemTime>,
+}
+
+impl RateLimit {
+    /// Reads the `X-RateLimit-Limit`, `X-RateLimit-Remaining` and `X-RateLimit-Reset` headers.
+    /// Returns `None` if none of them are present.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::retry::RateLimit;
+    /// # use sendgrid_rs::transport::Response;
+    /// # use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let response = Response::new(429, "")
+    ///     .header("X-RateLimit-Limit", "600")
+    ///     .header("X-RateLimit-Remaining", "0")
+    ///     .header("X-RateLimit-Reset", "1600000000");
+    /// let limit = RateLimit::from_response(&response).unwrap();
+    /// assert_eq!(limit.remaining(), Some(0));
+    /// assert_eq!(limit.reset(), Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000)));
+    /// ```
+    pub fn from_response(response: &Response) -> Option<Self> {
+        RateLimit::from_headers(response.headers())
+    }
+
+    /// Like `from_response`, but reads the headers from name/value pairs
+    pub(crate) fn from_headers(headers: &[(String, String)]) -> Option<Self> {
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .and_then(|(_, v)| v.trim().parse::<u64>().ok())
+        };
+        let limit = RateLimit {
+            limit: header("X-RateLimit-Limit"),
+            remaining: header("X-RateLimit-Remaining"),
+            reset: header("X-RateLimit-Reset").map(|s| UNIX_EPOCH + Duration::from_secs(s)),
+        };
+        if limit.limit.is_none() && limit.remaining.is_none() && limit.reset.is_none() {
+            None
+        } else {
+            Some(limit)
+        }
+    }
+
+    /// The number of requests allowed in the current window
+    pub fn limit(&self) -> Option<u64> {
+        self.limit
+    }
+
+    /// The number of requests left in the current window
+    pub fn remaining(&self) -> Option<u64> {
+        self.remaining
+    }
+
+    /// When the current window ends
+    pub fn reset(&self) -> Option<SystemTime> {
+        self.reset
+    }
+}
+
+/// Decides whether and when a response should be retried. Use `RetryPolicyBuilder` to construct
+/// this.
+#[derive(Debug)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    seed: AtomicU64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(60),
+            jitter: true,
+            seed: AtomicU64::new(RandomState::new().build_hasher().finish() | 1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns how long to wait before retrying, or `None` if the response must not be retried.
+    /// `retries` is the number of retries already made for this request.
+    ///
+    /// A 429 is retried when its `X-RateLimit-Reset` time is reached, falling back to backoff if
+    /// the header is missing. A 5xx is retried after an exponential backoff, randomized with full
+    /// jitter if enabled. Nothing else is retried, and nothing is retried after `max_retries` or
+    /// if the wait would exceed `max_delay`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::retry::RetryPolicyBuilder;
+    /// # use sendgrid_rs::transport::Response;
+    /// # use std::time::{Duration, SystemTime};
+    ///
+    /// let policy = RetryPolicyBuilder::default().jitter(false).build();
+    /// let now = SystemTime::now();
+    /// assert_eq!(
+    ///     policy.delay(1, &Response::new(503, ""), now),
+    ///     Some(Duration::from_secs(1))
+    /// );
+    /// assert_eq!(policy.delay(0, &Response::new(400, ""), now), None);
+    /// ```
+    pub fn delay(&self, retries: u32, response: &Response, now: SystemTime) -> Option<Duration> {
+        if retries >= self.max_retries {
+            return None;
+        }
+        let delay = match response.status() {
+            429 => match RateLimit::from_response(response).and_then(|l| l.reset()) {
+                Some(reset) => reset.duration_since(now).unwrap_or_default(),
+                None => self.backoff(retries),
+            },
+            500..=599 => self.backoff(retries),
+            _ => return None,
+        };
+        if delay > self.max_delay {
+            None
+        } else {
+            Some(delay)
+        }
+    }
+
+    fn backoff(&self, retries: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .checked_mul(2u32.saturating_pow(retries))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        if self.jitter {
+            delay.mul_f64(self.random())
+        } else {
+            delay
+        }
+    }
+
+    /// A xorshift generator for jitter, which does not need to be cryptographically secure
+    fn random(&self) -> f64 {
+        let mut x = self.seed.load(Ordering::Relaxed);
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.seed.store(x, Ordering::Relaxed);
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Builder pattern for `RetryPolicy`. Make sure you call `build()` when done to consume this and
+/// return the underlying `RetryPolicy`. Construct with default().
+#[derive(Default)]
+pub struct RetryPolicyBuilder {
+    policy: RetryPolicy,
+}
+
+impl RetryPolicyBuilder {
+    /// Sets the maximum number of retries for a single request. Defaults to 3.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::retry::RetryPolicyBuilder;
+    ///
+    /// let builder = RetryPolicyBuilder::default()
+    ///               .max_retries(5);
+    /// ```
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.policy.max_retries = retries;
+        self
+    }
+
+    /// Sets the delay before the first retry of a 5xx, which doubles with each retry. Defaults to
+    /// 500 milliseconds.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::retry::RetryPolicyBuilder;
+    /// # use std::time::Duration;
+    ///
+    /// let builder = RetryPolicyBuilder::default()
+    ///               .base_delay(Duration::from_secs(1));
+    /// ```
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.policy.base_delay = delay;
+        self
+    }
+
+    /// Sets the longest the policy will wait before a retry. A response that asks for a longer
+    /// wait is not retried. Defaults to 60 seconds.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::retry::RetryPolicyBuilder;
+    /// # use std::time::Duration;
+    ///
+    /// let builder = RetryPolicyBuilder::default()
+    ///               .max_delay(Duration::from_secs(10));
+    /// ```
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.policy.max_delay = delay;
+        self
+    }
+
+    /// Turns randomization of the 5xx backoff on or off. Defaults to on.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::retry::RetryPolicyBuilder;
+    ///
+    /// let builder = RetryPolicyBuilder::default()
+    ///               .jitter(false);
+    /// ```
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.policy.jitter = jitter;
+        self
+    }
+
+    /// Consumes the builder and returns the underlying `RetryPolicy`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::retry::RetryPolicyBuilder;
+    ///
+    /// let policy = RetryPolicyBuilder::default().build();
+    /// ```
+    pub fn build(self) -> RetryPolicy {
+        self.policy
+    }
+}
+
+/// A `Transport` that retries requests through another `Transport` according to a
+/// `RetryPolicy`. Errors from the inner transport are returned without retrying.
+///
+/// # Examples
+/// ```
+/// # use sendgrid_rs::retry::{RetryPolicyBuilder, RetryTransport};
+/// # use sendgrid_rs::transport::{Request, Response, Transport};
+/// # use sendgrid_rs::ClientBuilder;
+/// # struct Accept;
+/// # impl Transport for Accept {
+/// #     type Error = std::io::Error;
+/// #     fn send(&self, _request: &Request) -> Result<Response, Self::Error> {
+/// #         Ok(Response::new(202, ""))
+/// #     }
+/// # }
+/// # let transport = Accept;
+/// let client = ClientBuilder::new(
+///     "SENDGRID SECRET API KEY",
+///     RetryTransport::new(transport, RetryPolicyBuilder::default().build()),
+/// )
+/// .build();
+/// ```
+pub struct RetryTransport<T, C = SystemClock> {
+    inner: T,
+    policy: RetryPolicy,
+    clock: C,
+}
+
+impl<T> RetryTransport<T> {
+    /// Wraps `inner`, waiting between retries with the system clock
+    pub fn new(inner: T, policy: RetryPolicy) -> Self {
+        RetryTransport::with_clock(inner, policy, SystemClock)
+    }
+}
+
+impl<T, C> RetryTransport<T, C> {
+    /// Wraps `inner`, reading the time from and waiting between retries with `clock`
+    pub fn with_clock(inner: T, policy: RetryPolicy, clock: C) -> Self {
+        RetryTransport {
+            inner,
+            policy,
+            clock,
+        }
+    }
+
+    /// The wrapped transport
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: Transport, C: Clock> Transport for RetryTransport<T, C> {
+    type Error = T::Error;
+
+    fn send(&self, request: &Request) -> Result<Response, Self::Error> {
+        let mut retries = 0;
+        loop {
+            let response = self.inner.send(request)?;
+            match self.policy.delay(retries, &response, self.clock.now()) {
+                Some(delay) => {
+                    self.clock.sleep(delay);
+                    retries += 1;
+                }
+                None => return Ok(response),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RetryPolicyBuilder, RetryTransport};
+    use crate::clock::FakeClock;
+    use crate::transport::{Method, Request, Response, Transport};
+    use std::cell::{Cell, RefCell};
+    use std::time::{Duration, SystemTime};
+
+    struct FakeTransport {
+        responses: RefCell<Vec<Response>>,
+        calls: Cell<usize>,
+    }
+
+    impl FakeTransport {
+        fn new(mut responses: Vec<Response>) -> Self {
+            responses.reverse();
+            FakeTransport {
+                responses: RefCell::new(responses),
+                calls: Cell::new(0),
+            }
+        }
+    }
+
+    impl Transport for FakeTransport {
+        type Error = std::io::Error;
+
+        fn send(&self, _request: &Request) -> Result<Response, Self::Error> {
+            self.calls.set(self.calls.get() + 1);
+            Ok(self.responses.borrow_mut().pop().unwrap())
+        }
+    }
+
+    fn request() -> Request {
+        Request::new(Method::Post, "http://localhost/v3/mail/send")
+    }
+
+    #[test]
+    fn waits_for_rate_limit_reset() {
+        let clock = FakeClock::at(1_600_000_000);
+        let transport = FakeTransport::new(vec![
+            Response::new(429, "").header("X-RateLimit-Reset", "1600000030"),
+            Response::new(202, ""),
+        ]);
+        let retry =
+            RetryTransport::with_clock(&transport, RetryPolicyBuilder::default().build(), &clock);
+        assert_eq!(retry.send(&request()).unwrap().status(), 202);
+        assert_eq!(clock.sleeps(), vec![Duration::from_secs(30)]);
+    }
+
+    #[test]
+    fn backs_off_exponentially_on_server_errors() {
+        let clock = FakeClock::at(1_600_000_000);
+        let transport = FakeTransport::new(vec![
+            Response::new(500, ""),
+            Response::new(502, ""),
+            Response::new(503, ""),
+            Response::new(503, ""),
+        ]);
+        let policy = RetryPolicyBuilder::default()
+            .base_delay(Duration::from_secs(1))
+            .jitter(false)
+            .build();
+        let retry = RetryTransport::with_clock(&transport, policy, &clock);
+        assert_eq!(retry.send(&request()).unwrap().status(), 503);
+        assert_eq!(transport.calls.get(), 4);
+        assert_eq!(
+            clock.sleeps(),
+            vec![
+                Duration::from_secs(1),
+                Duration::from_secs(2),
+                Duration::from_secs(4)
+            ]
+        );
+    }
+
+    #[test]
+    fn jitter_stays_within_backoff() {
+        let policy = RetryPolicyBuilder::default()
+            .base_delay(Duration::from_secs(1))
+            .build();
+        let now = SystemTime::now();
+        for _ in 0..100 {
+            let delay = policy.delay(2, &Response::new(500, ""), now).unwrap();
+            assert!(delay <= Duration::from_secs(4));
+        }
+    }
+
+    #[test]
+    fn never_retries_client_errors() {
+        let clock = FakeClock::at(1_600_000_000);
+        let transport = FakeTransport::new(vec![Response::new(400, "")]);
+        let retry =
+            RetryTransport::with_clock(&transport, RetryPolicyBuilder::default().build(), &clock);
+        assert_eq!(retry.send(&request()).unwrap().status(), 400);
+        assert_eq!(transport.calls.get(), 1);
+        assert!(clock.sleeps().is_empty());
+    }
+
+    #[test]
+    fn gives_up_when_reset_is_too_far_away() {
+        let clock = FakeClock::at(1_600_000_000);
+        let transport = FakeTransport::new(vec![
+            Response::new(429, "").header("X-RateLimit-Reset", "1600003600")
+        ]);
+        let retry = RetryTransport::with_clock(
+            &transport,
+            RetryPolicyBuilder::default()
+                .max_delay(Duration::from_secs(60))
+                .build(),
+            &clock,
+        );
+        assert_eq!(retry.send(&request()).unwrap().status(), 429);
+        assert!(clock.sleeps().is_empty());
+    }
+}
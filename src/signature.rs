@@ -0,0 +1,361 @@
+//! Verifies that Event Webhook requests were signed by SendGrid. Enabled with the `signature`
+//! feature.
+//!
+//! With the Signed Event Webhook enabled, SendGrid signs the timestamp in the
+//! `TIMESTAMP_HEADER` followed by the raw request body with ECDSA over P-256, and sends the
+//! base64 encoded signature in the `SIGNATURE_HEADER`. A `Verifier` checks the signature with the
+//! verification key from SendGrid's settings, and rejects timestamps outside its tolerance so a
+//! captured request can't be replayed later.
+//!
+//! # Examples
+//! ```no_run
+//! # use sendgrid_rs::signature::{Verifier, VerifierBuilder, VerifyError};
+//! # use sendgrid_rs::webhook::Event;
+//! let verifier = VerifierBuilder::new("MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE...")
+//!     .build()
+//!     .unwrap();
+//!
+//! // Called with the values of SIGNATURE_HEADER and TIMESTAMP_HEADER and the raw body
+//! fn handle(
+//!     verifier: &Verifier,
+//!     signature: &str,
+//!     timestamp: &str,
+//!     body: &[u8],
+//! ) -> Result<Vec<Event>, VerifyError> {
+//!     verifier.verify(signature, timestamp, body)
+//! }
+//! ```
+
+use crate::clock::{self, Clock, SystemClock};
+use crate::webhook::{self, Event};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use p256::ecdsa::signature::Verifier as _;
+use p256::ecdsa::{Signature, VerifyingKey};
+use p256::pkcs8::DecodePublicKey;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// The header holding the base64 encoded signature
+pub const SIGNATURE_HEADER: &str = "X-Twilio-Email-Event-Webhook-Signature";
+
+/// The header holding the Unix timestamp the request was signed at
+pub const TIMESTAMP_HEADER: &str = "X-Twilio-Email-Event-Webhook-Timestamp";
+
+/// How far the timestamp of a request may be from the current time by default
+pub const DEFAULT_TOLERANCE: Duration = Duration::from_secs(5 * 60);
+
+/// The reasons a request fails verification
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum VerifyError {
+    /// The public key isn't a base64 encoded DER P-256 public key
+    InvalidPublicKey,
+    /// The signature header isn't a base64 encoded DER ECDSA signature
+    MalformedSignature,
+    /// The timestamp header isn't a Unix timestamp
+    MalformedTimestamp,
+    /// The timestamp, a Unix timestamp, is further from the current time than the tolerance
+    StaleTimestamp(u64),
+    /// The signature doesn't match the timestamp and body
+    BadSignature,
+    /// The body is signed but isn't a valid array of events
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyError::InvalidPublicKey => write!(f, "invalid public key"),
+            VerifyError::MalformedSignature => write!(f, "malformed signature"),
+            VerifyError::MalformedTimestamp => write!(f, "malformed timestamp"),
+            VerifyError::StaleTimestamp(time) => {
+                write!(f, "timestamp {} is outside the tolerance", time)
+            }
+            VerifyError::BadSignature => write!(f, "signature does not match"),
+            VerifyError::Parse(e) => write!(f, "failed to parse events: {}", e),
+        }
+    }
+}
+
+impl Error for VerifyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VerifyError::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Verifies signed Event Webhook requests. Use `VerifierBuilder` to construct this.
+#[derive(Debug, Clone)]
+pub struct Verifier {
+    key: VerifyingKey,
+    tolerance: Duration,
+}
+
+/// Builder pattern for `Verifier`. Make sure you call `build()` when done to consume the builder
+/// and return the underlying `Verifier`.
+pub struct VerifierBuilder {
+    public_key: String,
+    tolerance: Duration,
+}
+
+impl VerifierBuilder {
+    /// Constructs a `VerifierBuilder`. The required parameter is the verification key shown in
+    /// SendGrid's Event Webhook settings, a base64 encoded DER public key.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::signature::VerifierBuilder;
+    ///
+    /// let builder = VerifierBuilder::new("MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE...");
+    /// ```
+    pub fn new(public_key: impl Into<String>) -> Self {
+        VerifierBuilder {
+            public_key: public_key.into(),
+            tolerance: DEFAULT_TOLERANCE,
+        }
+    }
+
+    /// Sets how far the timestamp of a request may be from the current time, `DEFAULT_TOLERANCE`
+    /// by default
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::signature::VerifierBuilder;
+    /// # use std::time::Duration;
+    ///
+    /// let builder = VerifierBuilder::new("MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE...")
+    ///     .tolerance(Duration::from_secs(60));
+    /// ```
+    pub fn tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Consumes the builder and returns the `Verifier`, or `VerifyError::InvalidPublicKey` if the
+    /// public key can't be decoded
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::signature::{VerifierBuilder, VerifyError};
+    ///
+    /// let verifier = VerifierBuilder::new("not a key").build();
+    /// assert!(matches!(verifier, Err(VerifyError::InvalidPublicKey)));
+    /// ```
+    pub fn build(self) -> Result<Verifier, VerifyError> {
+        let der = STANDARD
+            .decode(self.public_key.trim())
+            .map_err(|_| VerifyError::InvalidPublicKey)?;
+        let key =
+            VerifyingKey::from_public_key_der(&der).map_err(|_| VerifyError::InvalidPublicKey)?;
+        Ok(Verifier {
+            key,
+            tolerance: self.tolerance,
+        })
+    }
+}
+
+impl Verifier {
+    /// Verifies a request from its signature and timestamp headers and raw body, returning the
+    /// events in the body only if SendGrid signed it within the tolerance of the current time.
+    pub fn verify(
+        &self,
+        signature: &str,
+        timestamp: &str,
+        body: &[u8],
+    ) -> Result<Vec<Event>, VerifyError> {
+        self.verify_with_clock(signature, timestamp, body, &SystemClock)
+    }
+
+    /// Like `verify`, but checks the timestamp against `clock` instead of the system time.
+    pub fn verify_with_clock(
+        &self,
+        signature: &str,
+        timestamp: &str,
+        body: &[u8],
+        clock: &impl Clock,
+    ) -> Result<Vec<Event>, VerifyError> {
+        let signature = STANDARD
+            .decode(signature.trim())
+            .ok()
+            .and_then(|der| Signature::from_der(&der).ok())
+            .ok_or(VerifyError::MalformedSignature)?;
+        let time: u64 = timestamp
+            .trim()
+            .parse()
+            .map_err(|_| VerifyError::MalformedTimestamp)?;
+
+        let now = clock::unix_seconds(clock.now());
+        if now.abs_diff(time) > self.tolerance.as_secs() {
+            return Err(VerifyError::StaleTimestamp(time));
+        }
+
+        let mut payload = timestamp.trim().as_bytes().to_vec();
+        payload.extend_from_slice(body);
+        let signature = signature.normalize_s().unwrap_or(signature);
+        self.key
+            .verify(&payload, &signature)
+            .map_err(|_| VerifyError::BadSignature)?;
+        webhook::parse(body).map_err(VerifyError::Parse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{VerifierBuilder, VerifyError};
+    use crate::clock::FakeClock;
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use p256::ecdsa::signature::Signer;
+    use p256::ecdsa::{Signature, SigningKey};
+    use p256::pkcs8::EncodePublicKey;
+    use std::time::Duration;
+
+    const BODY: &[u8] = br#"[{"email":"a@example.com","timestamp":1700000000,"event":"open"}]"#;
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_slice(&[7; 32]).unwrap()
+    }
+
+    fn public_key(key: &SigningKey) -> String {
+        let der = key.verifying_key().to_public_key_der().unwrap();
+        STANDARD.encode(der.as_bytes())
+    }
+
+    fn sign(key: &SigningKey, timestamp: &str, body: &[u8]) -> String {
+        let mut payload = timestamp.as_bytes().to_vec();
+        payload.extend_from_slice(body);
+        let signature: Signature = key.sign(&payload);
+        STANDARD.encode(signature.to_der().as_bytes())
+    }
+
+    #[test]
+    fn accepts_signed_requests() {
+        let key = signing_key();
+        let verifier = VerifierBuilder::new(public_key(&key)).build().unwrap();
+        let signature = sign(&key, "1700000000", BODY);
+
+        let events = verifier
+            .verify_with_clock(
+                &signature,
+                "1700000000",
+                BODY,
+                &FakeClock::at(1_700_000_000),
+            )
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].name(), "open");
+    }
+
+    #[test]
+    fn rejects_tampered_requests() {
+        let key = signing_key();
+        let verifier = VerifierBuilder::new(public_key(&key)).build().unwrap();
+        let signature = sign(&key, "1700000000", BODY);
+
+        let tampered = String::from_utf8_lossy(BODY).replace("open", "click");
+        assert!(matches!(
+            verifier.verify_with_clock(
+                &signature,
+                "1700000000",
+                tampered.as_bytes(),
+                &FakeClock::at(1_700_000_000)
+            ),
+            Err(VerifyError::BadSignature)
+        ));
+        assert!(matches!(
+            verifier.verify_with_clock(
+                &signature,
+                "1700000001",
+                BODY,
+                &FakeClock::at(1_700_000_000)
+            ),
+            Err(VerifyError::BadSignature)
+        ));
+
+        let other = VerifierBuilder::new(public_key(&SigningKey::from_slice(&[8; 32]).unwrap()))
+            .build()
+            .unwrap();
+        assert!(matches!(
+            other.verify_with_clock(
+                &signature,
+                "1700000000",
+                BODY,
+                &FakeClock::at(1_700_000_000)
+            ),
+            Err(VerifyError::BadSignature)
+        ));
+    }
+
+    #[test]
+    fn enforces_timestamp_tolerance() {
+        let key = signing_key();
+        let verifier = VerifierBuilder::new(public_key(&key))
+            .tolerance(Duration::from_secs(60))
+            .build()
+            .unwrap();
+
+        for (timestamp, accepted) in [
+            ("1699999940", true),
+            ("1700000060", true),
+            ("1699999939", false),
+            ("1700000061", false),
+        ] {
+            let signature = sign(&key, timestamp, BODY);
+            let result = verifier.verify_with_clock(
+                &signature,
+                timestamp,
+                BODY,
+                &FakeClock::at(1_700_000_000),
+            );
+            if accepted {
+                assert!(result.is_ok(), "{}", timestamp);
+            } else {
+                assert!(
+                    matches!(result, Err(VerifyError::StaleTimestamp(t)) if t.to_string() == timestamp)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let key = signing_key();
+        let verifier = VerifierBuilder::new(public_key(&key)).build().unwrap();
+        let signature = sign(&key, "1700000000", BODY);
+
+        assert!(matches!(
+            verifier.verify_with_clock(
+                "bm90IGRlcg==",
+                "1700000000",
+                BODY,
+                &FakeClock::at(1_700_000_000)
+            ),
+            Err(VerifyError::MalformedSignature)
+        ));
+        assert!(matches!(
+            verifier.verify_with_clock(
+                &signature,
+                "yesterday",
+                BODY,
+                &FakeClock::at(1_700_000_000)
+            ),
+            Err(VerifyError::MalformedTimestamp)
+        ));
+
+        let body = b"not json";
+        let signature = sign(&key, "1700000000", body);
+        assert!(matches!(
+            verifier.verify_with_clock(
+                &signature,
+                "1700000000",
+                body,
+                &FakeClock::at(1_700_000_000)
+            ),
+            Err(VerifyError::Parse(_))
+        ));
+    }
+}
@@ -0,0 +1,738 @@
+//! Sending a `Message` through SendGrid's SMTP relay, as a fallback for when the HTTP API is
+//! unreachable. Features of the v3 API that SMTP has no place for, such as categories, custom
+//! args, ASM groups, `send_at`, the IP pool and the mail and tracking settings, are carried in the
+//! `X-SMTPAPI` header.
+//!
+//! Each `Personalization` becomes one `Envelope`, rendered with [mime](../mime/index.html) so
+//! substitutions are applied before sending. `batch_id` and sandbox mode have no SMTP equivalent
+//! and are ignored.
+//!
+//! SendGrid's relay requires authentication, with the username `apikey` and an API key as the
+//! password. This crate does not provide TLS and won't send the key in the clear, so it does not
+//! connect by itself: open a TLS connection to `SENDGRID_RELAY_TLS` (implicit TLS on port 465)
+//! with the TLS crate of your choice, and pass the stream to `SmtpTransport::send_over`.
+
+use crate::clock::{Clock, SystemClock};
+use crate::message::Message;
+use crate::mime::{self, RenderError};
+use crate::personalization::Personalization;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// The JSON carried in the `X-SMTPAPI` header
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct SmtpApiHeader {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    category: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    unique_args: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    section: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    send_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asm_group_id: Option<i32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    asm_groups_to_display: Vec<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip_pool: Option<String>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    filters: Map<String, Value>,
+}
+
+impl SmtpApiHeader {
+    /// Builds the header for the recipients of `personalization`
+    pub fn new(message: &Message, personalization: &Personalization) -> Self {
+        // Sorted, so the same message always renders the same header
+        let mut unique_args: BTreeMap<_, _> = message.custom_args.clone().into_iter().collect();
+        unique_args.extend(personalization.custom_args.clone());
+        let mut header = SmtpApiHeader {
+            category: message.categories.clone(),
+            unique_args,
+            section: message.sections.clone().into_iter().collect(),
+            send_at: personalization.send_at.or(message.send_at),
+            asm_group_id: message.asm.as_ref().map(|a| a.group_id),
+            asm_groups_to_display: message
+                .asm
+                .as_ref()
+                .map(|a| a.groups_to_display.clone())
+                .unwrap_or_default(),
+            ip_pool: message.ip_pool_name.clone(),
+            filters: Map::new(),
+        };
+
+        if let Some(template_id) = &message.template_id {
+            header.filter("templates", json!({ "template_id": template_id }));
+        }
+        if let Some(settings) = &message.mail_settings {
+            if let Some(bcc) = &settings.bcc {
+                header.filter("bcc", json!({ "email": bcc.email }));
+            }
+            if settings.bypass_list_management.is_some() {
+                header.filter("bypass_list_management", json!({}));
+            }
+            if let Some(footer) = &settings.footer {
+                header.filter(
+                    "footer",
+                    json!({ "text/plain": footer.text, "text/html": footer.html }),
+                );
+            }
+            if let Some(spam_check) = &settings.spam_check {
+                header.filter(
+                    "spamcheck",
+                    json!({ "maxscore": spam_check.threshold, "url": spam_check.post_to_url }),
+                );
+            }
+        }
+        if let Some(settings) = &message.tracking_settings {
+            if let Some(click) = &settings.click_tracking {
+                header.filter(
+                    "clicktrack",
+                    json!({ "enable_text": u8::from(click.enable_text) }),
+                );
+            }
+            if let Some(open) = &settings.open_tracking {
+                header.filter("opentrack", json!({ "replace": open.substitution_tag }));
+            }
+            if let Some(subscription) = &settings.subscription_tracking {
+                header.filter(
+                    "subscriptiontrack",
+                    json!({
+                        "text/plain": subscription.text,
+                        "text/html": subscription.html,
+                        "replace": subscription.substitution_tag,
+                    }),
+                );
+            }
+            if let Some(ga) = &settings.ganalytics {
+                header.filter(
+                    "ganalytics",
+                    json!({
+                        "utm_source": ga.utm_source,
+                        "utm_medium": ga.utm_medium,
+                        "utm_term": ga.utm_term,
+                        "utm_content": ga.utm_content,
+                        "utm_campaign": ga.utm_campaign,
+                    }),
+                );
+            }
+        }
+        header
+    }
+
+    /// Adds an enabled filter, leaving out settings that are not set
+    fn filter(&mut self, name: &str, settings: Value) {
+        let mut settings = match settings {
+            Value::Object(map) => map,
+            _ => Map::new(),
+        };
+        settings.retain(|_, v| !v.is_null());
+        settings.insert(String::from("enable"), json!(1));
+        self.filters
+            .insert(name.to_string(), json!({ "settings": settings }));
+    }
+
+    /// Returns true if nothing needs to be sent in the header
+    pub fn is_empty(&self) -> bool {
+        *self == SmtpApiHeader::default()
+    }
+
+    /// Formats the header value. Non-ASCII characters are escaped, and the JSON is folded over
+    /// multiple lines, also before spaces within long strings, so no line of the header is
+    /// longer than SMTP's limit of 998 octets. Fails with `RenderError::LineTooLong` if a string
+    /// has more than that without a space to fold at.
+    pub fn to_header_value(&self) -> Result<String, RenderError> {
+        let json = serde_json::to_string_pretty(self).expect("could not serialize X-SMTPAPI");
+        let mut escaped = String::new();
+        for c in json.chars() {
+            if c.is_ascii() {
+                escaped.push(c);
+            } else {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    escaped.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+        }
+
+        // Leaves room for the header name on the first line
+        let limit = MAX_LINE_LENGTH - "X-SMTPAPI: ".len();
+        let mut lines = Vec::new();
+        for (i, line) in escaped.split('\n').enumerate() {
+            let mut line = if i == 0 {
+                line.to_string()
+            } else {
+                format!(" {}", line)
+            };
+            // Unfolding only removes the CRLF, so folding before a space keeps strings intact
+            while line.len() > limit {
+                let at = line[..=limit]
+                    .rfind(' ')
+                    .filter(|&at| !line[..at].trim().is_empty())
+                    .ok_or_else(|| RenderError::LineTooLong(String::from("X-SMTPAPI")))?;
+                let rest = line.split_off(at);
+                lines.push(line);
+                line = rest;
+            }
+            lines.push(line);
+        }
+        Ok(lines.join("\r\n"))
+    }
+}
+
+/// The longest line SMTP allows, excluding the CRLF
+const MAX_LINE_LENGTH: usize = 998;
+
+/// What to send over SMTP for one `Personalization`: the sender, every recipient (to, cc and
+/// bcc), and the rendered message including the `X-SMTPAPI` header
+#[derive(Debug, Clone, PartialEq)]
+pub struct Envelope {
+    from: String,
+    recipients: Vec<String>,
+    data: String,
+}
+
+impl Envelope {
+    /// The address for `MAIL FROM`
+    pub fn from(&self) -> &str {
+        &self.from
+    }
+
+    /// The addresses for `RCPT TO`
+    pub fn recipients(&self) -> &[String] {
+        &self.recipients
+    }
+
+    /// The message for `DATA`, before dot-stuffing
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+}
+
+/// Converts the `Message` into one `Envelope` per `Personalization`
+pub fn envelopes(message: &Message) -> Result<Vec<Envelope>, RenderError> {
+    envelopes_with_clock(message, &SystemClock)
+}
+
+/// Converts the `Message` into one `Envelope` per `Personalization`, dated by `clock`
+pub fn envelopes_with_clock(
+    message: &Message,
+    clock: &impl Clock,
+) -> Result<Vec<Envelope>, RenderError> {
+    message
+        .personalizations
+        .iter()
+        .map(|personalization| {
+            let mut data = String::new();
+            let header = SmtpApiHeader::new(message, personalization);
+            if !header.is_empty() {
+                data.push_str(&format!("X-SMTPAPI: {}\r\n", header.to_header_value()?));
+            }
+            data.push_str(&mime::render_with_clock(message, personalization, clock)?);
+            Ok(Envelope {
+                from: message.from.email.clone(),
+                recipients: personalization
+                    .to
+                    .iter()
+                    .chain(&personalization.cc)
+                    .chain(&personalization.bcc)
+                    .map(|c| c.email.clone())
+                    .collect(),
+                data,
+            })
+        })
+        .collect()
+}
+
+/// The error returned when sending over SMTP fails
+#[derive(Debug)]
+pub enum SmtpError {
+    /// The connection failed
+    Io(io::Error),
+    /// The server rejected a command
+    Reply { code: u16, message: String },
+    /// The `Message` could not be rendered
+    Render(RenderError),
+    /// An envelope address contains a line break or angle bracket and can't be sent in
+    /// `MAIL FROM` or `RCPT TO`
+    InvalidAddress(String),
+}
+
+impl fmt::Display for SmtpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SmtpError::Io(e) => write!(f, "SMTP connection failed: {}", e),
+            SmtpError::Reply { code, message } => {
+                write!(f, "SMTP server replied {}: {}", code, message)
+            }
+            SmtpError::Render(e) => write!(f, "{}", e),
+            SmtpError::InvalidAddress(address) => {
+                write!(f, "invalid envelope address {:?}", address)
+            }
+        }
+    }
+}
+
+impl Error for SmtpError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SmtpError::Io(e) => Some(e),
+            SmtpError::Render(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SmtpError {
+    fn from(e: io::Error) -> Self {
+        SmtpError::Io(e)
+    }
+}
+
+impl From<RenderError> for SmtpError {
+    fn from(e: RenderError) -> Self {
+        SmtpError::Render(e)
+    }
+}
+
+/// The address of SendGrid's SMTP relay for connections that start with TLS. Connect to it with
+/// TLS and pass the stream to `SmtpTransport::send_over`.
+pub const SENDGRID_RELAY_TLS: &str = "smtp.sendgrid.net:465";
+
+/// Sends a `Message` over an SMTP connection. Use `SmtpTransportBuilder` to construct this.
+pub struct SmtpTransport {
+    credentials: Option<(String, String)>,
+    hello: String,
+}
+
+impl SmtpTransport {
+    /// Sends every envelope of the `Message` over an already established connection,
+    /// authenticating if credentials are set. The stream must be encrypted, e.g. a TLS stream
+    /// to `SENDGRID_RELAY_TLS`, when credentials are set, as they are only base64 encoded.
+    pub fn send_over<S: Read + Write>(
+        &self,
+        stream: S,
+        message: &Message,
+    ) -> Result<(), SmtpError> {
+        self.send_over_with_clock(stream, message, &SystemClock)
+    }
+
+    /// Like `send_over`, but dates the rendered messages by `clock`
+    pub fn send_over_with_clock<S: Read + Write>(
+        &self,
+        stream: S,
+        message: &Message,
+        clock: &impl Clock,
+    ) -> Result<(), SmtpError> {
+        let envelopes = envelopes_with_clock(message, clock)?;
+        for envelope in &envelopes {
+            for address in std::iter::once(&envelope.from).chain(&envelope.recipients) {
+                if address.contains(['\r', '\n', '<', '>']) {
+                    return Err(SmtpError::InvalidAddress(address.clone()));
+                }
+            }
+        }
+
+        let mut session = Session {
+            stream: BufReader::new(stream),
+        };
+        session.expect(&[220])?;
+        session.command(&format!("EHLO {}", self.hello), &[250])?;
+        if let Some((username, password)) = &self.credentials {
+            session.command("AUTH LOGIN", &[334])?;
+            session.command(&STANDARD.encode(username), &[334])?;
+            session.command(&STANDARD.encode(password), &[235])?;
+        }
+        for envelope in &envelopes {
+            session.command(&format!("MAIL FROM:<{}>", envelope.from), &[250])?;
+            for recipient in &envelope.recipients {
+                session.command(&format!("RCPT TO:<{}>", recipient), &[250, 251])?;
+            }
+            session.command("DATA", &[354])?;
+            session.data(&envelope.data)?;
+        }
+        session.command("QUIT", &[221])?;
+        Ok(())
+    }
+}
+
+struct Session<S> {
+    stream: BufReader<S>,
+}
+
+impl<S: Read + Write> Session<S> {
+    fn command(&mut self, line: &str, expected: &[u16]) -> Result<(), SmtpError> {
+        let stream = self.stream.get_mut();
+        stream.write_all(line.as_bytes())?;
+        stream.write_all(b"\r\n")?;
+        stream.flush()?;
+        self.expect(expected)
+    }
+
+    fn data(&mut self, data: &str) -> Result<(), SmtpError> {
+        let stream = self.stream.get_mut();
+        // The terminating CRLF of the last line is written below, not as an extra empty line
+        let data = data.strip_suffix("\r\n").unwrap_or(data);
+        for line in data.split("\r\n") {
+            if line.starts_with('.') {
+                stream.write_all(b".")?;
+            }
+            stream.write_all(line.as_bytes())?;
+            stream.write_all(b"\r\n")?;
+        }
+        stream.write_all(b".\r\n")?;
+        stream.flush()?;
+        self.expect(&[250])
+    }
+
+    /// Reads a possibly multi-line reply and checks its code
+    fn expect(&mut self, expected: &[u16]) -> Result<(), SmtpError> {
+        let mut message = String::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line)? == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            let code = line.get(..3).and_then(|c| c.parse::<u16>().ok());
+            let code = code.ok_or_else(|| SmtpError::Reply {
+                code: 0,
+                message: line.trim_end().to_string(),
+            })?;
+            message.push_str(line.get(4..).unwrap_or_default().trim_end());
+            if line.as_bytes().get(3) == Some(&b'-') {
+                message.push('\n');
+                continue;
+            }
+            return if expected.contains(&code) {
+                Ok(())
+            } else {
+                Err(SmtpError::Reply { code, message })
+            };
+        }
+    }
+}
+
+/// Builder pattern for `SmtpTransport`. Make sure you call `build()` when done to consume this
+/// and return the underlying `SmtpTransport`. Construct with default().
+pub struct SmtpTransportBuilder {
+    transport: SmtpTransport,
+}
+
+impl Default for SmtpTransportBuilder {
+    fn default() -> Self {
+        SmtpTransportBuilder {
+            transport: SmtpTransport {
+                credentials: None,
+                hello: String::from("localhost"),
+            },
+        }
+    }
+}
+
+impl SmtpTransportBuilder {
+    /// Authenticates with `AUTH LOGIN`. For SendGrid the username is `apikey` and the password
+    /// is an API key. Only pass an encrypted stream to `SmtpTransport::send_over` when these
+    /// are set.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::smtp::SmtpTransportBuilder;
+    ///
+    /// let builder = SmtpTransportBuilder::default()
+    ///     .credentials("apikey", "SENDGRID SECRET API KEY");
+    /// ```
+    pub fn credentials<S: Into<String>>(mut self, username: S, password: S) -> Self {
+        self.transport.credentials = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Sets the host name sent with `EHLO`. Defaults to `localhost`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::smtp::SmtpTransportBuilder;
+    ///
+    /// let builder = SmtpTransportBuilder::default()
+    ///     .hello("mail.example.com");
+    /// ```
+    pub fn hello(mut self, host: impl Into<String>) -> Self {
+        self.transport.hello = host.into();
+        self
+    }
+
+    /// Consumes the builder and returns the underlying `SmtpTransport`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::smtp::SmtpTransportBuilder;
+    ///
+    /// let transport = SmtpTransportBuilder::default().build();
+    /// ```
+    pub fn build(self) -> SmtpTransport {
+        self.transport
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{envelopes, envelopes_with_clock, SmtpApiHeader, SmtpError, SmtpTransportBuilder};
+    use crate::clock::FakeClock;
+    use crate::mime::RenderError;
+    use crate::{
+        AsmBuilder, ContactBuilder, Content, MailSettingsBuilder, MessageBuilder,
+        PersonalizationBuilder, TrackingSettingsBuilder,
+    };
+    use serde_json::{json, Value};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn message() -> crate::message::Message {
+        MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "Subject")
+            .content(Content::new("text/plain", "Body\r\n.hidden"))
+            .category("Marketing")
+            .custom_arg("campaign", "spring")
+            .asm(AsmBuilder::new(1).group_to_display(2).build())
+            .ip_pool_name("pool")
+            .send_at(UNIX_EPOCH + Duration::from_secs(1_600_000_000))
+            .mail_settings(
+                MailSettingsBuilder::default()
+                    .bypass_list_management()
+                    .spam_check(Some(5), None)
+                    .build(),
+            )
+            .tracking_settings(
+                TrackingSettingsBuilder::default()
+                    .click_tracking(true)
+                    .open_tracking("[OPEN]")
+                    .build(),
+            )
+            .personalization(
+                PersonalizationBuilder::default()
+                    .to(ContactBuilder::new("a@example.com").build())
+                    .bcc(ContactBuilder::new("hidden@example.com").build())
+                    .custom_arg("user_id", "1")
+                    .build(),
+            )
+            .personalization(
+                PersonalizationBuilder::default()
+                    .to(ContactBuilder::new("c@example.com").build())
+                    .send_at(UNIX_EPOCH + Duration::from_secs(1_600_000_100))
+                    .build(),
+            )
+            .build()
+    }
+
+    #[test]
+    fn builds_smtpapi_header() {
+        let message = message();
+        let header = SmtpApiHeader::new(&message, &message.personalizations[0]);
+        let value: Value = serde_json::from_str(&header.to_header_value().unwrap()).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "category": ["Marketing"],
+                "unique_args": {"campaign": "spring", "user_id": "1"},
+                "send_at": 1_600_000_000,
+                "asm_group_id": 1,
+                "asm_groups_to_display": [2],
+                "ip_pool": "pool",
+                "filters": {
+                    "bypass_list_management": {"settings": {"enable": 1}},
+                    "spamcheck": {"settings": {"enable": 1, "maxscore": 5}},
+                    "clicktrack": {"settings": {"enable": 1, "enable_text": 1}},
+                    "opentrack": {"settings": {"enable": 1, "replace": "[OPEN]"}},
+                }
+            })
+        );
+        let second = SmtpApiHeader::new(&message, &message.personalizations[1]);
+        assert_eq!(second.send_at, Some(1_600_000_100));
+    }
+
+    #[test]
+    fn escapes_and_folds_header() {
+        let message = MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "s")
+            .category("caf\u{e9} \u{1f600}")
+            .build();
+        let value = SmtpApiHeader::new(&message, &Default::default())
+            .to_header_value()
+            .unwrap();
+        assert!(value.is_ascii());
+        assert!(value.contains("\r\n "));
+        assert!(value.split("\r\n").skip(1).all(|l| l.starts_with(" ")));
+        assert!(value.contains("caf\\u00e9 \\ud83d\\ude00"));
+    }
+
+    #[test]
+    fn folds_long_values_at_spaces() {
+        let long = "word ".repeat(500);
+        let message = MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "s")
+            .custom_arg("note", long.as_str())
+            .build();
+        let header = SmtpApiHeader::new(&message, &Default::default());
+        let value = header.to_header_value().unwrap();
+        let first = format!("X-SMTPAPI: {}", value);
+        assert!(first.split("\r\n").all(|l| l.len() <= 998));
+        assert!(value.split("\r\n").skip(1).all(|l| l.starts_with(" ")));
+        let unfolded: Value = serde_json::from_str(&value.replace("\r\n", "")).unwrap();
+        assert_eq!(unfolded["unique_args"]["note"], long);
+
+        let message = MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "s")
+            .custom_arg("note", "x".repeat(1000).as_str())
+            .build();
+        let header = SmtpApiHeader::new(&message, &Default::default());
+        match header.to_header_value() {
+            Err(RenderError::LineTooLong(name)) => assert_eq!(name, "X-SMTPAPI"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn one_envelope_per_personalization() {
+        let envelopes = envelopes(&message()).unwrap();
+        assert_eq!(envelopes.len(), 2);
+        assert_eq!(envelopes[0].from(), "from@example.com");
+        assert_eq!(
+            envelopes[0].recipients(),
+            ["a@example.com", "hidden@example.com"]
+        );
+        assert!(envelopes[0].data().starts_with("X-SMTPAPI: {"));
+        assert!(!envelopes[0].data().contains("hidden@example.com"));
+        assert_eq!(envelopes[1].recipients(), ["c@example.com"]);
+    }
+
+    /// A minimal SMTP sink that accepts one session and returns everything the client sent. It
+    /// doesn't advertise STARTTLS.
+    fn sink(reject_rcpt: bool) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut transcript = String::new();
+            let mut in_data = false;
+            writer.write_all(b"220 sink ready\r\n").unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                transcript.push_str(&line);
+                let line = line.trim_end_matches("\r\n").to_string();
+                let reply: &[u8] = if in_data {
+                    if line != "." {
+                        continue;
+                    }
+                    in_data = false;
+                    b"250 queued\r\n"
+                } else if line.starts_with("EHLO") {
+                    b"250-sink\r\n250 AUTH LOGIN\r\n"
+                } else if line == "AUTH LOGIN" || line == "YXBpa2V5" {
+                    b"334 \r\n"
+                } else if line == "S0VZ" {
+                    b"235 authenticated\r\n"
+                } else if line.starts_with("RCPT") && reject_rcpt {
+                    b"550 no such user\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                writer.write_all(reply).unwrap();
+            }
+            transcript
+        });
+        (address, handle)
+    }
+
+    #[test]
+    fn sends_envelopes_to_sink() {
+        let (address, sink) = sink(false);
+        // Stands in for an encrypted stream
+        let stream = TcpStream::connect(address).unwrap();
+        SmtpTransportBuilder::default()
+            .credentials("apikey", "KEY")
+            .build()
+            .send_over_with_clock(stream, &message(), &FakeClock::at(1_600_000_000))
+            .unwrap();
+        let transcript = sink.join().unwrap();
+        let lines: Vec<_> = transcript.lines().collect();
+        assert_eq!(lines[0], "EHLO localhost");
+        assert_eq!(lines[1..4], ["AUTH LOGIN", "YXBpa2V5", "S0VZ"]);
+        assert_eq!(
+            lines[4..7],
+            [
+                "MAIL FROM:<from@example.com>",
+                "RCPT TO:<a@example.com>",
+                "RCPT TO:<hidden@example.com>"
+            ]
+        );
+        assert!(lines.iter().any(|l| l.starts_with("X-SMTPAPI: {")));
+        assert!(lines.contains(&"..hidden"));
+        assert_eq!(lines.iter().filter(|l| **l == "DATA").count(), 2);
+        assert_eq!(*lines.last().unwrap(), "QUIT");
+
+        let data = &envelopes_with_clock(&message(), &FakeClock::at(1_600_000_000)).unwrap()[0];
+        assert!(data.data().ends_with(".hidden\r\n"));
+        let start = transcript.find("DATA\r\n").unwrap() + "DATA\r\n".len();
+        let end = start + transcript[start..].find("\r\n.\r\n").unwrap() + "\r\n.\r\n".len();
+        assert_eq!(
+            &transcript[start..end],
+            format!("{}.\r\n", data.data().replace("\r\n.", "\r\n.."))
+        );
+    }
+
+    #[test]
+    fn refuses_injected_envelope_addresses() {
+        let message = MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "s")
+            .content(Content::new("text/plain", "Body"))
+            .personalization(
+                PersonalizationBuilder::default()
+                    .to(ContactBuilder::new("a@example.com").build())
+                    .bcc(ContactBuilder::new("b@example.com>\r\nRCPT TO:<evil@example.com").build())
+                    .build(),
+            )
+            .build();
+        let (address, sink) = sink(false);
+        let stream = TcpStream::connect(address).unwrap();
+        let result = SmtpTransportBuilder::default()
+            .build()
+            .send_over(stream, &message);
+        match result {
+            Err(SmtpError::InvalidAddress(address)) => {
+                assert_eq!(address, "b@example.com>\r\nRCPT TO:<evil@example.com")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(sink.join().unwrap(), "");
+    }
+
+    #[test]
+    fn reports_rejected_commands() {
+        let (address, sink) = sink(true);
+        let stream = TcpStream::connect(address).unwrap();
+        let result = SmtpTransportBuilder::default()
+            .build()
+            .send_over(stream, &message());
+        match result {
+            Err(SmtpError::Reply { code, message }) => {
+                assert_eq!(code, 550);
+                assert_eq!(message, "no such user");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        sink.join().unwrap();
+    }
+}
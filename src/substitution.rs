@@ -0,0 +1,293 @@
+//! Applies SendGrid's legacy substitutions and sections locally, so fully substituted emails can
+//! be previewed offline.
+//!
+//! A `Personalization`'s substitutions replace their keys in the subject and each `Content`.
+//! After substituting, any section key of the `Message` found in the result is replaced with the
+//! section's value, which is itself substituted and may contain further section keys. Text is
+//! scanned once from left to right, replacing the longest key that matches at each position, so
+//! a substituted value is never substituted again.
+//!
+//! # Examples
+//! ```
+//! # use sendgrid_rs::{ContactBuilder, Content, MessageBuilder, PersonalizationBuilder};
+//! # use sendgrid_rs::substitution;
+//! let message = MessageBuilder::new(
+//!     ContactBuilder::new("from@example.com").build(),
+//!     "Hello -name-",
+//! )
+//! .content(Content::new("text/plain", "-greeting- Thanks for signing up."))
+//! .section("%en_greeting%", "Welcome aboard, -name-!")
+//! .build();
+//! let personalization = PersonalizationBuilder::default()
+//!     .substitution("-name-", "Alice")
+//!     .substitution("-greeting-", "%en_greeting%")
+//!     .build();
+//!
+//! let substituted = substitution::apply(&message, &personalization).unwrap();
+//! assert_eq!(substituted.subject(), "Hello Alice");
+//! assert_eq!(
+//!     substituted.content()[0].value(),
+//!     "Welcome aboard, Alice! Thanks for signing up."
+//! );
+//! ```
+
+use crate::message::Message;
+use crate::personalization::Personalization;
+use crate::Content;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// The maximum total size in bytes of the keys and values of a `Personalization`'s substitutions
+pub const MAX_SUBSTITUTIONS_SIZE: usize = 10_000;
+
+/// The reasons substitutions can't be applied
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum SubstitutionError {
+    /// The substitutions total more than `MAX_SUBSTITUTIONS_SIZE` bytes
+    TooLarge(usize),
+    /// The section with this key expands into itself
+    SectionCycle(String),
+}
+
+impl fmt::Display for SubstitutionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubstitutionError::TooLarge(size) => write!(
+                f,
+                "substitutions total {} bytes, exceeding the limit of {}",
+                size, MAX_SUBSTITUTIONS_SIZE
+            ),
+            SubstitutionError::SectionCycle(key) => {
+                write!(f, "section {} expands into itself", key)
+            }
+        }
+    }
+}
+
+impl Error for SubstitutionError {}
+
+/// The subject and content of a `Message` as received by one `Personalization`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Substituted {
+    subject: String,
+    content: Vec<Content>,
+}
+
+impl Substituted {
+    /// The substituted subject, from the `Personalization` if it sets one
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    /// The substituted content, in the same order as the `Message`'s
+    pub fn content(&self) -> &[Content] {
+        &self.content
+    }
+}
+
+/// Applies `personalization`'s substitutions and `message`'s sections to the subject and content.
+pub fn apply(
+    message: &Message,
+    personalization: &Personalization,
+) -> Result<Substituted, SubstitutionError> {
+    let size = substitutions_size(personalization);
+    if size > MAX_SUBSTITUTIONS_SIZE {
+        return Err(SubstitutionError::TooLarge(size));
+    }
+
+    let engine = Engine::new(&personalization.substitutions, &message.sections);
+    let subject = personalization
+        .subject
+        .as_deref()
+        .unwrap_or(&message.subject);
+    let subject = engine.expand(subject, &mut vec![])?;
+    let content = message
+        .content
+        .iter()
+        .map(|c| {
+            Ok(Content {
+                c_type: c.c_type.clone(),
+                value: engine.expand(&c.value, &mut vec![])?,
+            })
+        })
+        .collect::<Result<_, SubstitutionError>>()?;
+    Ok(Substituted { subject, content })
+}
+
+/// The size SendGrid counts against `MAX_SUBSTITUTIONS_SIZE`
+pub(crate) fn substitutions_size(personalization: &Personalization) -> usize {
+    personalization
+        .substitutions
+        .iter()
+        .map(|(k, v)| k.len() + v.len())
+        .sum()
+}
+
+struct Engine<'a> {
+    substitutions: Keys<'a>,
+    sections: Keys<'a>,
+}
+
+impl<'a> Engine<'a> {
+    fn new(
+        substitutions: &'a HashMap<String, String>,
+        sections: &'a HashMap<String, String>,
+    ) -> Self {
+        Engine {
+            substitutions: Keys::new(substitutions),
+            sections: Keys::new(sections),
+        }
+    }
+
+    /// Substitutes `text`, then expands the sections in the result. `active` holds the keys of the
+    /// sections being expanded, to catch a section that contains itself.
+    fn expand(&self, text: &str, active: &mut Vec<&'a str>) -> Result<String, SubstitutionError> {
+        let substituted = self
+            .substitutions
+            .replace(text, |_, value| Ok(value.to_owned()))?;
+        self.sections.replace(&substituted, |key, value| {
+            if active.contains(&key) {
+                return Err(SubstitutionError::SectionCycle(key.to_owned()));
+            }
+            active.push(key);
+            let expanded = self.expand(value, active);
+            active.pop();
+            expanded
+        })
+    }
+}
+
+/// Replacement keys, longest first so a key that contains another key is replaced whole
+struct Keys<'a> {
+    keys: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Keys<'a> {
+    fn new(map: &'a HashMap<String, String>) -> Self {
+        let mut keys: Vec<(&str, &str)> = map
+            .iter()
+            .filter(|(k, _)| !k.is_empty())
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        keys.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then(a.0.cmp(b.0)));
+        Keys { keys }
+    }
+
+    fn longest_match(&self, text: &str) -> Option<(&'a str, &'a str)> {
+        self.keys.iter().copied().find(|(k, _)| text.starts_with(k))
+    }
+
+    /// Replaces every key in `text` with what `expand` makes of the key and its value, scanning
+    /// once
+    fn replace(
+        &self,
+        text: &str,
+        mut expand: impl FnMut(&'a str, &'a str) -> Result<String, SubstitutionError>,
+    ) -> Result<String, SubstitutionError> {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while !rest.is_empty() {
+            match self.longest_match(rest) {
+                Some((key, value)) => {
+                    out.push_str(&expand(key, value)?);
+                    rest = &rest[key.len()..];
+                }
+                None => {
+                    let c = rest.chars().next().unwrap_or_default();
+                    out.push(c);
+                    rest = &rest[c.len_utf8()..];
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, SubstitutionError};
+    use crate::{ContactBuilder, Content, MessageBuilder, PersonalizationBuilder};
+
+    fn builder() -> MessageBuilder {
+        MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "-subject-")
+    }
+
+    #[test]
+    fn expands_nested_sections() {
+        let message = builder()
+            .content(Content::new("text/html", "<p>-body-</p>"))
+            .content(Content::new("text/plain", "-body- -body-"))
+            .section("%body%", "%greeting%, -name-. %footer%")
+            .section("%greeting%", "Hi")
+            .section("%footer%", "Bye -name-")
+            .build();
+        let personalization = PersonalizationBuilder::default()
+            .subject("For -name-")
+            .substitution("-name-", "Ann")
+            .substitution("-body-", "%body%")
+            .build();
+
+        let substituted = apply(&message, &personalization).unwrap();
+        assert_eq!(substituted.subject(), "For Ann");
+        assert_eq!(
+            substituted.content()[0],
+            Content::new("text/html", "<p>Hi, Ann. Bye Ann</p>")
+        );
+        assert_eq!(
+            substituted.content()[1].value(),
+            "Hi, Ann. Bye Ann Hi, Ann. Bye Ann"
+        );
+    }
+
+    #[test]
+    fn replaces_longest_keys_once() {
+        let message = builder()
+            .content(Content::new("text/plain", "-name- -name_full- -a-"))
+            .build();
+        let personalization = PersonalizationBuilder::default()
+            .substitution("-name-", "Ann")
+            .substitution("-name_full-", "Ann Smith")
+            .substitution("-a-", "-name-")
+            .build();
+
+        let substituted = apply(&message, &personalization).unwrap();
+        assert_eq!(substituted.subject(), "-subject-");
+        assert_eq!(substituted.content()[0].value(), "Ann Ann Smith -name-");
+    }
+
+    #[test]
+    fn rejects_section_cycles() {
+        let message = builder()
+            .content(Content::new("text/plain", "%a%"))
+            .section("%a%", "%b%")
+            .section("%b%", "x -loop-")
+            .build();
+        let personalization = PersonalizationBuilder::default()
+            .substitution("-loop-", "%a%")
+            .build();
+
+        assert_eq!(
+            apply(&message, &personalization),
+            Err(SubstitutionError::SectionCycle(String::from("%a%")))
+        );
+    }
+
+    #[test]
+    fn enforces_size_limit() {
+        let message = builder().content(Content::new("text/plain", "x")).build();
+        let personalization = PersonalizationBuilder::default()
+            .substitution("-k-", &"v".repeat(9_997))
+            .build();
+        assert!(apply(&message, &personalization).is_ok());
+
+        let personalization = PersonalizationBuilder::default()
+            .substitution("-k-", &"v".repeat(9_998))
+            .build();
+        assert_eq!(
+            apply(&message, &personalization),
+            Err(SubstitutionError::TooLarge(10_001))
+        );
+    }
+}
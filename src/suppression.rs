@@ -0,0 +1,656 @@
+//! Models and requests for managing suppression lists. SendGrid stops delivering to an address
+//! once it bounces, is blocked, reports a message as spam, or is found to be invalid, and keeps
+//! it on the matching list until it is removed.
+//!
+//! Each request implements [Endpoint](../client/trait.Endpoint.html) and is made with
+//! `Client::call`.
+//!
+//! # Examples
+//! ```
+//! # use sendgrid_rs::suppression::{DeleteSuppressionsBuilder, ListSuppressionsBuilder, SuppressionList};
+//! # use sendgrid_rs::client::ClientBuilder;
+//! # use sendgrid_rs::transport::{Request, Response, Transport};
+//! # use std::time::{Duration, SystemTime};
+//! # struct Fake;
+//! # impl Transport for Fake {
+//! #     type Error = std::io::Error;
+//! #     fn send(&self, request: &Request) -> Result<Response, Self::Error> {
+//! #         Ok(Response::new(200, r#"[{"created": 1700000000, "email": "old@example.com", "reason": "550 5.1.1 User unknown", "status": "5.1.1"}]"#))
+//! #     }
+//! # }
+//! let client = ClientBuilder::new("SENDGRID SECRET API KEY", Fake).build();
+//!
+//! let week_ago = SystemTime::now() - Duration::from_secs(7 * 24 * 60 * 60);
+//! let bounces = client
+//!     .call(
+//!         &ListSuppressionsBuilder::new(SuppressionList::Bounces)
+//!             .start_time(week_ago)
+//!             .limit(100)
+//!             .build(),
+//!     )
+//!     .unwrap();
+//!
+//! let request = bounces
+//!     .iter()
+//!     .fold(DeleteSuppressionsBuilder::new(SuppressionList::Bounces), |builder, bounce| {
+//!         builder.email(bounce.email())
+//!     })
+//!     .try_build()
+//!     .unwrap();
+//! ```
+
+use crate::client::{encode_path_segment, Endpoint};
+use crate::clock;
+use crate::transport::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The path of the bounce list endpoints, relative to the base URL
+pub const BOUNCES_PATH: &str = "/v3/suppression/bounces";
+
+/// The path of the block list endpoints, relative to the base URL
+pub const BLOCKS_PATH: &str = "/v3/suppression/blocks";
+
+/// The path of the spam report list endpoints, relative to the base URL
+pub const SPAM_REPORTS_PATH: &str = "/v3/suppression/spam_reports";
+
+/// The path of the invalid email list endpoints, relative to the base URL
+pub const INVALID_EMAILS_PATH: &str = "/v3/suppression/invalid_emails";
+
+/// The suppression lists SendGrid keeps for an account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SuppressionList {
+    /// Addresses whose receiving server rejected a message permanently
+    Bounces,
+    /// Addresses whose receiving server rejected a message for a temporary reason, such as a
+    /// full mailbox or a blocked IP
+    Blocks,
+    /// Addresses whose recipient marked a message as spam
+    SpamReports,
+    /// Addresses that are malformed or whose domain doesn't accept mail
+    InvalidEmails,
+}
+
+impl SuppressionList {
+    /// The path of the list's endpoints, relative to the base URL
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::suppression::SuppressionList;
+    ///
+    /// assert_eq!(SuppressionList::Blocks.path(), "/v3/suppression/blocks");
+    /// ```
+    pub fn path(self) -> &'static str {
+        match self {
+            SuppressionList::Bounces => BOUNCES_PATH,
+            SuppressionList::Blocks => BLOCKS_PATH,
+            SuppressionList::SpamReports => SPAM_REPORTS_PATH,
+            SuppressionList::InvalidEmails => INVALID_EMAILS_PATH,
+        }
+    }
+
+    fn email_path(self, email: &str) -> String {
+        format!("{}/{}", self.path(), encode_path_segment(email))
+    }
+}
+
+/// An address on a suppression list, as returned by SendGrid. Which of the optional fields are
+/// present depends on the list.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Suppression {
+    created: u64,
+    email: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip: Option<String>,
+}
+
+impl Suppression {
+    /// The suppressed address
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
+    /// When the address was added to the list
+    pub fn created(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.created)
+    }
+
+    /// Why the address was added, usually the receiving server's response. Present for bounces,
+    /// blocks and invalid emails.
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    /// The enhanced SMTP status code, e.g. `5.1.1`. Present for bounces and blocks.
+    pub fn status(&self) -> Option<&str> {
+        self.status.as_deref()
+    }
+
+    /// The IP address the message was sent from. Present for spam reports.
+    pub fn ip(&self) -> Option<&str> {
+        self.ip.as_deref()
+    }
+}
+
+/// `GET /v3/suppression/{list}`, lists the addresses on a suppression list, newest first. Use
+/// `ListSuppressionsBuilder` to construct this.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListSuppressions {
+    list: SuppressionList,
+    start_time: Option<u64>,
+    end_time: Option<u64>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+impl Endpoint for ListSuppressions {
+    type Output = Vec<Suppression>;
+
+    fn method(&self) -> Method {
+        Method::Get
+    }
+
+    fn path(&self) -> String {
+        let query: Vec<_> = [
+            ("start_time", self.start_time),
+            ("end_time", self.end_time),
+            ("limit", self.limit.map(u64::from)),
+            ("offset", self.offset.map(u64::from)),
+        ]
+        .iter()
+        .filter_map(|(name, value)| value.map(|value| format!("{}={}", name, value)))
+        .collect();
+        if query.is_empty() {
+            String::from(self.list.path())
+        } else {
+            format!("{}?{}", self.list.path(), query.join("&"))
+        }
+    }
+}
+
+/// Builder pattern for `ListSuppressions`. Make sure you call `build()` when done to consume the
+/// builder and return the underlying `ListSuppressions`.
+pub struct ListSuppressionsBuilder {
+    list: ListSuppressions,
+}
+
+impl ListSuppressionsBuilder {
+    /// Constructs a `ListSuppressionsBuilder`. The required parameter is the list to read.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::suppression::{ListSuppressionsBuilder, SuppressionList};
+    ///
+    /// let builder = ListSuppressionsBuilder::new(SuppressionList::SpamReports);
+    /// ```
+    pub fn new(list: SuppressionList) -> Self {
+        ListSuppressionsBuilder {
+            list: ListSuppressions {
+                list,
+                start_time: None,
+                end_time: None,
+                limit: None,
+                offset: None,
+            },
+        }
+    }
+
+    /// Only lists addresses added at or after `time`. SendGrid counts in whole seconds.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::suppression::{ListSuppressionsBuilder, SuppressionList};
+    /// # use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let builder = ListSuppressionsBuilder::new(SuppressionList::Bounces)
+    ///     .start_time(UNIX_EPOCH + Duration::from_secs(1700000000));
+    /// ```
+    pub fn start_time(mut self, time: SystemTime) -> Self {
+        self.list.start_time = Some(clock::unix_seconds(time));
+        self
+    }
+
+    /// Only lists addresses added at or before `time`. SendGrid counts in whole seconds.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::suppression::{ListSuppressionsBuilder, SuppressionList};
+    /// # use std::time::SystemTime;
+    ///
+    /// let builder = ListSuppressionsBuilder::new(SuppressionList::Bounces)
+    ///     .end_time(SystemTime::now());
+    /// ```
+    pub fn end_time(mut self, time: SystemTime) -> Self {
+        self.list.end_time = Some(clock::unix_seconds(time));
+        self
+    }
+
+    /// Sets the most addresses returned by one call
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::suppression::{ListSuppressionsBuilder, SuppressionList};
+    ///
+    /// let builder = ListSuppressionsBuilder::new(SuppressionList::Blocks).limit(500);
+    /// ```
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.list.limit = Some(limit);
+        self
+    }
+
+    /// Skips the first `offset` addresses, for reading the list a page at a time
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::suppression::{ListSuppressionsBuilder, SuppressionList};
+    ///
+    /// let second_page = ListSuppressionsBuilder::new(SuppressionList::Blocks)
+    ///     .limit(500)
+    ///     .offset(500);
+    /// ```
+    pub fn offset(mut self, offset: u32) -> Self {
+        self.list.offset = Some(offset);
+        self
+    }
+
+    /// Consumes the builder and returns the underlying `ListSuppressions`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::client::Endpoint;
+    /// # use sendgrid_rs::suppression::{ListSuppressionsBuilder, SuppressionList};
+    ///
+    /// let request = ListSuppressionsBuilder::new(SuppressionList::InvalidEmails)
+    ///     .limit(10)
+    ///     .build();
+    /// assert_eq!(request.path(), "/v3/suppression/invalid_emails?limit=10");
+    /// ```
+    pub fn build(self) -> ListSuppressions {
+        self.list
+    }
+}
+
+/// `GET /v3/suppression/{list}/{email}`, looks up an address on a suppression list. The list is
+/// empty if the address isn't suppressed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GetSuppression {
+    list: SuppressionList,
+    email: String,
+}
+
+impl GetSuppression {
+    /// Constructs a `GetSuppression` for `email` on `list`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::suppression::{GetSuppression, SuppressionList};
+    ///
+    /// let request = GetSuppression::new(SuppressionList::Bounces, "to@example.com");
+    /// ```
+    pub fn new(list: SuppressionList, email: impl Into<String>) -> Self {
+        GetSuppression {
+            list,
+            email: email.into(),
+        }
+    }
+}
+
+impl Endpoint for GetSuppression {
+    type Output = Vec<Suppression>;
+
+    fn method(&self) -> Method {
+        Method::Get
+    }
+
+    fn path(&self) -> String {
+        self.list.email_path(&self.email)
+    }
+}
+
+/// `DELETE /v3/suppression/{list}/{email}`, removes an address from a suppression list
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeleteSuppression {
+    list: SuppressionList,
+    email: String,
+}
+
+impl DeleteSuppression {
+    /// Constructs a `DeleteSuppression` for `email` on `list`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::suppression::{DeleteSuppression, SuppressionList};
+    ///
+    /// let request = DeleteSuppression::new(SuppressionList::Blocks, "to@example.com");
+    /// ```
+    pub fn new(list: SuppressionList, email: impl Into<String>) -> Self {
+        DeleteSuppression {
+            list,
+            email: email.into(),
+        }
+    }
+}
+
+impl Endpoint for DeleteSuppression {
+    type Output = ();
+
+    fn method(&self) -> Method {
+        Method::Delete
+    }
+
+    fn path(&self) -> String {
+        self.list.email_path(&self.email)
+    }
+}
+
+/// The reasons a `DeleteSuppressions` can't be built
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SuppressionError {
+    /// No addresses were added. Use `DeleteAllSuppressions` to clear a whole list.
+    NoEmails,
+}
+
+impl fmt::Display for SuppressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SuppressionError::NoEmails => write!(f, "no addresses to delete"),
+        }
+    }
+}
+
+impl Error for SuppressionError {}
+
+/// `DELETE /v3/suppression/{list}`, removes several addresses from a suppression list. Use
+/// `DeleteSuppressionsBuilder` to construct this.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeleteSuppressions {
+    list: SuppressionList,
+    emails: Vec<String>,
+}
+
+impl Endpoint for DeleteSuppressions {
+    type Output = ();
+
+    fn method(&self) -> Method {
+        Method::Delete
+    }
+
+    fn path(&self) -> String {
+        String::from(self.list.path())
+    }
+
+    fn body(&self) -> Option<String> {
+        Some(json!({ "emails": self.emails }).to_string())
+    }
+}
+
+/// Builder pattern for `DeleteSuppressions`. Make sure you call `build()` or `try_build()` when
+/// done to consume the builder and return the underlying `DeleteSuppressions`.
+pub struct DeleteSuppressionsBuilder {
+    delete: DeleteSuppressions,
+}
+
+impl DeleteSuppressionsBuilder {
+    /// Constructs a `DeleteSuppressionsBuilder`. The required parameter is the list to remove
+    /// addresses from.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::suppression::{DeleteSuppressionsBuilder, SuppressionList};
+    ///
+    /// let builder = DeleteSuppressionsBuilder::new(SuppressionList::InvalidEmails);
+    /// ```
+    pub fn new(list: SuppressionList) -> Self {
+        DeleteSuppressionsBuilder {
+            delete: DeleteSuppressions {
+                list,
+                emails: vec![],
+            },
+        }
+    }
+
+    /// Adds an address to remove
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::suppression::{DeleteSuppressionsBuilder, SuppressionList};
+    ///
+    /// let builder = DeleteSuppressionsBuilder::new(SuppressionList::Bounces)
+    ///     .email("first@example.com")
+    ///     .email("second@example.com");
+    /// ```
+    pub fn email(mut self, email: impl Into<String>) -> Self {
+        self.delete.emails.push(email.into());
+        self
+    }
+
+    /// Consumes the builder and returns the underlying `DeleteSuppressions`. Use `try_build` to
+    /// make sure at least one address was added.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::client::Endpoint;
+    /// # use sendgrid_rs::suppression::{DeleteSuppressionsBuilder, SuppressionList};
+    ///
+    /// let request = DeleteSuppressionsBuilder::new(SuppressionList::Blocks)
+    ///     .email("to@example.com")
+    ///     .build();
+    /// assert_eq!(request.body().unwrap(), r#"{"emails":["to@example.com"]}"#);
+    /// ```
+    pub fn build(self) -> DeleteSuppressions {
+        self.delete
+    }
+
+    /// Consumes the builder and returns the underlying `DeleteSuppressions`, or
+    /// `SuppressionError::NoEmails` if no address was added
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::suppression::{DeleteSuppressionsBuilder, SuppressionError, SuppressionList};
+    ///
+    /// let request = DeleteSuppressionsBuilder::new(SuppressionList::Blocks)
+    ///     .email("to@example.com")
+    ///     .try_build();
+    /// assert!(request.is_ok());
+    ///
+    /// let empty = DeleteSuppressionsBuilder::new(SuppressionList::Blocks).try_build();
+    /// assert_eq!(empty, Err(SuppressionError::NoEmails));
+    /// ```
+    pub fn try_build(self) -> Result<DeleteSuppressions, SuppressionError> {
+        if self.delete.emails.is_empty() {
+            return Err(SuppressionError::NoEmails);
+        }
+        Ok(self.delete)
+    }
+}
+
+/// `DELETE /v3/suppression/{list}` with `delete_all`, removes every address from a suppression
+/// list. Recipients who bounced or reported spam will be sent to again, so be sure this is
+/// intended.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeleteAllSuppressions {
+    list: SuppressionList,
+}
+
+impl DeleteAllSuppressions {
+    /// Constructs a `DeleteAllSuppressions` clearing `list`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::suppression::{DeleteAllSuppressions, SuppressionList};
+    ///
+    /// let request = DeleteAllSuppressions::new(SuppressionList::InvalidEmails);
+    /// ```
+    pub fn new(list: SuppressionList) -> Self {
+        DeleteAllSuppressions { list }
+    }
+}
+
+impl Endpoint for DeleteAllSuppressions {
+    type Output = ();
+
+    fn method(&self) -> Method {
+        Method::Delete
+    }
+
+    fn path(&self) -> String {
+        String::from(self.list.path())
+    }
+
+    fn body(&self) -> Option<String> {
+        Some(json!({ "delete_all": true }).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DeleteAllSuppressions, DeleteSuppression, DeleteSuppressionsBuilder, GetSuppression,
+        ListSuppressionsBuilder, SuppressionError, SuppressionList,
+    };
+    use crate::client::ClientBuilder;
+    use crate::transport::{Method, Replay};
+    use serde_json::json;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn lists_with_time_range_and_pagination() {
+        let transport = Replay::new(
+            200,
+            r#"[
+                {"created": 1700000000, "email": "a@example.com", "reason": "550 5.1.1 User unknown", "status": "5.1.1"},
+                {"created": 1690000000, "email": "b@example.com", "ip": "10.63.202.100"}
+            ]"#,
+        );
+        let client = ClientBuilder::new("KEY", &transport).build();
+
+        let request = ListSuppressionsBuilder::new(SuppressionList::Bounces)
+            .start_time(UNIX_EPOCH + Duration::from_millis(1_600_000_000_900))
+            .end_time(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+            .limit(2)
+            .offset(4)
+            .build();
+        let suppressions = client.call(&request).unwrap();
+        assert_eq!(
+            transport.last(),
+            (
+                Method::Get,
+                String::from(
+                    "https://api.sendgrid.com/v3/suppression/bounces\
+                     ?start_time=1600000000&end_time=1700000000&limit=2&offset=4"
+                ),
+                None
+            )
+        );
+
+        assert_eq!(suppressions.len(), 2);
+        assert_eq!(suppressions[0].email(), "a@example.com");
+        assert_eq!(
+            suppressions[0].created(),
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+        assert_eq!(suppressions[0].reason(), Some("550 5.1.1 User unknown"));
+        assert_eq!(suppressions[0].status(), Some("5.1.1"));
+        assert_eq!(suppressions[0].ip(), None);
+        assert_eq!(suppressions[1].reason(), None);
+        assert_eq!(suppressions[1].ip(), Some("10.63.202.100"));
+
+        client
+            .call(&ListSuppressionsBuilder::new(SuppressionList::SpamReports).build())
+            .unwrap();
+        assert_eq!(
+            transport.last().1,
+            "https://api.sendgrid.com/v3/suppression/spam_reports"
+        );
+    }
+
+    #[test]
+    fn gets_and_deletes_one_address() {
+        let transport = Replay::new(200, "[]");
+        let client = ClientBuilder::new("KEY", &transport).build();
+
+        let found = client
+            .call(&GetSuppression::new(
+                SuppressionList::InvalidEmails,
+                "a+b@example.com",
+            ))
+            .unwrap();
+        assert!(found.is_empty());
+        assert_eq!(
+            transport.last(),
+            (
+                Method::Get,
+                String::from(
+                    "https://api.sendgrid.com/v3/suppression/invalid_emails/a%2Bb@example.com"
+                ),
+                None
+            )
+        );
+
+        let transport = Replay::new(204, "");
+        let client = ClientBuilder::new("KEY", &transport).build();
+        client
+            .call(&DeleteSuppression::new(
+                SuppressionList::Blocks,
+                "a@example.com",
+            ))
+            .unwrap();
+        assert_eq!(
+            transport.last(),
+            (
+                Method::Delete,
+                String::from("https://api.sendgrid.com/v3/suppression/blocks/a@example.com"),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn deletes_many_or_all_addresses() {
+        let transport = Replay::new(204, "");
+        let client = ClientBuilder::new("KEY", &transport).build();
+
+        client
+            .call(
+                &DeleteSuppressionsBuilder::new(SuppressionList::Bounces)
+                    .email("a@example.com")
+                    .email("b@example.com")
+                    .try_build()
+                    .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            transport.last(),
+            (
+                Method::Delete,
+                String::from("https://api.sendgrid.com/v3/suppression/bounces"),
+                Some(json!({"emails": ["a@example.com", "b@example.com"]}))
+            )
+        );
+
+        assert_eq!(
+            DeleteSuppressionsBuilder::new(SuppressionList::Bounces).try_build(),
+            Err(SuppressionError::NoEmails)
+        );
+
+        client
+            .call(&DeleteAllSuppressions::new(SuppressionList::SpamReports))
+            .unwrap();
+        assert_eq!(
+            transport.last(),
+            (
+                Method::Delete,
+                String::from("https://api.sendgrid.com/v3/suppression/spam_reports"),
+                Some(json!({"delete_all": true}))
+            )
+        );
+    }
+}
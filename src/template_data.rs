@@ -0,0 +1,28 @@
+use serde::Serialize;
+
+/// A type whose serialized fields are the `dynamic_template_data` of a `Personalization`, so the
+/// Handlebars keys a dynamic template uses are checked by the compiler. Enable the `derive`
+/// feature to `#[derive(TemplateData)]` along with `serde::Serialize`, optionally tying the type
+/// to a template with `#[sendgrid(template_id = "d-...")]`.
+///
+/// # Examples
+/// ```
+/// # use sendgrid_rs::TemplateData;
+/// # use serde::Serialize;
+/// #[derive(Serialize)]
+/// struct Receipt {
+///     order_number: u32,
+/// }
+///
+/// impl TemplateData for Receipt {
+///     fn template_id() -> Option<&'static str> {
+///         Some("d-0123456789abcdef0123456789abcdef")
+///     }
+/// }
+/// ```
+pub trait TemplateData: Serialize {
+    /// The id of the dynamic template this data is for, if any
+    fn template_id() -> Option<&'static str> {
+        None
+    }
+}
@@ -1,41 +1,41 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 /// Struct used for serializing the ClickTracking node into SendGrid's API format. Use
 /// `TrackingSettingsBuilder` to configure this.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ClickTrackingSetting {
-    enable: bool,
-    enable_text: bool,
+    pub(crate) enable: bool,
+    pub(crate) enable_text: bool,
 }
 
 /// Struct used for serializing the OpenTracking node into SendGrid's API format. Use
 /// `TrackingSettingsBuilder` to configure this.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct OpenTrackingSetting {
-    enable: bool,
-    substitution_tag: String,
+    pub(crate) enable: bool,
+    pub(crate) substitution_tag: String,
 }
 
 /// Struct used for serializing the SubscriptionTracking node into SendGrid's API format. Use
 /// `TrackingSettingsBuilder` to configure this.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct SubscriptionTrackingSetting {
-    enable: bool,
-    text: Option<String>,
-    html: Option<String>,
-    substitution_tag: String,
+    pub(crate) enable: bool,
+    pub(crate) text: Option<String>,
+    pub(crate) html: Option<String>,
+    pub(crate) substitution_tag: String,
 }
 
 /// Struct used for serializing the GaTracking  node into SendGrid's API format. Use
 /// `GaTrackingSettingBuilder` to construct this.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct GaTrackingSetting {
-    enable: bool,
-    utm_source: Option<String>,
-    utm_medium: Option<String>,
-    utm_term: Option<String>,
-    utm_content: Option<String>,
-    utm_campaign: Option<String>,
+    pub(crate) enable: bool,
+    pub(crate) utm_source: Option<String>,
+    pub(crate) utm_medium: Option<String>,
+    pub(crate) utm_term: Option<String>,
+    pub(crate) utm_content: Option<String>,
+    pub(crate) utm_campaign: Option<String>,
 }
 
 impl Default for GaTrackingSetting {
@@ -144,12 +144,12 @@ impl GaTrackingSettingBuilder {
 
 /// Configures the SendGrid API node for TrackingSettings. Use `TrackingSettingsBuilder` to
 /// construct this.
-#[derive(Serialize, Default, Debug)]
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
 pub struct TrackingSettings {
-    click_tracking: Option<ClickTrackingSetting>,
-    open_tracking: Option<OpenTrackingSetting>,
-    subscription_tracking: Option<SubscriptionTrackingSetting>,
-    ganalytics: Option<GaTrackingSetting>,
+    pub(crate) click_tracking: Option<ClickTrackingSetting>,
+    pub(crate) open_tracking: Option<OpenTrackingSetting>,
+    pub(crate) subscription_tracking: Option<SubscriptionTrackingSetting>,
+    pub(crate) ganalytics: Option<GaTrackingSetting>,
 }
 
 /// Builder pattern for `TrackingSettings`. Make sure you call `build()` to consume this and return
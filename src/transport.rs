@@ -0,0 +1,333 @@
+//! The `Transport` and `AsyncTransport` traits are how a [Client](../client/struct.Client.html)
+//! talks HTTP. This crate does not pick an HTTP library for you: implement one of these traits for
+//! the library you already use, or turn on the `ureq` (blocking) or `reqwest` (async) cargo
+//! features for a ready made implementation.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+
+/// A boxed future returned by `AsyncTransport`
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// The HTTP methods used by SendGrid's API
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Put,
+    Patch,
+    Delete,
+}
+
+impl Method {
+    /// The method name as it appears in an HTTP request line
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Patch => "PATCH",
+            Method::Delete => "DELETE",
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An HTTP request ready to be sent by a `Transport`. The `Client` constructs these with the
+/// full URL, the authorization header and the JSON body already in place.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    method: Method,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Option<String>,
+}
+
+impl Request {
+    pub(crate) fn new(method: Method, url: impl Into<String>) -> Self {
+        Request {
+            method,
+            url: url.into(),
+            headers: vec![],
+            body: None,
+        }
+    }
+
+    pub(crate) fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub(crate) fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// The HTTP method
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// The full URL, including the base URL the `Client` was configured with
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The headers to send, as name/value pairs
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// The request body, if any
+    pub fn body_str(&self) -> Option<&str> {
+        self.body.as_deref()
+    }
+}
+
+/// An HTTP response returned by a `Transport`. Any status code is a successful `Response` as far
+/// as the `Transport` is concerned, the `Client` decides what is an error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Response {
+    /// Constructs a `Response` from a status code and body. Use this when implementing a
+    /// `Transport`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::transport::Response;
+    ///
+    /// let response = Response::new(202, "")
+    ///     .header("X-Message-Id", "abc123");
+    /// ```
+    pub fn new(status: u16, body: impl Into<String>) -> Self {
+        Response {
+            status,
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+
+    /// Adds a header to the `Response`
+    ///
+    /// # Examples
+    /// ```
+    /// # use sendgrid_rs::transport::Response;
+    ///
+    /// let response = Response::new(202, "")
+    ///     .header("X-Message-Id", "abc123");
+    /// assert_eq!(response.header_value("x-message-id"), Some("abc123"));
+    /// ```
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// The HTTP status code
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Returns true for 2xx status codes
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// All response headers, as name/value pairs
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Looks up a header by name, ignoring case
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The response body
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+}
+
+/// A blocking HTTP transport
+pub trait Transport {
+    /// The error returned when the request could not be completed, e.g. a connection failure
+    type Error;
+
+    /// Performs the `Request` and returns the `Response`, whatever its status code
+    fn send(&self, request: &Request) -> Result<Response, Self::Error>;
+}
+
+impl<T: Transport + ?Sized> Transport for &T {
+    type Error = T::Error;
+
+    fn send(&self, request: &Request) -> Result<Response, Self::Error> {
+        (**self).send(request)
+    }
+}
+
+/// An async HTTP transport
+pub trait AsyncTransport {
+    /// The error returned when the request could not be completed, e.g. a connection failure
+    type Error;
+
+    /// Performs the `Request` and returns the `Response`, whatever its status code
+    fn send<'a>(&'a self, request: &'a Request) -> BoxFuture<'a, Result<Response, Self::Error>>;
+}
+
+/// A blocking `Transport` backed by `ureq`. Enable the `ureq` cargo feature to use this.
+#[cfg(feature = "ureq")]
+pub struct UreqTransport {
+    agent: ureq::Agent,
+}
+
+#[cfg(feature = "ureq")]
+impl UreqTransport {
+    /// Constructs a `UreqTransport` from an existing `ureq::Agent`. The agent must be configured
+    /// not to treat HTTP status codes as errors.
+    pub fn new(agent: ureq::Agent) -> Self {
+        UreqTransport { agent }
+    }
+}
+
+#[cfg(feature = "ureq")]
+impl Default for UreqTransport {
+    fn default() -> Self {
+        let config = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .build();
+        UreqTransport::new(ureq::Agent::new_with_config(config))
+    }
+}
+
+#[cfg(feature = "ureq")]
+impl Transport for UreqTransport {
+    type Error = ureq::Error;
+
+    fn send(&self, request: &Request) -> Result<Response, Self::Error> {
+        let mut builder = ureq::http::Request::builder()
+            .method(request.method().as_str())
+            .uri(request.url());
+        for (name, value) in request.headers() {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        let response = match request.body_str() {
+            Some(body) => self.agent.run(builder.body(body)?)?,
+            None => self.agent.run(builder.body(())?)?,
+        };
+
+        let status = response.status().as_u16();
+        let headers: Vec<(String, String)> = response
+            .headers()
+            .iter()
+            .filter_map(|(n, v)| Some((n.to_string(), v.to_str().ok()?.to_string())))
+            .collect();
+        let body = response.into_body().read_to_string()?;
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+/// An `AsyncTransport` backed by `reqwest`. Enable the `reqwest` cargo feature to use this.
+#[cfg(feature = "reqwest")]
+#[derive(Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "reqwest")]
+impl ReqwestTransport {
+    /// Constructs a `ReqwestTransport` from an existing `reqwest::Client`
+    pub fn new(client: reqwest::Client) -> Self {
+        ReqwestTransport { client }
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl AsyncTransport for ReqwestTransport {
+    type Error = reqwest::Error;
+
+    fn send<'a>(&'a self, request: &'a Request) -> BoxFuture<'a, Result<Response, Self::Error>> {
+        Box::pin(async move {
+            let method = match request.method() {
+                Method::Get => reqwest::Method::GET,
+                Method::Post => reqwest::Method::POST,
+                Method::Put => reqwest::Method::PUT,
+                Method::Patch => reqwest::Method::PATCH,
+                Method::Delete => reqwest::Method::DELETE,
+            };
+            let mut builder = self.client.request(method, request.url());
+            for (name, value) in request.headers() {
+                builder = builder.header(name.as_str(), value.as_str());
+            }
+            if let Some(body) = request.body_str() {
+                builder = builder.body(body.to_string());
+            }
+
+            let response = builder.send().await?;
+            let status = response.status().as_u16();
+            let headers = response
+                .headers()
+                .iter()
+                .filter_map(|(n, v)| Some((n.to_string(), v.to_str().ok()?.to_string())))
+                .collect();
+            let body = response.text().await?;
+            Ok(Response {
+                status,
+                headers,
+                body,
+            })
+        })
+    }
+}
+
+/// A `Transport` for tests that records every request and answers each with the same `Response`
+#[cfg(test)]
+pub(crate) struct Replay {
+    requests: std::cell::RefCell<Vec<Request>>,
+    response: Response,
+}
+
+#[cfg(test)]
+impl Replay {
+    pub(crate) fn new(status: u16, body: &str) -> Self {
+        Replay {
+            requests: std::cell::RefCell::new(vec![]),
+            response: Response::new(status, body),
+        }
+    }
+
+    /// The method, URL and parsed JSON body of the last request
+    pub(crate) fn last(&self) -> (Method, String, Option<serde_json::Value>) {
+        let requests = self.requests.borrow();
+        let request = requests.last().unwrap();
+        let body = request.body_str().map(|b| serde_json::from_str(b).unwrap());
+        (request.method(), request.url().to_string(), body)
+    }
+}
+
+#[cfg(test)]
+impl Transport for Replay {
+    type Error = std::io::Error;
+
+    fn send(&self, request: &Request) -> Result<Response, Self::Error> {
+        self.requests.borrow_mut().push(request.clone());
+        Ok(self.response.clone())
+    }
+}
@@ -0,0 +1,934 @@
+//! Checks a `Message` against the rules SendGrid's API enforces, so a payload that would be
+//! rejected with a 400 can be caught before it is sent. Use
+//! [MessageBuilder::try_build](../message/struct.MessageBuilder.html#method.try_build) or
+//! [Message::validate](../message/struct.Message.html#method.validate) to run these checks.
+
+use crate::clock;
+use crate::inline;
+use crate::message::Message;
+use crate::personalization::Personalization;
+use crate::substitution::{self, MAX_SUBSTITUTIONS_SIZE};
+use crate::Contact;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+/// The maximum number of personalizations SendGrid accepts in a single `Message`
+pub const MAX_PERSONALIZATIONS: usize = 1000;
+
+/// The maximum number of recipients (to, cc and bcc combined) SendGrid accepts in a single
+/// `Message`
+pub const MAX_RECIPIENTS: usize = 1000;
+
+/// The maximum total size in bytes of the (base64 encoded) attachments SendGrid accepts in a
+/// single `Message`
+pub const MAX_ATTACHMENTS_SIZE: usize = 30 * 1024 * 1024;
+
+/// The maximum size in bytes of a `Message`'s JSON, see `Message::size`
+pub const MAX_MESSAGE_SIZE: usize = 30 * 1024 * 1024;
+
+/// The maximum total size in bytes of the keys and values of the custom_args sent with each
+/// personalization, counting those of the `Message` it doesn't override
+pub const MAX_CUSTOM_ARGS_SIZE: usize = 10_000;
+
+/// The maximum number of categories SendGrid accepts in a single `Message`
+pub const MAX_CATEGORIES: usize = 10;
+
+/// The maximum length in characters of a category
+pub const MAX_CATEGORY_LENGTH: usize = 255;
+
+/// Headers SendGrid sets itself and rejects in `headers`, in lowercase
+pub const RESERVED_HEADERS: &[&str] = &[
+    "x-sg-id",
+    "x-sg-eid",
+    "received",
+    "dkim-signature",
+    "content-type",
+    "content-transfer-encoding",
+    "to",
+    "from",
+    "subject",
+    "reply-to",
+    "cc",
+    "bcc",
+];
+
+/// How far ahead SendGrid accepts a `send_at` time
+pub const MAX_SCHEDULE_AHEAD: Duration = Duration::from_secs(72 * 60 * 60);
+
+/// The kind of problem found while validating a `Message`
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ValidationErrorKind {
+    /// The `Message` has no personalizations
+    NoPersonalizations,
+    /// The `Message` has more than `MAX_PERSONALIZATIONS` personalizations
+    TooManyPersonalizations(usize),
+    /// The `Message` has more than `MAX_RECIPIENTS` recipients across all personalizations
+    TooManyRecipients(usize),
+    /// A personalization has no `to` recipients
+    NoRecipients,
+    /// An email address is empty
+    EmptyEmail,
+    /// The same email address appears more than once in a personalization's to, cc and bcc
+    DuplicateRecipient(String),
+    /// The `Message` has no content and no template_id
+    MissingContent,
+    /// A `Content` has an empty value
+    EmptyContent,
+    /// A `text/plain` `Content` is present but is not the first `Content`
+    PlainTextNotFirst,
+    /// No subject is set on the `Message`, a personalization, or through a template
+    MissingSubject,
+    /// The attachments add up to more than `MAX_ATTACHMENTS_SIZE` bytes
+    AttachmentsTooLarge(usize),
+    /// HTML `Content` references an image by `cid:` but no `Attachment` has that content id
+    MissingInlineAttachment(String),
+    /// An inline `Attachment`'s content id is referenced by no `<img>` in the HTML `Content`, so
+    /// it won't be shown in the body. Not checked when the `Message` uses a template.
+    UnreferencedInlineAttachment(String),
+    /// A personalization's substitutions add up to more than `MAX_SUBSTITUTIONS_SIZE` bytes
+    SubstitutionsTooLarge(usize),
+    /// The send_at time, a Unix timestamp, is more than `MAX_SCHEDULE_AHEAD` in the future
+    SendAtTooFarAhead(u64),
+    /// The `Message`'s JSON is larger than `MAX_MESSAGE_SIZE` bytes
+    MessageTooLarge(usize),
+    /// The custom_args sent with a personalization add up to more than `MAX_CUSTOM_ARGS_SIZE`
+    /// bytes
+    CustomArgsTooLarge(usize),
+    /// The `Message` has more than `MAX_CATEGORIES` categories
+    TooManyCategories(usize),
+    /// A category is longer than `MAX_CATEGORY_LENGTH` characters
+    CategoryTooLong(usize),
+    /// A header is one of the `RESERVED_HEADERS`
+    ReservedHeader(String),
+}
+
+impl fmt::Display for ValidationErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationErrorKind::NoPersonalizations => {
+                write!(f, "at least one personalization is required")
+            }
+            ValidationErrorKind::TooManyPersonalizations(count) => write!(
+                f,
+                "{} personalizations exceeds the limit of {}",
+                count, MAX_PERSONALIZATIONS
+            ),
+            ValidationErrorKind::TooManyRecipients(count) => write!(
+                f,
+                "{} recipients exceeds the limit of {}",
+                count, MAX_RECIPIENTS
+            ),
+            ValidationErrorKind::NoRecipients => write!(f, "at least one to address is required"),
+            ValidationErrorKind::EmptyEmail => write!(f, "email address is empty"),
+            ValidationErrorKind::DuplicateRecipient(email) => {
+                write!(f, "{} appears more than once in to, cc and bcc", email)
+            }
+            ValidationErrorKind::MissingContent => {
+                write!(f, "content is required when no template_id is set")
+            }
+            ValidationErrorKind::EmptyContent => write!(f, "content value is empty"),
+            ValidationErrorKind::PlainTextNotFirst => {
+                write!(f, "text/plain content must be the first content")
+            }
+            ValidationErrorKind::MissingSubject => write!(f, "a subject is required"),
+            ValidationErrorKind::AttachmentsTooLarge(size) => write!(
+                f,
+                "attachments total {} bytes, exceeding the limit of {}",
+                size, MAX_ATTACHMENTS_SIZE
+            ),
+            ValidationErrorKind::MissingInlineAttachment(cid) => {
+                write!(f, "no attachment has the content id {}", cid)
+            }
+            ValidationErrorKind::UnreferencedInlineAttachment(cid) => {
+                write!(
+                    f,
+                    "no image in the HTML content references content id {}",
+                    cid
+                )
+            }
+            ValidationErrorKind::SendAtTooFarAhead(time) => write!(
+                f,
+                "send_at {} is more than {} hours ahead",
+                time,
+                MAX_SCHEDULE_AHEAD.as_secs() / 3600
+            ),
+            ValidationErrorKind::SubstitutionsTooLarge(size) => write!(
+                f,
+                "substitutions total {} bytes, exceeding the limit of {}",
+                size, MAX_SUBSTITUTIONS_SIZE
+            ),
+            ValidationErrorKind::MessageTooLarge(size) => write!(
+                f,
+                "message is {} bytes, exceeding the limit of {}",
+                size, MAX_MESSAGE_SIZE
+            ),
+            ValidationErrorKind::CustomArgsTooLarge(size) => write!(
+                f,
+                "custom_args total {} bytes, exceeding the limit of {}",
+                size, MAX_CUSTOM_ARGS_SIZE
+            ),
+            ValidationErrorKind::TooManyCategories(count) => write!(
+                f,
+                "{} categories exceeds the limit of {}",
+                count, MAX_CATEGORIES
+            ),
+            ValidationErrorKind::CategoryTooLong(length) => write!(
+                f,
+                "category is {} characters, exceeding the limit of {}",
+                length, MAX_CATEGORY_LENGTH
+            ),
+            ValidationErrorKind::ReservedHeader(name) => {
+                write!(f, "{} is a reserved header", name)
+            }
+        }
+    }
+}
+
+/// A single validation failure, with the path of the offending field within the `Message`
+/// (e.g. `personalizations[3].cc[0].email`), or an empty path if the whole `Message` is at fault
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    path: String,
+    kind: ValidationErrorKind,
+}
+
+impl ValidationError {
+    pub(crate) fn new(path: impl Into<String>, kind: ValidationErrorKind) -> Self {
+        ValidationError {
+            path: path.into(),
+            kind,
+        }
+    }
+
+    /// The path of the offending field within the `Message`
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// What is wrong with the field
+    pub fn kind(&self) -> &ValidationErrorKind {
+        &self.kind
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.kind)
+        } else {
+            write!(f, "{}: {}", self.path, self.kind)
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+/// Every `ValidationError` found in a `Message`. This is never empty when returned as an error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationErrors {
+    errors: Vec<ValidationError>,
+}
+
+impl ValidationErrors {
+    /// Iterate over the individual errors
+    pub fn iter(&self) -> std::slice::Iter<'_, ValidationError> {
+        self.errors.iter()
+    }
+
+    /// The number of errors found
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// Returns true if no errors were found
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Consumes this and returns the underlying errors
+    pub fn into_inner(self) -> Vec<ValidationError> {
+        self.errors
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ValidationErrors {}
+
+impl IntoIterator for ValidationErrors {
+    type Item = ValidationError;
+    type IntoIter = std::vec::IntoIter<ValidationError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ValidationErrors {
+    type Item = &'a ValidationError;
+    type IntoIter = std::slice::Iter<'a, ValidationError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.iter()
+    }
+}
+
+pub(crate) fn validate(message: &Message, now: SystemTime) -> Result<(), ValidationErrors> {
+    let mut errors = vec![];
+    check_contact(&message.from, "from", &mut errors);
+    if let Some(reply_to) = &message.reply_to {
+        check_contact(reply_to, "reply_to", &mut errors);
+    }
+    check_personalizations(message, &mut errors);
+    check_content(message, &mut errors);
+    check_subject(message, &mut errors);
+    check_attachments(message, &mut errors);
+    check_send_at(message, now, &mut errors);
+    check_headers(message, &mut errors);
+    check_categories(message, &mut errors);
+    check_custom_args(message, &mut errors);
+
+    let size = message.size().total();
+    if size > MAX_MESSAGE_SIZE {
+        errors.push(ValidationError::new(
+            "",
+            ValidationErrorKind::MessageTooLarge(size),
+        ));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationErrors { errors })
+    }
+}
+
+fn check_contact(contact: &Contact, path: &str, errors: &mut Vec<ValidationError>) {
+    if contact.email.trim().is_empty() {
+        errors.push(ValidationError::new(
+            format!("{}.email", path),
+            ValidationErrorKind::EmptyEmail,
+        ));
+    }
+}
+
+fn check_personalizations(message: &Message, errors: &mut Vec<ValidationError>) {
+    let count = message.personalizations.len();
+    if count == 0 {
+        errors.push(ValidationError::new(
+            "personalizations",
+            ValidationErrorKind::NoPersonalizations,
+        ));
+    } else if count > MAX_PERSONALIZATIONS {
+        errors.push(ValidationError::new(
+            "personalizations",
+            ValidationErrorKind::TooManyPersonalizations(count),
+        ));
+    }
+
+    let recipients: usize = message
+        .personalizations
+        .iter()
+        .map(Personalization::recipient_count)
+        .sum();
+    if recipients > MAX_RECIPIENTS {
+        errors.push(ValidationError::new(
+            "personalizations",
+            ValidationErrorKind::TooManyRecipients(recipients),
+        ));
+    }
+
+    for (i, personalization) in message.personalizations.iter().enumerate() {
+        let path = format!("personalizations[{}]", i);
+        if personalization.to.is_empty() {
+            errors.push(ValidationError::new(
+                format!("{}.to", path),
+                ValidationErrorKind::NoRecipients,
+            ));
+        }
+
+        let mut seen = HashSet::new();
+        let fields = [
+            ("to", &personalization.to),
+            ("cc", &personalization.cc),
+            ("bcc", &personalization.bcc),
+        ];
+        for (field, contacts) in fields.iter() {
+            for (j, contact) in contacts.iter().enumerate() {
+                let contact_path = format!("{}.{}[{}]", path, field, j);
+                check_contact(contact, &contact_path, errors);
+                let email = contact.email.trim().to_lowercase();
+                if !email.is_empty() && !seen.insert(email.clone()) {
+                    errors.push(ValidationError::new(
+                        format!("{}.email", contact_path),
+                        ValidationErrorKind::DuplicateRecipient(email),
+                    ));
+                }
+            }
+        }
+
+        let size = substitution::substitutions_size(personalization);
+        if size > MAX_SUBSTITUTIONS_SIZE {
+            errors.push(ValidationError::new(
+                format!("{}.substitutions", path),
+                ValidationErrorKind::SubstitutionsTooLarge(size),
+            ));
+        }
+    }
+}
+
+fn check_content(message: &Message, errors: &mut Vec<ValidationError>) {
+    if message.content.is_empty() && message.template_id.is_none() {
+        errors.push(ValidationError::new(
+            "content",
+            ValidationErrorKind::MissingContent,
+        ));
+    }
+
+    for (i, content) in message.content.iter().enumerate() {
+        if content.value.is_empty() {
+            errors.push(ValidationError::new(
+                format!("content[{}].value", i),
+                ValidationErrorKind::EmptyContent,
+            ));
+        }
+        if i > 0 && content.c_type.eq_ignore_ascii_case("text/plain") {
+            errors.push(ValidationError::new(
+                format!("content[{}].type", i),
+                ValidationErrorKind::PlainTextNotFirst,
+            ));
+        }
+        if content.c_type.eq_ignore_ascii_case("text/html") {
+            for cid in inline::cid_references(&content.value) {
+                let attached = message
+                    .attachments
+                    .iter()
+                    .any(|a| a.content_id.as_deref() == Some(cid));
+                if !attached {
+                    errors.push(ValidationError::new(
+                        format!("content[{}].value", i),
+                        ValidationErrorKind::MissingInlineAttachment(String::from(cid)),
+                    ));
+                }
+            }
+        }
+    }
+
+    let html: Vec<_> = message
+        .content
+        .iter()
+        .filter(|c| c.c_type.eq_ignore_ascii_case("text/html"))
+        .collect();
+    if message.template_id.is_some() || html.is_empty() {
+        return;
+    }
+    for (i, attachment) in message.attachments.iter().enumerate() {
+        let inline = attachment
+            .disposition
+            .as_deref()
+            .is_some_and(|d| d.eq_ignore_ascii_case("inline"));
+        let cid = match &attachment.content_id {
+            Some(cid) if inline => cid,
+            _ => continue,
+        };
+        let referenced = html
+            .iter()
+            .any(|c| inline::cid_references(&c.value).any(|r| r == cid));
+        if !referenced {
+            errors.push(ValidationError::new(
+                format!("attachments[{}].content_id", i),
+                ValidationErrorKind::UnreferencedInlineAttachment(cid.clone()),
+            ));
+        }
+    }
+}
+
+fn check_subject(message: &Message, errors: &mut Vec<ValidationError>) {
+    let has_subject = !message.subject.is_empty()
+        || message.template_id.is_some()
+        || (!message.personalizations.is_empty()
+            && message
+                .personalizations
+                .iter()
+                .all(|p| p.subject.as_ref().is_some_and(|s| !s.is_empty())));
+    if !has_subject {
+        errors.push(ValidationError::new(
+            "subject",
+            ValidationErrorKind::MissingSubject,
+        ));
+    }
+}
+
+fn check_send_at(message: &Message, now: SystemTime, errors: &mut Vec<ValidationError>) {
+    let latest = clock::unix_seconds(now + MAX_SCHEDULE_AHEAD);
+    let personalizations = message
+        .personalizations
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (format!("personalizations[{}].send_at", i), p.send_at));
+    for (path, send_at) in
+        std::iter::once((String::from("send_at"), message.send_at)).chain(personalizations)
+    {
+        if let Some(time) = send_at.filter(|t| *t > latest) {
+            errors.push(ValidationError::new(
+                path,
+                ValidationErrorKind::SendAtTooFarAhead(time),
+            ));
+        }
+    }
+}
+
+fn check_attachments(message: &Message, errors: &mut Vec<ValidationError>) {
+    let size: usize = message.attachments.iter().map(|a| a.content.len()).sum();
+    if size > MAX_ATTACHMENTS_SIZE {
+        errors.push(ValidationError::new(
+            "attachments",
+            ValidationErrorKind::AttachmentsTooLarge(size),
+        ));
+    }
+}
+
+fn check_headers(message: &Message, errors: &mut Vec<ValidationError>) {
+    let personalizations = message
+        .personalizations
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (format!("personalizations[{}].headers", i), &p.headers));
+    for (path, headers) in
+        std::iter::once((String::from("headers"), &message.headers)).chain(personalizations)
+    {
+        let mut names: Vec<&String> = headers.keys().collect();
+        names.sort();
+        for name in names {
+            if is_reserved_header(name) {
+                errors.push(ValidationError::new(
+                    format!("{}.{}", path, name),
+                    ValidationErrorKind::ReservedHeader(name.clone()),
+                ));
+            }
+        }
+    }
+}
+
+/// Returns true if `name` is one of the `RESERVED_HEADERS`, ignoring case
+pub(crate) fn is_reserved_header(name: &str) -> bool {
+    RESERVED_HEADERS
+        .iter()
+        .any(|reserved| name.trim().eq_ignore_ascii_case(reserved))
+}
+
+fn check_categories(message: &Message, errors: &mut Vec<ValidationError>) {
+    let count = message.categories.len();
+    if count > MAX_CATEGORIES {
+        errors.push(ValidationError::new(
+            "categories",
+            ValidationErrorKind::TooManyCategories(count),
+        ));
+    }
+    for (i, category) in message.categories.iter().enumerate() {
+        let length = category.chars().count();
+        if length > MAX_CATEGORY_LENGTH {
+            errors.push(ValidationError::new(
+                format!("categories[{}]", i),
+                ValidationErrorKind::CategoryTooLong(length),
+            ));
+        }
+    }
+}
+
+fn check_custom_args(message: &Message, errors: &mut Vec<ValidationError>) {
+    let shared = custom_args_size(message.custom_args.iter());
+    if shared > MAX_CUSTOM_ARGS_SIZE {
+        errors.push(ValidationError::new(
+            "custom_args",
+            ValidationErrorKind::CustomArgsTooLarge(shared),
+        ));
+        return;
+    }
+    for (i, personalization) in message.personalizations.iter().enumerate() {
+        let inherited = message
+            .custom_args
+            .iter()
+            .filter(|(k, _)| !personalization.custom_args.contains_key(*k));
+        let total = custom_args_size(personalization.custom_args.iter().chain(inherited));
+        if total > MAX_CUSTOM_ARGS_SIZE {
+            errors.push(ValidationError::new(
+                format!("personalizations[{}].custom_args", i),
+                ValidationErrorKind::CustomArgsTooLarge(total),
+            ));
+        }
+    }
+}
+
+fn custom_args_size<'a>(args: impl Iterator<Item = (&'a String, &'a String)>) -> usize {
+    args.map(|(k, v)| k.len() + v.len()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ValidationError, ValidationErrorKind, MAX_MESSAGE_SIZE, MAX_SCHEDULE_AHEAD};
+    use crate::clock::FakeClock;
+    use crate::{
+        AttachmentBuilder, ContactBuilder, Content, MessageBuilder, PersonalizationBuilder,
+    };
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn builder() -> MessageBuilder {
+        MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "Subject")
+            .content(Content::new("text/plain", "Body"))
+    }
+
+    fn to(email: &str) -> PersonalizationBuilder {
+        PersonalizationBuilder::default().to(ContactBuilder::new(email).build())
+    }
+
+    #[test]
+    fn accepts_valid_message() {
+        assert!(builder()
+            .personalization(to("to@example.com").build())
+            .try_build()
+            .is_ok());
+    }
+
+    #[test]
+    fn requires_personalization() {
+        let errors = builder().try_build().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        let error = errors.iter().next().unwrap();
+        assert_eq!(error.path(), "personalizations");
+        assert_eq!(error.kind(), &ValidationErrorKind::NoPersonalizations);
+    }
+
+    #[test]
+    fn limits_personalizations_and_recipients() {
+        let personalizations = (0..1001)
+            .map(|i| to(&format!("to{}@example.com", i)).build())
+            .collect();
+        let errors = builder()
+            .personalizations(personalizations)
+            .try_build()
+            .unwrap_err();
+        let kinds: Vec<_> = errors.iter().map(|e| e.kind().clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ValidationErrorKind::TooManyPersonalizations(1001),
+                ValidationErrorKind::TooManyRecipients(1001),
+            ]
+        );
+    }
+
+    #[test]
+    fn collects_every_violation_with_paths() {
+        let errors = MessageBuilder::new(ContactBuilder::new("").build(), "Subject")
+            .content(Content::new("text/html", "<p>Body</p>"))
+            .content(Content::new("text/plain", ""))
+            .personalization(to("a@example.com").build())
+            .personalization(
+                to("b@example.com")
+                    .cc(ContactBuilder::new("c@example.com").build())
+                    .bcc(ContactBuilder::new("B@Example.com").build())
+                    .build(),
+            )
+            .personalization(PersonalizationBuilder::default().build())
+            .try_build()
+            .unwrap_err();
+        let paths: Vec<_> = errors.iter().map(|e| e.path()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "from.email",
+                "personalizations[1].bcc[0].email",
+                "personalizations[2].to",
+                "content[1].value",
+                "content[1].type",
+            ]
+        );
+    }
+
+    #[test]
+    fn template_replaces_content_and_subject() {
+        assert!(
+            MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "")
+                .template_id("d-0001")
+                .personalization(to("to@example.com").build())
+                .try_build()
+                .is_ok()
+        );
+        let errors = MessageBuilder::new(ContactBuilder::new("from@example.com").build(), "")
+            .personalization(to("to@example.com").build())
+            .try_build()
+            .unwrap_err();
+        let kinds: Vec<_> = errors.iter().map(|e| e.kind().clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ValidationErrorKind::MissingContent,
+                ValidationErrorKind::MissingSubject
+            ]
+        );
+    }
+
+    #[test]
+    fn limits_total_attachment_size() {
+        let chunk = vec![0u8; 12 * 1024 * 1024];
+        let errors = builder()
+            .personalization(to("to@example.com").build())
+            .attachment(AttachmentBuilder::from_bytes(&chunk, "a.bin").build())
+            .attachment(AttachmentBuilder::from_bytes(&chunk, "b.bin").build())
+            .try_build()
+            .unwrap_err();
+        let error = errors.iter().next().unwrap();
+        assert_eq!(error.path(), "attachments");
+        assert_eq!(
+            error.kind(),
+            &ValidationErrorKind::AttachmentsTooLarge(2 * 16 * 1024 * 1024)
+        );
+    }
+
+    #[test]
+    fn requires_attachments_for_cid_references() {
+        let errors = builder()
+            .personalization(to("to@example.com").build())
+            .content(Content::new(
+                "text/html",
+                r#"<img src="logo.png"><img src="cid:chart">"#,
+            ))
+            .inline_image(
+                "logo.png",
+                AttachmentBuilder::from_bytes(b"png", "logo.png").build(),
+            )
+            .try_build()
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        let error = errors.iter().next().unwrap();
+        assert_eq!(error.path(), "content[1].value");
+        assert_eq!(
+            error.kind(),
+            &ValidationErrorKind::MissingInlineAttachment(String::from("chart"))
+        );
+    }
+
+    #[test]
+    fn reports_unreferenced_inline_images() {
+        let errors = builder()
+            .personalization(to("to@example.com").build())
+            .content(Content::new("text/html", r#"<img src="images/logo.png">"#))
+            .inline_image(
+                "logo.png",
+                AttachmentBuilder::from_bytes(b"png", "logo.png")
+                    .content_id("logo")
+                    .build(),
+            )
+            .try_build()
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        let error = errors.iter().next().unwrap();
+        assert_eq!(error.path(), "attachments[0].content_id");
+        assert_eq!(
+            error.kind(),
+            &ValidationErrorKind::UnreferencedInlineAttachment(String::from("logo"))
+        );
+
+        // The template's HTML isn't known, so nothing can be checked
+        assert!(builder()
+            .personalization(to("to@example.com").build())
+            .content(Content::new("text/html", "<p>Hi</p>"))
+            .template_id("d-0001")
+            .inline_image(
+                "logo.png",
+                AttachmentBuilder::from_bytes(b"png", "logo.png").build()
+            )
+            .try_build()
+            .is_ok());
+    }
+
+    #[test]
+    fn limits_substitutions_size() {
+        let errors = builder()
+            .personalization(
+                to("to@example.com")
+                    .substitution("-body-", &"x".repeat(10_000))
+                    .build(),
+            )
+            .try_build()
+            .unwrap_err();
+        let error = errors.iter().next().unwrap();
+        assert_eq!(error.path(), "personalizations[0].substitutions");
+        assert_eq!(
+            error.kind(),
+            &ValidationErrorKind::SubstitutionsTooLarge(10_006)
+        );
+    }
+
+    #[test]
+    fn limits_send_at_to_schedule_window() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let clock = FakeClock::at(1_700_000_000);
+        let ok = builder()
+            .send_at(now + MAX_SCHEDULE_AHEAD)
+            .personalization(to("to@example.com").send_at(now).build())
+            .try_build_with_clock(&clock);
+        assert!(ok.is_ok());
+
+        let errors = builder()
+            .send_at(now + MAX_SCHEDULE_AHEAD + Duration::from_secs(1))
+            .personalization(to("a@example.com").build())
+            .personalization(
+                to("b@example.com")
+                    .send_at(now + Duration::from_secs(4 * 24 * 3600))
+                    .build(),
+            )
+            .try_build_with_clock(&clock)
+            .unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| (e.path(), e.kind().clone()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "send_at",
+                    ValidationErrorKind::SendAtTooFarAhead(1_700_259_201)
+                ),
+                (
+                    "personalizations[1].send_at",
+                    ValidationErrorKind::SendAtTooFarAhead(1_700_345_600)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn limits_categories() {
+        let errors = (0..11)
+            .fold(builder(), |b, i| b.category(format!("category {}", i)))
+            .category("é".repeat(256))
+            .personalization(to("to@example.com").build())
+            .try_build()
+            .unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| (e.path(), e.kind().clone()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("categories", ValidationErrorKind::TooManyCategories(12)),
+                ("categories[11]", ValidationErrorKind::CategoryTooLong(256)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_reserved_headers() {
+        let errors = builder()
+            .header("X-Custom", "ok")
+            .header("Reply-To", "x@example.com")
+            .personalization(to("to@example.com").header("x-sg-id", "1").build())
+            .try_build()
+            .unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| (e.path(), e.kind().clone()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "headers.Reply-To",
+                    ValidationErrorKind::ReservedHeader(String::from("Reply-To"))
+                ),
+                (
+                    "personalizations[0].headers.x-sg-id",
+                    ValidationErrorKind::ReservedHeader(String::from("x-sg-id"))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn limits_custom_args_size() {
+        let message = builder()
+            .custom_arg("shared", &"s".repeat(5_000))
+            .personalization(to("a@example.com").custom_arg("shared", "").build())
+            .personalization(
+                to("b@example.com")
+                    .custom_arg("own", &"o".repeat(4_992))
+                    .build(),
+            )
+            .personalization(
+                to("c@example.com")
+                    .custom_arg("own", &"o".repeat(4_991))
+                    .build(),
+            );
+        let errors = message.try_build().unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| (e.path(), e.kind().clone()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![(
+                "personalizations[1].custom_args",
+                ValidationErrorKind::CustomArgsTooLarge(10_001)
+            )]
+        );
+
+        let errors = builder()
+            .custom_arg("shared", &"s".repeat(10_000))
+            .personalization(to("a@example.com").build())
+            .try_build()
+            .unwrap_err();
+        let error = errors.iter().next().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(error.path(), "custom_args");
+        assert_eq!(
+            error.kind(),
+            &ValidationErrorKind::CustomArgsTooLarge(10_006)
+        );
+    }
+
+    #[test]
+    fn limits_message_size() {
+        let message = builder()
+            .content(Content::new("text/html", &"x".repeat(MAX_MESSAGE_SIZE)))
+            .personalization(to("to@example.com").build())
+            .build();
+        let size = message.size();
+        assert_eq!(size.total(), message.to_json().len());
+        assert_eq!(
+            size.content(),
+            r#"[{"type":"text/plain","value":"Body"},{"type":"text/html","value":""}]"#.len()
+                + MAX_MESSAGE_SIZE
+        );
+        assert_eq!(
+            size.personalizations(),
+            r#"[{"to":[{"email":"to@example.com","name":null}]}]"#.len()
+        );
+
+        let errors = message.validate().unwrap_err().into_inner();
+        assert_eq!(
+            errors,
+            vec![ValidationError::new(
+                "",
+                ValidationErrorKind::MessageTooLarge(size.total())
+            )]
+        );
+        assert!(errors[0].to_string().starts_with("message is "));
+    }
+}
@@ -0,0 +1,476 @@
+//! Models for the events SendGrid's Event Webhook POSTs back after a `Message` is sent.
+//!
+//! A request body is a JSON array of events, parsed with `parse`. Every event carries the
+//! recipient, a timestamp and the categories and custom_args of the `Message` and
+//! `Personalization` it came from. Fields this crate doesn't model are kept rather than rejected,
+//! and events of types it doesn't know are parsed as `Event::Unknown`.
+//!
+//! # Examples
+//! ```
+//! # use sendgrid_rs::webhook::{self, Event};
+//! let body = br#"[
+//!     {"email": "ann@example.com", "timestamp": 1700000000, "event": "delivered",
+//!      "response": "250 OK", "category": "welcome", "user_id": "42"},
+//!     {"email": "ann@example.com", "timestamp": 1700000100, "event": "click",
+//!      "url": "https://example.com/start", "category": ["welcome", "onboarding"], "user_id": "42"}
+//! ]"#;
+//!
+//! let events = webhook::parse(body).unwrap();
+//! assert!(matches!(&events[0], Event::Delivered { response: Some(r), .. } if r == "250 OK"));
+//! assert_eq!(events[1].data().categories(), ["welcome", "onboarding"]);
+//! assert_eq!(events[1].data().custom_arg("user_id"), Some("42"));
+//! ```
+
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Fields SendGrid adds to events. SendGrid echoes custom_args back among these fields, so a
+/// custom_arg named after one of them, such as `status` or `url`, is shadowed by SendGrid's field
+/// and its value can't be read from events. SendGrid still accepts such keys when sending, so
+/// avoid them if you read custom_args from the Event Webhook.
+pub const EVENT_FIELDS: &[&str] = &[
+    "asm_group_id",
+    "attempt",
+    "bounce_classification",
+    "category",
+    "cert_err",
+    "email",
+    "event",
+    "ip",
+    "marketing_campaign_id",
+    "marketing_campaign_name",
+    "marketing_campaign_split_id",
+    "marketing_campaign_version",
+    "pool",
+    "post_type",
+    "reason",
+    "response",
+    "send_at",
+    "sg_content_type",
+    "sg_event_id",
+    "sg_machine_open",
+    "sg_message_id",
+    "sg_template_id",
+    "sg_template_name",
+    "sg_user_id",
+    "singlesend_id",
+    "singlesend_name",
+    "smtp-id",
+    "status",
+    "template_id",
+    "timestamp",
+    "tls",
+    "type",
+    "url",
+    "url_offset",
+    "useragent",
+];
+
+/// Parses the body of an Event Webhook request
+///
+/// # Examples
+/// ```
+/// # use sendgrid_rs::webhook;
+/// assert!(webhook::parse(b"[]").unwrap().is_empty());
+/// assert!(webhook::parse(br#"[{"event": "open"}]"#).is_err());
+/// ```
+pub fn parse(body: &[u8]) -> serde_json::Result<Vec<Event>> {
+    serde_json::from_slice(body)
+}
+
+/// The fields every event has
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventData {
+    email: String,
+    timestamp: u64,
+    smtp_id: Option<String>,
+    sg_event_id: Option<String>,
+    sg_message_id: Option<String>,
+    categories: Vec<String>,
+    fields: Map<String, Value>,
+}
+
+impl EventData {
+    /// The recipient's email address
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
+    /// When the event happened
+    pub fn timestamp(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.timestamp)
+    }
+
+    /// The `smtp-id` SendGrid assigned the message, if the event has one
+    pub fn smtp_id(&self) -> Option<&str> {
+        self.smtp_id.as_deref()
+    }
+
+    /// The unique id of the event, for deduplicating retried webhook requests
+    pub fn sg_event_id(&self) -> Option<&str> {
+        self.sg_event_id.as_deref()
+    }
+
+    /// The id SendGrid assigned the message
+    pub fn sg_message_id(&self) -> Option<&str> {
+        self.sg_message_id.as_deref()
+    }
+
+    /// The categories of the `Message`
+    pub fn categories(&self) -> &[String] {
+        &self.categories
+    }
+
+    /// The custom_args of the `Message` and `Personalization` the event came from, which SendGrid
+    /// echoes back among the event's fields, leaving out keys in `EVENT_FIELDS`
+    pub fn custom_args(&self) -> HashMap<&str, &str> {
+        self.fields
+            .iter()
+            .filter(|(k, _)| !EVENT_FIELDS.contains(&k.as_str()))
+            .filter_map(|(k, v)| Some((k.as_str(), v.as_str()?)))
+            .collect()
+    }
+
+    /// The custom_arg with this key, if it was set. Always `None` for a key in `EVENT_FIELDS`.
+    pub fn custom_arg(&self, key: &str) -> Option<&str> {
+        if EVENT_FIELDS.contains(&key) {
+            return None;
+        }
+        self.fields.get(key)?.as_str()
+    }
+
+    /// Every field not modeled by `EventData` or the `Event` variant, including custom_args
+    pub fn fields(&self) -> &Map<String, Value> {
+        &self.fields
+    }
+}
+
+/// An event reported by the Event Webhook
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    /// The message was received and is ready to be delivered
+    Processed(EventData),
+    /// The message will not be delivered, e.g. because the recipient is suppressed
+    Dropped {
+        data: EventData,
+        reason: Option<String>,
+    },
+    /// The receiving server accepted the message
+    Delivered {
+        data: EventData,
+        response: Option<String>,
+    },
+    /// The receiving server temporarily rejected the message, delivery will be retried
+    Deferred {
+        data: EventData,
+        response: Option<String>,
+        attempt: Option<u32>,
+    },
+    /// The receiving server rejected the message. `bounce_type` is `bounce` if it did so
+    /// permanently and `blocked` if temporarily.
+    Bounce {
+        data: EventData,
+        reason: Option<String>,
+        status: Option<String>,
+        bounce_type: Option<String>,
+    },
+    /// The recipient opened the message
+    Open {
+        data: EventData,
+        user_agent: Option<String>,
+        ip: Option<String>,
+    },
+    /// The recipient clicked a link in the message
+    Click {
+        data: EventData,
+        url: Option<String>,
+        user_agent: Option<String>,
+        ip: Option<String>,
+    },
+    /// The recipient marked the message as spam
+    SpamReport(EventData),
+    /// The recipient unsubscribed from all messages
+    Unsubscribe(EventData),
+    /// The recipient unsubscribed from an `Asm` group
+    GroupUnsubscribe {
+        data: EventData,
+        asm_group_id: Option<i32>,
+    },
+    /// The recipient resubscribed to an `Asm` group
+    GroupResubscribe {
+        data: EventData,
+        asm_group_id: Option<i32>,
+    },
+    /// An event of a type this crate doesn't know
+    Unknown { event: String, data: EventData },
+}
+
+impl Event {
+    /// The event type, as SendGrid names it
+    pub fn name(&self) -> &str {
+        match self {
+            Event::Processed(_) => "processed",
+            Event::Dropped { .. } => "dropped",
+            Event::Delivered { .. } => "delivered",
+            Event::Deferred { .. } => "deferred",
+            Event::Bounce { .. } => "bounce",
+            Event::Open { .. } => "open",
+            Event::Click { .. } => "click",
+            Event::SpamReport(_) => "spamreport",
+            Event::Unsubscribe(_) => "unsubscribe",
+            Event::GroupUnsubscribe { .. } => "group_unsubscribe",
+            Event::GroupResubscribe { .. } => "group_resubscribe",
+            Event::Unknown { event, .. } => event,
+        }
+    }
+
+    /// The fields every event has
+    pub fn data(&self) -> &EventData {
+        match self {
+            Event::Processed(data)
+            | Event::SpamReport(data)
+            | Event::Unsubscribe(data)
+            | Event::Dropped { data, .. }
+            | Event::Delivered { data, .. }
+            | Event::Deferred { data, .. }
+            | Event::Bounce { data, .. }
+            | Event::Open { data, .. }
+            | Event::Click { data, .. }
+            | Event::GroupUnsubscribe { data, .. }
+            | Event::GroupResubscribe { data, .. }
+            | Event::Unknown { data, .. } => data,
+        }
+    }
+
+    /// The custom_args of the `Message` and `Personalization` the event came from, leaving out
+    /// any named after one of the `EVENT_FIELDS`
+    pub fn custom_args(&self) -> HashMap<&str, &str> {
+        self.data().custom_args()
+    }
+}
+
+impl<'de> Deserialize<'de> for Event {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut fields = Map::deserialize(deserializer)?;
+        let event =
+            take_string(&mut fields, "event").ok_or_else(|| de::Error::missing_field("event"))?;
+        let email =
+            take_string(&mut fields, "email").ok_or_else(|| de::Error::missing_field("email"))?;
+        let timestamp = fields
+            .remove("timestamp")
+            .and_then(|t| t.as_u64().or_else(|| t.as_f64().map(|f| f as u64)))
+            .ok_or_else(|| de::Error::missing_field("timestamp"))?;
+        let categories = match fields.remove("category") {
+            Some(Value::String(category)) => vec![category],
+            Some(Value::Array(categories)) => categories
+                .into_iter()
+                .filter_map(|c| c.as_str().map(String::from))
+                .collect(),
+            _ => vec![],
+        };
+
+        let mut data = EventData {
+            email,
+            timestamp,
+            smtp_id: take_string(&mut fields, "smtp-id"),
+            sg_event_id: take_string(&mut fields, "sg_event_id"),
+            sg_message_id: take_string(&mut fields, "sg_message_id"),
+            categories,
+            fields,
+        };
+        let fields = &mut data.fields;
+        Ok(match event.as_str() {
+            "processed" => Event::Processed(data),
+            "dropped" => Event::Dropped {
+                reason: take_string(fields, "reason"),
+                data,
+            },
+            "delivered" => Event::Delivered {
+                response: take_string(fields, "response"),
+                data,
+            },
+            "deferred" => Event::Deferred {
+                response: take_string(fields, "response"),
+                attempt: take_number(fields, "attempt"),
+                data,
+            },
+            "bounce" => Event::Bounce {
+                reason: take_string(fields, "reason"),
+                status: take_string(fields, "status"),
+                bounce_type: take_string(fields, "type"),
+                data,
+            },
+            "open" => Event::Open {
+                user_agent: take_string(fields, "useragent"),
+                ip: take_string(fields, "ip"),
+                data,
+            },
+            "click" => Event::Click {
+                url: take_string(fields, "url"),
+                user_agent: take_string(fields, "useragent"),
+                ip: take_string(fields, "ip"),
+                data,
+            },
+            "spamreport" => Event::SpamReport(data),
+            "unsubscribe" => Event::Unsubscribe(data),
+            "group_unsubscribe" => Event::GroupUnsubscribe {
+                asm_group_id: take_number(fields, "asm_group_id"),
+                data,
+            },
+            "group_resubscribe" => Event::GroupResubscribe {
+                asm_group_id: take_number(fields, "asm_group_id"),
+                data,
+            },
+            _ => Event::Unknown { event, data },
+        })
+    }
+}
+
+/// Removes the field if it is a string
+fn take_string(fields: &mut Map<String, Value>, key: &str) -> Option<String> {
+    match fields.remove(key) {
+        Some(Value::String(s)) => Some(s),
+        Some(other) => {
+            fields.insert(key.to_string(), other);
+            None
+        }
+        None => None,
+    }
+}
+
+/// Removes the field if it is a number, or a string holding one, that fits in `T`
+fn take_number<T: std::str::FromStr>(fields: &mut Map<String, Value>, key: &str) -> Option<T> {
+    let number = match fields.get(key)? {
+        Value::Number(n) => n.to_string().parse().ok(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    };
+    if number.is_some() {
+        fields.remove(key);
+    }
+    number
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Event};
+    use serde_json::json;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn parses_every_event_type() {
+        let body = json!([
+            {"email": "a@example.com", "timestamp": 1, "event": "processed", "smtp-id": "<id@sg>",
+             "sg_event_id": "e1", "sg_message_id": "m1", "pool": {"name": "main", "id": 1}},
+            {"email": "a@example.com", "timestamp": 2, "event": "dropped", "reason": "Bounced Address"},
+            {"email": "a@example.com", "timestamp": 3, "event": "delivered", "response": "250 OK"},
+            {"email": "a@example.com", "timestamp": 4, "event": "deferred", "response": "400 try later", "attempt": "5"},
+            {"email": "a@example.com", "timestamp": 5, "event": "bounce", "reason": "500 unknown recipient",
+             "status": "5.0.0", "type": "blocked"},
+            {"email": "a@example.com", "timestamp": 6, "event": "open", "useragent": "Mozilla", "ip": "10.0.0.1",
+             "sg_machine_open": false},
+            {"email": "a@example.com", "timestamp": 7, "event": "click", "url": "https://example.com",
+             "url_offset": {"index": 0, "type": "html"}},
+            {"email": "a@example.com", "timestamp": 8, "event": "spamreport"},
+            {"email": "a@example.com", "timestamp": 9, "event": "unsubscribe"},
+            {"email": "a@example.com", "timestamp": 10, "event": "group_unsubscribe", "asm_group_id": 42},
+            {"email": "a@example.com", "timestamp": 11.0, "event": "group_resubscribe", "asm_group_id": 42},
+            {"email": "a@example.com", "timestamp": 12, "event": "account_status_change"},
+        ]);
+        let events = parse(body.to_string().as_bytes()).unwrap();
+
+        let names: Vec<_> = events.iter().map(Event::name).collect();
+        assert_eq!(
+            names,
+            vec![
+                "processed",
+                "dropped",
+                "delivered",
+                "deferred",
+                "bounce",
+                "open",
+                "click",
+                "spamreport",
+                "unsubscribe",
+                "group_unsubscribe",
+                "group_resubscribe",
+                "account_status_change",
+            ]
+        );
+        let processed = events[0].data();
+        assert_eq!(processed.smtp_id(), Some("<id@sg>"));
+        assert_eq!(processed.sg_event_id(), Some("e1"));
+        assert_eq!(processed.sg_message_id(), Some("m1"));
+        assert_eq!(processed.fields()["pool"]["name"], "main");
+        assert_eq!(
+            events[3],
+            Event::Deferred {
+                data: events[3].data().clone(),
+                response: Some(String::from("400 try later")),
+                attempt: Some(5),
+            }
+        );
+        assert!(matches!(
+            &events[4],
+            Event::Bounce { bounce_type: Some(t), status: Some(s), .. } if t == "blocked" && s == "5.0.0"
+        ));
+        assert!(matches!(
+            &events[5],
+            Event::Open { user_agent: Some(_), ip: Some(ip), .. } if ip == "10.0.0.1"
+        ));
+        assert!(matches!(
+            &events[6],
+            Event::Click { url: Some(url), user_agent: None, .. } if url == "https://example.com"
+        ));
+        assert!(matches!(
+            events[10],
+            Event::GroupResubscribe {
+                asm_group_id: Some(42),
+                ..
+            }
+        ));
+        assert_eq!(
+            events[10].data().timestamp(),
+            UNIX_EPOCH + Duration::from_secs(11)
+        );
+        assert!(events.iter().all(|e| e.custom_args().is_empty()));
+    }
+
+    #[test]
+    fn separates_custom_args_from_sendgrid_fields() {
+        let body = json!([{
+            "email": "a@example.com",
+            "timestamp": 1,
+            "event": "delivered",
+            "ip": "10.0.0.1",
+            "tls": 1,
+            "order_id": "1234",
+            "campaign": "spring",
+            "count": 3,
+        }]);
+        let events = parse(body.to_string().as_bytes()).unwrap();
+
+        let mut args: Vec<_> = events[0].custom_args().into_iter().collect();
+        args.sort();
+        assert_eq!(args, vec![("campaign", "spring"), ("order_id", "1234")]);
+        assert_eq!(events[0].data().custom_arg("ip"), None);
+        assert_eq!(events[0].data().fields()["count"], 3);
+    }
+
+    #[test]
+    fn requires_common_fields() {
+        for event in [
+            json!({"timestamp": 1, "event": "open"}),
+            json!({"email": "a@example.com", "event": "open"}),
+            json!({"email": "a@example.com", "timestamp": 1}),
+            json!("open"),
+        ] {
+            let body = json!([event]).to_string();
+            assert!(parse(body.as_bytes()).is_err(), "{}", body);
+        }
+    }
+}